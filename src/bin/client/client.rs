@@ -7,17 +7,19 @@ use std::{fs, io, thread};
 use std::io::{Read, Write};
 use std::sync::mpsc::{self, Receiver, Sender};
 
+use chrono::Utc;
 use nardol::prelude::{FromBytes, FromRon, IntoBytes, IntoMessage, ToRon};
 use rusqlite::Connection;
 use serde::{Serialize, Deserialize};
 use ron::de;
 
 use nardol::error::{NetCommsError, NetCommsErrorKind};
-use shared::message::ServerReply;
+use shared::message::{FileReference, FileTransfer, Notification, ServerError, ServerReply, transfer};
 use shared::{ImplementedMessage, MessageKind, RequestRaw};
+use shared::config::DEFAULT_SEARCH_RESULTS;
 use shared::user::UserLite;
 
-use crate::command::{self, CommandRaw};
+use crate::command::{self, Command, CommandRaw};
 
 
 #[path ="./sql.rs"]
@@ -25,6 +27,20 @@ mod sql;
 pub use sql::*;
 
 
+/// Number of attempts to send a file before the transfer is given up, it can be resumed later by sending it again.
+const FILE_SEND_ATTEMPTS: u32 = 3;
+
+/// Number of attempts to send a text message, every attempt sends it with the same uuid,
+/// so server saves it only once.
+const TEXT_SEND_ATTEMPTS: u32 = 3;
+
+/// How often messages whose time to live ended are deleted.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Number of messages shown by history command if it does not ask for other.
+const HISTORY_LENGTH: usize = 20;
+
+
 pub enum Output {
     Error(String),
     FromRun(String),
@@ -38,6 +54,13 @@ pub struct ClientConfig {
     pub port: u16,
     pub request_incoming_messages_timer: u64,
     pub save_location: PathBuf,
+    /// Files up to this size in bytes are downloaded without asking.
+    #[serde(default)]
+    pub auto_download_limit: u64,
+    /// Maximum number of bytes of a file held in memory at once during its transfer,
+    /// if [None] [FILE_CHUNK_SIZE](shared::message::transfer::FILE_CHUNK_SIZE) is used.
+    #[serde(default)]
+    pub max_buffered_bytes: Option<usize>,
 }
 
 impl ClientConfig {
@@ -70,13 +93,22 @@ impl ClientConfig {
 
 pub fn get_user(socket: SocketAddrV4,
                 current_user: UserLite,
+                db_path: &Path,
                 output_t: Sender<Output>) -> Result<UserLite, NetCommsError> {
 
     output_t.send(Output::FromRun(
         "Use register <username> <password> <password> or\nlogin <username> <password>\n".to_string()
     )).unwrap();       
     let cmd_raw = command::CommandRaw::get::<String>(None);
-    let cmd = cmd_raw.process(current_user)?;
+    let mut db_conn = Connection::open(db_path).unwrap();
+    let cmd = match cmd_raw.process(current_user)? {
+        // Device registered by an earlier login is reused, so it keeps messages that are waiting for it.
+        Command::Login(user_unchecked, mut author) => {
+            author.set_device_id(get_device_id(&mut db_conn, &user_unchecked.username));
+            Command::Login(user_unchecked, author)
+        },
+        cmd => cmd,
+    };
     let request = cmd.into_message()?;
 
     let location = Path::new("D:\\stepa\\Documents\\Rust\\net_comms_logs\\client_logs");
@@ -88,8 +120,11 @@ pub fn get_user(socket: SocketAddrV4,
             let message_kind = metadata.message_kind();
             match message_kind {
                 MessageKind::SeverReply => {
-                    let server_reply = ServerReply::from_ron(&String::from_buff(&msg.content_move().into_buff())?)?;
+                    let server_reply = ServerReply::from_ron(&msg.content_move().into_string())?;
                     if let ServerReply::User(user) = server_reply {
+                        if let Some(device_id) = user.device_id() {
+                            set_device_id(&mut db_conn, &user.username(), device_id);
+                        }
                         output_t.send(Output::FromRun("Successful login.".to_string())).unwrap();
                         return Ok(user);
                     } else {
@@ -156,8 +191,9 @@ pub fn ip(config: &ClientConfig) -> Ipv4Addr {
     }
 }
 
-pub fn get_waiting_messages(user: UserLite, socket: SocketAddrV4, _mpsc_transmitter: Sender<ImplementedMessage>, 
+pub fn get_waiting_messages(user: UserLite, socket: SocketAddrV4,
                             request_incoming_messages_timer: u64,
+                            auto_download_limit: u64,
                             save_location: &Path,
                             db_path: &Path,
                             output_t: Sender<Output>) -> JoinHandle<()> {
@@ -169,7 +205,15 @@ pub fn get_waiting_messages(user: UserLite, socket: SocketAddrV4, _mpsc_transmit
 
         let mut db_conn = Connection::open(db_location).unwrap();
 
+        // Files user did not answer about before it quit are asked about again.
+        for (file_reference, author) in get_pending_file_references(&mut db_conn) {
+            ask_about_file(&file_reference, &author, &output_t);
+        }
+
         loop {
+            // Downloads that were interrupted are resumed first.
+            resume_downloads(&user, socket, &save_location, &mut db_conn, &output_t);
+
             // Need to solve error handling. Maybe another mpsc channel?
             let request = RequestRaw::GetWaitingMessagesAuto(user.clone());
             let message = request.into_message().unwrap();
@@ -178,27 +222,50 @@ pub fn get_waiting_messages(user: UserLite, socket: SocketAddrV4, _mpsc_transmit
 
                 Ok(mut stream) => {
                     message.send(&mut stream).unwrap();
+                    let mut file_references = Vec::new();
+                    // Ids on server of messages that were stored, they are acknowledged, so server stops sending them.
+                    // They are marked as read only once user shows them.
+                    let mut read_ids = Vec::new();
+                    // Ids of queued notifications that were processed, they are acknowledged the same way.
+                    let mut notifications_ids = Vec::new();
                     while let Ok(message)
                      = ImplementedMessage::receive(&mut stream, Some(save_location.clone())) {
+                        let message_id = message.metadata().message_id();
+                        match message.metadata().message_kind() {
+                            MessageKind::FileReference => {
+                                let author = message.metadata().author_username();
+                                let content = message.content_move().into_string();
+                                let file_reference = FileReference::from_ron(&content).unwrap();
+                                // Reference is saved before it is acknowledged, so it is not lost if client quits.
+                                if insert_file_reference(&mut db_conn, &file_reference, &author, "pending") {
+                                    file_references.push((file_reference, author));
+                                }
+                            },
+                            MessageKind::Notification => {
+                                process_notification(&mut db_conn, message, &output_t);
+                                notifications_ids.extend(message_id);
+                                continue;
+                            },
+                            _ => process_received_message(&mut db_conn, message, &output_t),
+                        }
+                        read_ids.extend(message_id);
+                    }
 
-                        let metadata = message.metadata();
-                        let message_kind = metadata.message_kind();       
-
-                        let message_out = format!(
-                            "{author} [{datetime}]: {content}",
-                            author = message.metadata().author_username(),
-                            datetime = message.metadata().datetime_as_string(),
-                            content = match message_kind {
-                                MessageKind::File => format!("Received a file {name} at {location}",
-                                    name = PathBuf::from(message.metadata().file_name().unwrap()).file_name().unwrap().to_string_lossy(),
-                                    location = PathBuf::from(message.metadata().file_name().unwrap()).to_string_lossy()
-                                                            ),
-                                _ => String::from_buff(&message.content().into_buff()).unwrap()                                                        
-                        });
-                        
-                        output_t.send(Output::FromRun(message_out)).unwrap();
-                         
-                        insert_message(&mut db_conn, message);
+                    // Small files are downloaded right away, user is asked about the others
+                    // and about those that failed to download.
+                    for (file_reference, author) in file_references {
+                        if file_reference.size() > auto_download_limit
+                           || !download_file(socket, file_reference.clone(), &user, &save_location, &mut db_conn, &output_t) {
+                            ask_about_file(&file_reference, &author, &output_t);
+                        }
+                    }
+
+                    if !notifications_ids.is_empty() {
+                        send_quiet_request(socket, RequestRaw::AckNotifications(notifications_ids, user.clone()), &save_location, &output_t);
+                    }
+
+                    if !read_ids.is_empty() {
+                        send_quiet_request(socket, RequestRaw::AckMessages(read_ids, user.clone()), &save_location, &output_t);
                     }
                 },
                 Err(_) => todo!(),
@@ -208,21 +275,774 @@ pub fn get_waiting_messages(user: UserLite, socket: SocketAddrV4, _mpsc_transmit
     }).unwrap()
 }
 
-pub fn process_user_input(socket: SocketAddrV4, user: UserLite, output_t: Sender<Output>) {
+/// Asks user whether to download a file announced by given [FileReference], answer is given by [Command::Yes]
+/// or [Command::No].
+fn ask_about_file(file_reference: &FileReference, author: &str, output_t: &Sender<Output>) {
+
+    output_t.send(Output::FromRun(format!(
+        "{author} sent a file {name} ({size} bytes), download it? [y/n]",
+        author = author,
+        name = file_reference.name(),
+        size = file_reference.size(),
+    ))).unwrap();
+}
+
+/// Sends a request whose successful answer is not shown to the user, like acknowledging received messages.
+fn send_quiet_request(socket: SocketAddrV4,
+                      request: RequestRaw,
+                      save_location: &Path,
+                      output_t: &Sender<Output>) {
+
+    let message = request.into_message().unwrap();
+
+    if let Ok(mut stream) = TcpStream::connect(&socket) {
+        if message.send(&mut stream).is_err() {
+            return;
+        }
+        // Server always answers, only errors are shown.
+        if let Ok(reply) = ImplementedMessage::receive(&mut stream, Some(save_location.to_path_buf())) {
+            if let Ok(ServerReply::Error(text)) = ServerReply::from_ron(&reply.content_move().into_string()) {
+                output_t.send(Output::Error(text)).unwrap();
+            }
+        }
+    }
+}
+
+/// Tells server that user is writing a message to given recipients, server answer is not waited for.
+fn send_typing(socket: SocketAddrV4, recipients: Vec<String>, user: &UserLite, save_location: &Path) {
+
+    let message = RequestRaw::Typing(recipients, user.clone()).into_message().unwrap();
+    let save_location = save_location.to_owned();
+
+    thread::Builder::new().name("Typing".to_string()).spawn(move || {
+        if let Ok(mut stream) = TcpStream::connect(&socket) {
+            // Typing events are best effort, refused ones are not shown.
+            if message.send(&mut stream).is_ok() {
+                let _ = ImplementedMessage::receive(&mut stream, Some(save_location));
+            }
+        }
+    }).unwrap();
+}
+
+/// Starts a thread that every [SWEEP_INTERVAL] deletes messages whose time to live ended,
+/// together with their files stored inside `save_location`.
+pub fn sweep_expired_messages(save_location: &Path, db_path: &Path, output_t: Sender<Output>) -> JoinHandle<()> {
+
+    let save_location = save_location.to_owned();
+    let db_location = db_path.to_owned();
+
+    thread::Builder::new().name("SweepExpiredMessages".to_string()).spawn(move || {
+
+        let mut db_conn = Connection::open(db_location).unwrap();
+
+        loop {
+            for (id, server_id, files) in get_expired_messages(&mut db_conn, Utc::now()) {
+                // Files outside save location were not received, so they are not deleted.
+                for file in files.iter().filter(|file| file.starts_with(&save_location)) {
+                    let _ = fs::remove_file(file);
+                }
+                delete_message(&mut db_conn, id);
+                if let Some(server_id) = server_id {
+                    output_t.send(Output::FromRun(format!("Message {} expired and was deleted.", server_id))).unwrap();
+                }
+            }
+            thread::sleep(SWEEP_INTERVAL);
+        }
+    }).unwrap()
+}
+
+/// Saves received message to the database and prints it,
+/// message that was sent again because its acknowledgement got lost is already saved and is not printed.
+fn process_received_message(db_conn: &mut Connection, message: ImplementedMessage, output_t: &Sender<Output>) {
+
+    let message_out = format_message(db_conn, &message);
+     
+    if insert_message(db_conn, message) {
+        output_t.send(Output::FromRun(message_out)).unwrap();
+    }
+}
+
+/// Receives given number of messages that follow [ServerReply::Messages] as an answer to history request
+/// for conversation with `peer_or_group` and saves those that are not saved yet.
+fn save_history(stream: &mut TcpStream,
+                count: usize,
+                peer_or_group: &str,
+                save_location: &Path,
+                db_conn: &mut Connection,
+                output_t: &Sender<Output>) {
+
+    if count == 0 {
+        output_t.send(Output::FromRun(format!("There are no more messages with {}.", peer_or_group))).unwrap();
+        return;
+    }
+
+    let mut saved = 0;
+    let mut oldest_id = None;
+    for _ in 0..count {
+        match ImplementedMessage::receive(stream, Some(save_location.to_path_buf())) {
+            Ok(message) => {
+                // Messages come oldest first.
+                oldest_id = oldest_id.or(message.metadata().message_id());
+                // Files from history are not asked about, they can be downloaded with Command::Download.
+                if let MessageKind::FileReference = message.metadata().message_kind() {
+                    let author = message.metadata().author_username();
+                    let file_reference = FileReference::from_ron(&message.content().into_string()).unwrap();
+                    insert_file_reference(db_conn, &file_reference, &author, "declined");
+                    // Attachments are announced after their message, which was already saved.
+                    if file_reference.attachment().is_some() {
+                        continue;
+                    }
+                }
+                if insert_message(db_conn, message) {
+                    saved += 1;
+                }
+            },
+            Err(e) => {
+                output_t.send(Output::Error(format!("{}", e))).unwrap();
+                return;
+            },
+        }
+    }
+
+    let mut out = format!("Saved {} of {} messages with {}.", saved, count, peer_or_group);
+    if let Some(oldest_id) = oldest_id {
+        out.push_str(&format!(" Older messages can be saved with \"sync {} {}\".", peer_or_group, oldest_id));
+    }
+    output_t.send(Output::FromRun(out)).unwrap();
+}
+
+/// Returns given message formatted so it can be shown to the user,
+/// if it is a reply, a snippet of the message it replies to is quoted from the database.
+fn format_message(db_conn: &mut Connection, message: &ImplementedMessage) -> String {
+
+    let metadata = message.metadata();
+    let message_kind = metadata.message_kind();       
+
+    // Presence is known only for users the user watches.
+    let author = match get_presence(db_conn, &metadata.author_username()) {
+        Some(presence) => format!("{} ({})", metadata.author_username(), presence.presence()),
+        None => metadata.author_username(),
+    };
+    let author = match metadata.group() {
+        Some(group) => format!("{} in {}", author, group),
+        None => author,
+    };
+
+    // Id is shown so user can refer to the message in later commands.
+    let datetime = match metadata.message_id() {
+        Some(message_id) => format!("{}, id {}", metadata.datetime_as_string(), message_id),
+        None => metadata.datetime_as_string(),
+    };
+
+    let mut edited = match metadata.send_at() {
+        Some(send_at) => format!(" (scheduled for {})", send_at),
+        None if metadata.edited() => " (edited)".to_string(),
+        None => String::new(),
+    };
+    if let Some(ttl) = metadata.ttl() {
+        edited.push_str(&format!(" (disappears {} seconds after it is read)", ttl));
+    }
+    let reactions = format_reactions(&metadata.reactions());
+
+    let mut message_out = format!(
+        "{author} [{datetime}]: {content}{edited}{reactions}",
+        author = author,
+        datetime = datetime,
+        content = match message_kind {
+            MessageKind::File => format!("Received a file {name} at {location}",
+                name = PathBuf::from(message.metadata().file_name().unwrap()).file_name().unwrap().to_string_lossy(),
+                location = PathBuf::from(message.metadata().file_name().unwrap()).to_string_lossy()
+                                        ),
+            MessageKind::FileReference => match FileReference::from_ron(&message.content().into_string()) {
+                Ok(file_reference) => format!("Sent a file {} ({} bytes)", file_reference.name(), file_reference.size()),
+                Err(_) => "Sent a file".to_string(),
+            },
+            MessageKind::SeverReply => format!("Announcement: {}", message.content().into_string()),
+            _ => match message.content().as_text() {
+                Some(text) => text.to_string(),
+                None => format!("Received {} bytes of binary data", message.content().data().len()),
+            },
+        },
+        edited = edited,
+        reactions = reactions,
+    );
+
+    if let Some(reply_to) = metadata.reply_to() {
+        match get_message_snippet(db_conn, reply_to, 40) {
+            Some((author, text)) => message_out.push_str(&format!("\n    > {}: {}", author, text)),
+            None => message_out.push_str(&format!("\n    > reply to message {}", reply_to)),
+        }
+    }
+
+    for attachment in metadata.attachments() {
+        message_out.push_str(&format!("\n    attached {name} ({mime_type}, {size} bytes) at {location}",
+            name = attachment.name(),
+            mime_type = attachment.mime_type(),
+            size = attachment.size(),
+            location = attachment.path().unwrap_or_default().to_string_lossy()));
+    }
+
+    message_out
+}
+
+/// Returns reactions to a message formatted as ` [:+1: 2, :heart: 1]`, empty if there are none.
+fn format_reactions(reactions: &Vec<(String, usize)>) -> String {
+
+    if reactions.is_empty() {
+        return String::new();
+    }
+
+    let reactions: Vec<String> = reactions.iter()
+                                          .map(|(reaction, count)| format!("{} {}", reaction, count))
+                                          .collect();
+    format!(" [{}]", reactions.join(", "))
+}
+
+/// Applies a [Notification] about an already received message to the database and prints it.
+fn process_notification(db_conn: &mut Connection, message: ImplementedMessage, output_t: &Sender<Output>) {
+
+    let notification = match Notification::from_ron(&message.content_move().into_string()) {
+        Ok(notification) => notification,
+        Err(e) => {
+            output_t.send(Output::Error(format!("Failed to read a notification.\n{}", e))).unwrap();
+            return;
+        },
+    };
+
+    match notification {
+        Notification::Edited { message_id, content, datetime: _ } => {
+            let text = match content.as_text() {
+                Some(text) => text.to_string(),
+                None => format!("{} bytes of binary data", content.data().len()),
+            };
+            // Message may not be stored yet if it arrived with its new content already.
+            let _ = update_message_content(db_conn, message_id, content);
+            output_t.send(Output::FromRun(format!("Message {} was edited: {} (edited)", message_id, text))).unwrap();
+        },
+        Notification::Deleted { message_id } => {
+            if hide_message(db_conn, message_id).is_ok() {
+                output_t.send(Output::FromRun(format!("Message {} was deleted.", message_id))).unwrap();
+            }
+        },
+        Notification::Reaction { message_id, username, reaction, added, reactions } => {
+            let _ = update_message_reactions(db_conn, message_id, &reactions);
+            let action = if added { "reacted with" } else { "removed reaction" };
+            output_t.send(Output::FromRun(format!("{} {} {} to message {}.{}",
+                                                  username, action, reaction, message_id,
+                                                  format_reactions(&reactions)))).unwrap();
+        },
+        Notification::Receipt { message_id, username, kind, datetime } => {
+            output_t.send(Output::FromRun(format!("Message {} was {} by {} [{}].",
+                                                  message_id, kind, username, datetime))).unwrap();
+        },
+        Notification::Presence(presence) => {
+            update_presence(db_conn, &presence);
+            output_t.send(Output::FromRun(format!("{}.", presence))).unwrap();
+        },
+        Notification::MessageOfTheDay(motd) => {
+            output_t.send(Output::FromRun(format!("Message of the day: {}", motd))).unwrap();
+        },
+        Notification::Typing { username, group } => {
+            let text = match group {
+                Some(group) => format!("{} is typing in {}...", username, group),
+                None => format!("{} is typing...", username),
+            };
+            output_t.send(Output::FromRun(text)).unwrap();
+        },
+    }
+}
+
+/// Asks server to send again every file that is only partially received inside staging directory,
+/// starting from the byte where the previous transfer stopped.
+fn resume_downloads(user: &UserLite,
+                    socket: SocketAddrV4,
+                    save_location: &Path,
+                    db_conn: &mut Connection,
+                    output_t: &Sender<Output>) {
+
+    for (transfer_id, offset) in transfer::unfinished_transfers(save_location) {
+        let request = RequestRaw::ResumeTransfer(transfer_id.clone(), offset, user.clone());
+        let message = request.into_message().unwrap();
+
+        if let Ok(mut stream) = TcpStream::connect(&socket) {
+            message.send(&mut stream).unwrap();
+            match ImplementedMessage::receive(&mut stream, Some(save_location.to_path_buf())) {
+                Ok(message) => {
+                    if let MessageKind::SeverReply = message.metadata().message_kind() {
+                        // Server does not know this transfer, so there is nothing to resume.
+                        let _ = fs::remove_file(transfer::staging_location(save_location, &transfer_id));
+                    } else {
+                        process_received_message(db_conn, message, output_t);
+                    }
+                },
+                Err(e) => {
+                    output_t.send(Output::Error(format!("Failed to resume download {}.\n{}", transfer_id, e))).unwrap();
+                },
+            }
+        }
+    }
+}
+
+/// Downloads a file announced by given [FileReference] and saves it to the database,
+/// its [FileReference] is then deleted.
+///
+/// Returns `false` if file was not downloaded.
+fn download_file(socket: SocketAddrV4,
+                 file_reference: FileReference,
+                 user: &UserLite,
+                 save_location: &Path,
+                 db_conn: &mut Connection,
+                 output_t: &Sender<Output>) -> bool {
+
+    let request = match file_reference.attachment() {
+        Some(position) => RequestRaw::DownloadAttachment(file_reference.message_id(), position, user.clone()),
+        None => RequestRaw::DownloadFile(file_reference.message_id(), user.clone()),
+    };
+    let message = request.into_message().unwrap();
+
+    match TcpStream::connect(&socket) {
+        Ok(mut stream) => {
+            message.send(&mut stream).unwrap();
+            match ImplementedMessage::receive(&mut stream, Some(save_location.to_path_buf())) {
+                // Server answers with a reply only if it can not send the file.
+                Ok(message) if matches!(message.metadata().message_kind(), MessageKind::SeverReply) => {
+                    let reason = match ServerReply::from_ron(&message.content_move().into_string()) {
+                        Ok(server_reply) => format_server_reply(server_reply),
+                        Err(e) => format!("{}", e),
+                    };
+                    output_t.send(Output::Error(format!("Failed to download {}.\n{}", file_reference.name(), reason))).unwrap();
+                    false
+                },
+                Ok(message) => {
+                    // Attachment belongs to a message that was already saved without it.
+                    let local_id = get_message_id_from_server_id(db_conn, file_reference.message_id());
+                    match (file_reference.attachment(), local_id) {
+                        (Some(position), Ok(local_id)) => {
+                            let attachment = message.metadata().attachments().remove(0);
+                            output_t.send(Output::FromRun(format!(
+                                "Downloaded {} to {}.",
+                                attachment.name(),
+                                attachment.path().unwrap().display(),
+                            ))).unwrap();
+                            add_attachment(db_conn, local_id, position, &attachment);
+                        },
+                        _ => process_received_message(db_conn, message, output_t),
+                    }
+                    delete_file_reference(db_conn, &file_reference);
+                    true
+                },
+                Err(e) => {
+                    output_t.send(Output::Error(
+                        format!("Failed to download {}, it will be resumed later.\n{}", file_reference.name(), e)
+                    )).unwrap();
+                    false
+                },
+            }
+        },
+        Err(e) => {
+            output_t.send(Output::Error(format!("{}", e))).unwrap();
+            false
+        },
+    }
+}
+
+/// Downloads a file announced by given [FileReference] in its own thread, so user can continue meanwhile.
+fn spawn_download(socket: SocketAddrV4,
+                  file_reference: FileReference,
+                  user: &UserLite,
+                  save_location: &Path,
+                  db_path: &Path,
+                  output_t: &Sender<Output>) {
+
+    let user = user.clone();
+    let save_location = save_location.to_owned();
+    let db_location = db_path.to_owned();
+    let output_t = output_t.clone();
+
+    thread::Builder::new().name("DownloadFile".to_string()).spawn(move || {
+        let mut db_conn = Connection::open(db_location).unwrap();
+        download_file(socket, file_reference, &user, &save_location, &mut db_conn, &output_t);
+    }).unwrap();
+}
+
+/// Sends given request and returns [ServerReply] to it.
+fn request_server_reply(socket: SocketAddrV4,
+                        request: RequestRaw,
+                        save_location: &Path) -> Result<ServerReply, NetCommsError> {
+
+    let message = request.into_message()?;
+    match TcpStream::connect(&socket) {
+        Ok(mut stream) => {
+            message.send(&mut stream)?;
+            let reply = ImplementedMessage::receive(&mut stream, Some(save_location.to_path_buf()))?;
+            let server_reply = ServerReply::from_ron(&reply.content_move().into_string())?;
+            Ok(server_reply)
+        },
+        Err(e) => Err(NetCommsError::new(
+            NetCommsErrorKind::WritingToStreamFailed,
+            Some(format!("Failed to connect to server. ({})", e)))),
+    }
+}
+
+/// Reads a reply after a message was sent to another user, server only answers if it rejected the message,
+/// otherwise it just closes the connection.
+fn read_rejection(stream: &mut TcpStream, save_location: &Path) -> Option<ServerError> {
+
+    let reply = ImplementedMessage::receive(stream, Some(save_location.to_path_buf())).ok()?;
+    match ServerReply::from_ron(&reply.content_move().into_string()).ok()? {
+        ServerReply::Rejected(server_error) => Some(server_error),
+        _ => None,
+    }
+}
+
+/// Receives given number of messages that follow [ServerReply::Messages] and prints them without saving them.
+fn show_messages(stream: &mut TcpStream,
+                 count: usize,
+                 save_location: &Path,
+                 db_conn: &mut Connection,
+                 output_t: &Sender<Output>) {
+
+    if count == 0 {
+        output_t.send(Output::FromRun("There are no messages.".to_string())).unwrap();
+        return;
+    }
+
+    for _ in 0..count {
+        match ImplementedMessage::receive(stream, Some(save_location.to_path_buf())) {
+            Ok(message) => output_t.send(Output::FromRun(format_message(db_conn, &message))).unwrap(),
+            Err(e) => {
+                output_t.send(Output::Error(format!("{}", e))).unwrap();
+                return;
+            },
+        }
+    }
+}
+
+/// Prints given messages saved in the database.
+fn show_saved_messages(db_conn: &mut Connection, messages: Vec<ImplementedMessage>, output_t: &Sender<Output>) {
+
+    if messages.is_empty() {
+        output_t.send(Output::FromRun("There are no messages.".to_string())).unwrap();
+        return;
+    }
+
+    for message in messages {
+        output_t.send(Output::FromRun(format_message(db_conn, &message))).unwrap();
+    }
+}
+
+/// Marks given messages the user was shown as read, server is told about those that were not read before,
+/// so their authors get a read receipt.
+fn mark_read(socket: SocketAddrV4,
+             messages: &[ImplementedMessage],
+             user: &UserLite,
+             save_location: &Path,
+             db_conn: &mut Connection,
+             output_t: &Sender<Output>) {
+
+    let read_ids: Vec<usize> = messages.iter()
+                                       .filter(|message| message.metadata_ref().author_id() != user.id())
+                                       .filter_map(|message| message.metadata_ref().message_id())
+                                       .filter(|&server_id| mark_message_read(db_conn, server_id))
+                                       .collect();
+
+    if !read_ids.is_empty() {
+        send_quiet_request(socket, RequestRaw::MarkRead(read_ids, user.clone()), save_location, output_t);
+    }
+}
+
+/// Returns [ServerReply] formatted so it can be shown to the user.
+fn format_server_reply(server_reply: ServerReply) -> String {
+    match server_reply {
+        ServerReply::Error(error) => error,
+        ServerReply::Rejected(server_error) => format!("{}", server_error),
+        ServerReply::StorageUsage(usage, Some(quota)) => format!("Using {} of {} bytes.", usage, quota),
+        ServerReply::StorageUsage(usage, None) => format!("Using {} bytes.", usage),
+        ServerReply::Success(description) => description,
+        ServerReply::GroupMembers(name, members) => {
+            let members: Vec<String> = members.iter()
+                                              .map(|member| match member.muted() {
+                                                  true => format!("{} ({}, muted)", member.username(), member.role()),
+                                                  false => format!("{} ({})", member.username(), member.role()),
+                                              })
+                                              .collect();
+            format!("Members of {}: {}", name, members.join(", "))
+        },
+        ServerReply::Presence(presences) if presences.is_empty() => "You do not watch anybody.".to_string(),
+        ServerReply::Presence(presences) => {
+            let presences: Vec<String> = presences.iter()
+                                                  .map(|presence| format!("{}", presence))
+                                                  .collect();
+            presences.join("\n")
+        },
+        ServerReply::Devices(devices) => {
+            let devices: Vec<String> = devices.iter()
+                                              .map(|device| format!("{}", device))
+                                              .collect();
+            devices.join("\n")
+        },
+        server_reply => format!("{:?}", server_reply),
+    }
+}
+
+/// Sends a [Message](shared::ImplementedMessage) with a file.
+///
+/// Before every attempt server is asked how many bytes of this file it already has,
+/// so only the rest of it is sent.
+///
+/// Returns [ServerError] if server rejected the file, such file is not sent again.
+fn send_file_message(socket: SocketAddrV4,
+                     mut message: ImplementedMessage,
+                     user: &UserLite,
+                     save_location: &Path) -> Result<Option<ServerError>, NetCommsError> {
+
+    let mut metadata = message.metadata();
+    let path = PathBuf::from(metadata.file_name().unwrap());
+    let mut file_transfer = FileTransfer::new(&path, user)?;
+
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+
+        let request = RequestRaw::GetTransferOffset(file_transfer.id(), user.clone());
+        if let ServerReply::TransferOffset(_, offset) = request_server_reply(socket, request, save_location)? {
+            file_transfer.set_offset(offset);
+        }
+        metadata.set_transfer(Some(file_transfer.clone()));
+        message.set_metadata(metadata.clone());
+
+        let result = match TcpStream::connect(&socket) {
+            Ok(mut stream) => {
+                let result = message.clone().send(&mut stream);
+                // Server could have stopped reading because it rejected the file.
+                if let Some(server_error) = read_rejection(&mut stream, save_location) {
+                    return Ok(Some(server_error));
+                }
+                result
+            },
+            Err(e) => Err(NetCommsError::new(
+                NetCommsErrorKind::WritingToStreamFailed,
+                Some(format!("Failed to connect to server. ({})", e)))),
+        };
+
+        match result {
+            Ok(_) => return Ok(None),
+            Err(e) => {
+                if attempt >= FILE_SEND_ATTEMPTS {
+                    return Err(e);
+                }
+                thread::sleep(Duration::new(1, 0));
+            },
+        }
+    }
+}
+
+/// Sends a text [Message](shared::ImplementedMessage), it is sent again if it fails.
+///
+/// Returns [ServerError] if server rejected the message, such message is not sent again.
+fn send_text_message(socket: SocketAddrV4,
+                     message: ImplementedMessage,
+                     save_location: &Path) -> Result<Option<ServerError>, NetCommsError> {
+
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+
+        let result = match TcpStream::connect(&socket) {
+            Ok(mut stream) => {
+                let result = message.clone().send(&mut stream);
+                if let Some(server_error) = read_rejection(&mut stream, save_location) {
+                    return Ok(Some(server_error));
+                }
+                result
+            },
+            Err(e) => Err(NetCommsError::new(
+                NetCommsErrorKind::WritingToStreamFailed,
+                Some(format!("Failed to connect to server. ({})", e)))),
+        };
+
+        match result {
+            Ok(_) => return Ok(None),
+            Err(e) => {
+                if attempt >= TEXT_SEND_ATTEMPTS {
+                    return Err(e);
+                }
+                thread::sleep(Duration::new(1, 0));
+            },
+        }
+    }
+}
+
+pub fn process_user_input(socket: SocketAddrV4,
+                          user: UserLite,
+                          save_location: &Path,
+                          db_path: &Path,
+                          output_t: Sender<Output>) {
 
     loop {
         let cmd_raw = CommandRaw::get::<String>(None);
         let cmd = cmd_raw.process(user.clone()).unwrap();
-        let message = cmd.into_message().unwrap();
+
+        // Recipients are told that user is typing while it writes content of the message.
+        let cmd = match cmd {
+            Command::Compose(recipients, send_at, ttl, _) => {
+                send_typing(socket, recipients.clone(), &user, save_location);
+                let content = CommandRaw::get(Some(format!("Message to {}: ", recipients.join(", "))));
+                let mut vec = vec!["send ".to_string()];
+                if let Some(send_at) = send_at {
+                    vec.push("--at ".to_string());
+                    vec.push(format!("{} ", send_at));
+                }
+                if let Some(ttl) = ttl {
+                    vec.push("--ttl ".to_string());
+                    vec.push(format!("{} ", ttl));
+                }
+                vec.push(format!("({}) ", recipients.join(",")));
+                vec.extend(content.vec);
+                match (CommandRaw { vec }).process(user.clone()) {
+                    Ok(Command::Compose(_, _, _, _)) => {
+                        output_t.send(Output::Error("No content in \"send\" command.".to_string())).unwrap();
+                        continue;
+                    },
+                    Ok(cmd) => cmd,
+                    Err(e) => {
+                        output_t.send(Output::Error(format!("{}", e))).unwrap();
+                        continue;
+                    },
+                }
+            },
+            cmd => cmd,
+        };
+
+        // Presence of an unwatched user would otherwise stay shown next to its messages.
+        if let Command::Unwatch(username, _) = &cmd {
+            delete_presence(&mut Connection::open(db_path).unwrap(), username);
+        }
+
+        // Answer to a history request is saved instead of being shown.
+        let sync_peer = match &cmd {
+            Command::Sync(peer_or_group, _, _) => Some(peer_or_group.clone()),
+            _ => None,
+        };
+
+        let message = match cmd {
+            // Answers are matched to files in order in which they arrived.
+            Command::Yes(_) | Command::No(_) => {
+                let mut db_conn = Connection::open(db_path).unwrap();
+                let file_reference = get_pending_file_references(&mut db_conn).into_iter().next();
+                match (file_reference, cmd) {
+                    (Some((file_reference, _)), Command::Yes(_)) => {
+                        set_file_reference_state(&mut db_conn, &file_reference, "accepted");
+                        spawn_download(socket, file_reference, &user, save_location, db_path, &output_t);
+                    },
+                    (Some((file_reference, _)), _) => {
+                        set_file_reference_state(&mut db_conn, &file_reference, "declined");
+                        output_t.send(Output::FromRun(format!(
+                            "Skipped {}, it can be downloaded later with \"download {}\".",
+                            file_reference.name(), file_reference.message_id()))).unwrap();
+                    },
+                    (None, _) => {
+                        output_t.send(Output::Error("There is nothing to answer to.".to_string())).unwrap();
+                    },
+                }
+                continue;
+            },
+            Command::Download(id, _) => {
+                let mut db_conn = Connection::open(db_path).unwrap();
+                // Every file of the message is downloaded, that is its file or all its attachments.
+                let mut file_references = get_file_references(&mut db_conn, id);
+                // File this device was not told about is asked for by id only.
+                if file_references.is_empty() {
+                    file_references.push(FileReference::new(id, format!("file from message {}", id), 0, String::new()));
+                }
+                for file_reference in file_references {
+                    spawn_download(socket, file_reference, &user, save_location, db_path, &output_t);
+                }
+                continue;
+            },
+            // Commands working with saved messages contact server only to mark shown messages as read.
+            Command::History(username, count, _) => {
+                let mut db_conn = Connection::open(db_path).unwrap();
+                let messages = get_conversation(&mut db_conn, &user.username(), &username, count.unwrap_or(HISTORY_LENGTH));
+                mark_read(socket, &messages, &user, save_location, &mut db_conn, &output_t);
+                show_saved_messages(&mut db_conn, messages, &output_t);
+                continue;
+            },
+            Command::SearchHistory(text, _) => {
+                let mut db_conn = Connection::open(db_path).unwrap();
+                let messages = search_messages(&mut db_conn, &text, DEFAULT_SEARCH_RESULTS);
+                show_saved_messages(&mut db_conn, messages, &output_t);
+                continue;
+            },
+            Command::Show(id, _) => {
+                let mut db_conn = Connection::open(db_path).unwrap();
+                match get_message_id_from_server_id(&mut db_conn, id).and_then(|id| get_message_by_id(&mut db_conn, id)) {
+                    Ok(message) => {
+                        mark_read(socket, &[message.clone()], &user, save_location, &mut db_conn, &output_t);
+                        let mut message_out = format_message(&mut db_conn, &message);
+                        message_out.push_str(&format!("\n    to {}", message.metadata().recipients().join(", ")));
+                        output_t.send(Output::FromRun(message_out)).unwrap();
+                    },
+                    Err(_) => output_t.send(Output::Error(format!("Message {} is not saved on this device.", id))).unwrap(),
+                }
+                continue;
+            },
+            cmd => cmd.into_message().unwrap(),
+        };
 
         println!("{}", message.clone().to_ron_pretty(None).unwrap());
 
+        if let Some(_) = message.metadata().file_name() {
+            match send_file_message(socket, message, &user, save_location) {
+                Ok(Some(server_error)) => output_t.send(Output::Error(format!("{}", server_error))).unwrap(),
+                Ok(None) => {},
+                Err(e) => {
+                    output_t.send(Output::Error(format!("Failed to send a file, send it again to resume.\n{}", e))).unwrap();
+                },
+            }
+            continue;
+        }
+
+        if !matches!(message.metadata().message_kind(), MessageKind::Request) {
+            match send_text_message(socket, message, save_location) {
+                Ok(Some(server_error)) => output_t.send(Output::Error(format!("{}", server_error))).unwrap(),
+                Ok(None) => {},
+                Err(e) => output_t.send(Output::Error(format!("{}", e))).unwrap(),
+            }
+            continue;
+        }
+
         match TcpStream::connect(&socket) {
             Ok(mut stream) => {
-                if let Some(path) = message.metadata().file_name() {
-                    ImplementedMessage::send_file(&mut stream, Path::new(&path)).unwrap();
-                } else {
-                    message.send(&mut stream).unwrap();
+                if let Err(e) = message.send(&mut stream) {
+                    output_t.send(Output::Error(format!("{}", e))).unwrap();
+                    continue;
+                }
+                // Every request is answered by server.
+                match ImplementedMessage::receive(&mut stream, Some(save_location.to_path_buf())) {
+                    Ok(reply) => {
+                        match ServerReply::from_ron(&reply.content_move().into_string()) {
+                            Ok(ServerReply::Messages(count)) => {
+                                let mut db_conn = Connection::open(db_path).unwrap();
+                                match &sync_peer {
+                                    Some(peer_or_group) => {
+                                        save_history(&mut stream, count, peer_or_group, save_location, &mut db_conn, &output_t);
+                                    },
+                                    None => show_messages(&mut stream, count, save_location, &mut db_conn, &output_t),
+                                }
+                            },
+                            Ok(ServerReply::Presence(presences)) => {
+                                let mut db_conn = Connection::open(db_path).unwrap();
+                                for presence in &presences {
+                                    update_presence(&mut db_conn, presence);
+                                }
+                                output_t.send(Output::FromRun(format_server_reply(ServerReply::Presence(presences)))).unwrap();
+                            },
+                            Ok(server_reply) => output_t.send(Output::FromRun(format_server_reply(server_reply))).unwrap(),
+                            Err(e) => output_t.send(Output::Error(format!("{}", e))).unwrap(),
+                        }
+                    },
+                    Err(e) => output_t.send(Output::Error(format!("{}", e))).unwrap(),
                 }
             },            
             Err(e) => {
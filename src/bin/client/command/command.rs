@@ -1,7 +1,9 @@
 use nardol::{bytes::{Bytes, IntoBytes},
               error::{NetCommsError, NetCommsErrorKind},
               prelude::{IntoMessage, ToRon, Packet, PacketKind}};
-use shared::{Content, ImplementedMessage, MessageKind, MetaData, Request,
+use shared::{Content, ImplementedMessage, MessageKind, MetaData, Request, RequestRaw,
+             message::{Attachment, DeleteScope},
+             group::GroupRole,
              config::{SERVER_ID, SERVER_USERNAME}, 
              user::{UserLite, UserUnchecked}};
 
@@ -22,13 +24,120 @@ pub enum Command {
     /// Command containing the [User] that used this command.
     No(UserLite),
 
+    /// Command to download file of a message with given id, even if it was declined before.
+    Download(usize, UserLite),
+
     /// Command that is containing all necessary information to construct a [Message](crate::message::Message). 
     /// * [MessageKind]
     /// * [User] -- author that used this command.
     /// * [Vec<String>] -- recipients of this [Message](crate::message::Message).
     /// * [Vec<u8>] -- content of this [Message](crate::message::Message).
     /// * [Option<String>] -- information if content of this [Message](crate::message::Message) is a file.
-    Send(MessageKind, UserLite, Vec<String>, Vec<u8>, Option<String>), 
+    /// * [Vec<Attachment>] -- files attached to the text of this [Message](crate::message::Message).
+    /// * [Option<String>] -- RFC 3339 datetime before which server does not deliver this [Message](crate::message::Message).
+    /// * [Option<u64>] -- number of seconds after which this [Message](crate::message::Message) is deleted.
+    Send(MessageKind, UserLite, Vec<String>, Vec<u8>, Option<String>, Vec<Attachment>, Option<String>, Option<u64>), 
+
+    /// Command to get how many bytes are stored on server for the [User] that used this command.
+    Usage(UserLite),
+
+    /// Command to create a group with given name, [User] that used this command becomes its first member.
+    CreateGroup(String, UserLite),
+
+    /// Command to add user with given username to a group with given name.
+    /// * [String] -- name of the group.
+    /// * [String] -- username of the invited user.
+    /// * [User] -- user that used this command.
+    InviteToGroup(String, String, UserLite),
+
+    /// Command to leave a group with given name.
+    LeaveGroup(String, UserLite),
+
+    /// Command to list members of a group with given name.
+    GroupMembers(String, UserLite),
+
+    /// Command to remove user with given username from a group with given name.
+    RemoveFromGroup(String, String, UserLite),
+
+    /// Command to mute, if [bool] is `true`, or unmute user with given username inside a group with given name.
+    MuteInGroup(String, String, bool, UserLite),
+
+    /// Command to set [GroupRole] of user with given username inside a group with given name.
+    SetGroupRole(String, String, GroupRole, UserLite),
+
+    /// Command to rename a group with given name to a new name.
+    RenameGroup(String, String, UserLite),
+
+    /// Command to delete a message with given id from a group with given name.
+    DeleteGroupMessage(String, usize, UserLite),
+
+    /// Command to replace text of a message with given id by a new one.
+    Edit(usize, String, UserLite),
+
+    /// Command to delete a message with given id for given [DeleteScope].
+    Delete(usize, DeleteScope, UserLite),
+
+    /// Command to reply with given text to a message with given id, reply is sent to the conversation of that message.
+    Reply(usize, String, UserLite),
+
+    /// Command to show whole thread of a message with given id.
+    Thread(usize, UserLite),
+
+    /// Command to add, or remove if `false`, given reaction to a message with given id.
+    React(usize, String, bool, UserLite),
+
+    /// Command to start writing a message to given recipients, its content is asked for afterwards.
+    /// Holds also the time the message is scheduled for and its time to live, if there are any.
+    Compose(Vec<String>, Option<String>, Option<u64>, UserLite),
+
+    /// Command to show messages of conversation with user with given username saved on this device,
+    /// [Option<usize>] is how many of the newest ones to show.
+    History(String, Option<usize>, UserLite),
+
+    /// Command to save messages of conversation with a user or a group from server to this device,
+    /// [Option<usize>] is id of a message only older messages are saved than.
+    Sync(String, Option<usize>, UserLite),
+
+    /// Command to show messages saved on this device that contain given text, newest first.
+    SearchHistory(String, UserLite),
+
+    /// Command to show message with given id saved on this device.
+    Show(usize, UserLite),
+
+    /// Command to make server find messages containing every word of given query, newest first.
+    /// * [Option<String>] -- username or group the messages have to be from a conversation with.
+    /// * [Option<String>] -- RFC 3339 datetime the messages have to be sent at or after.
+    /// * [Option<String>] -- RFC 3339 datetime the messages have to be sent before.
+    /// * [Option<usize>] -- maximum number of messages to show.
+    /// * [usize] -- number of newest matching messages to skip.
+    Search(String, Option<String>, Option<String>, Option<String>, Option<usize>, usize, UserLite),
+
+    /// Command to show every scheduled message that was not sent yet.
+    Scheduled(UserLite),
+
+    /// Command to cancel scheduled message with given id.
+    Cancel(usize, UserLite),
+
+    /// Command to turn sending of read receipts on or off.
+    ReadReceipts(bool, UserLite),
+
+    /// Command to start watching presence of user with given username.
+    Watch(String, UserLite),
+
+    /// Command to stop watching presence of user with given username.
+    Unwatch(String, UserLite),
+
+    /// Command to show presence of every watched user.
+    Presence(UserLite),
+
+    /// Command to set status message, [None] clears it.
+    Status(Option<String>, UserLite),
+
+    /// Command to show every device user is logged in from.
+    Devices(UserLite),
+
+    /// Command to revoke device with given id.
+    Revoke(usize, UserLite),
 
     /// Used to signalize that created command is an unknown command.
     Unknown
@@ -38,8 +147,8 @@ impl IntoMessage<'_, MetaData, Content> for Command {
     
     fn into_message(self) -> Result<ImplementedMessage, NetCommsError> {
         match self {
-            Command::Send(message_kind, author, recipients, content, file_name) => {
-                return from_send(message_kind, author, recipients, content.into_bytes(), file_name);    
+            Command::Send(message_kind, author, recipients, content, file_name, attachments, send_at, ttl) => {
+                return from_send(message_kind, author, recipients, content.into_bytes(), file_name, attachments, send_at, ttl);    
             }
             Command::Register(user_unchecked, author) => {
                 return from_register(user_unchecked, author);
@@ -47,6 +156,91 @@ impl IntoMessage<'_, MetaData, Content> for Command {
             Command::Login(user_unchecked, author) => {
                 return from_login(user_unchecked, author);
             }
+            Command::Usage(author) => {
+                return RequestRaw::GetStorageUsage(author).into_message();
+            }
+            Command::CreateGroup(name, author) => {
+                return RequestRaw::CreateGroup(name, author).into_message();
+            }
+            Command::InviteToGroup(name, username, author) => {
+                return RequestRaw::InviteToGroup(name, username, author).into_message();
+            }
+            Command::LeaveGroup(name, author) => {
+                return RequestRaw::LeaveGroup(name, author).into_message();
+            }
+            Command::GroupMembers(name, author) => {
+                return RequestRaw::GetGroupMembers(name, author).into_message();
+            }
+            Command::RemoveFromGroup(name, username, author) => {
+                return RequestRaw::RemoveFromGroup(name, username, author).into_message();
+            }
+            Command::MuteInGroup(name, username, muted, author) => {
+                return RequestRaw::MuteInGroup(name, username, muted, author).into_message();
+            }
+            Command::SetGroupRole(name, username, role, author) => {
+                return RequestRaw::SetGroupRole(name, username, role, author).into_message();
+            }
+            Command::RenameGroup(name, new_name, author) => {
+                return RequestRaw::RenameGroup(name, new_name, author).into_message();
+            }
+            Command::DeleteGroupMessage(name, message_id, author) => {
+                return RequestRaw::DeleteGroupMessage(name, message_id, author).into_message();
+            }
+            Command::Edit(id, text, author) => {
+                let new_content = Content::text(text);
+                return RequestRaw::EditMessage { id, new_content, author }.into_message();
+            }
+            Command::Delete(id, scope, author) => {
+                return RequestRaw::DeleteMessage { id, scope, author }.into_message();
+            }
+            Command::Reply(id, text, author) => {
+                // Server sends the reply to the same recipients as the message it replies to.
+                let mut message = from_send(MessageKind::Text, author, Vec::new(),
+                                            IntoBytes::into_bytes(text), None, Vec::new(), None, None)?;
+                let mut metadata = message.metadata();
+                metadata.set_reply_to(Some(id));
+                message.set_metadata(metadata);
+                return Ok(message);
+            }
+            Command::Thread(id, author) => {
+                return RequestRaw::GetThread(id, author).into_message();
+            }
+            Command::React(id, reaction, add, author) => {
+                return RequestRaw::React { id, reaction, add, author }.into_message();
+            }
+            Command::ReadReceipts(enabled, author) => {
+                return RequestRaw::SetReadReceipts(enabled, author).into_message();
+            }
+            Command::Sync(peer_or_group, before_id, author) => {
+                return RequestRaw::GetHistory { peer_or_group, before_id, limit: None, author }.into_message();
+            }
+            Command::Search(query, peer, from, to, limit, offset, author) => {
+                return RequestRaw::Search { query, peer, from, to, limit, offset, author }.into_message();
+            }
+            Command::Scheduled(author) => {
+                return RequestRaw::GetScheduledMessages(author).into_message();
+            }
+            Command::Cancel(id, author) => {
+                return RequestRaw::CancelScheduledMessage(id, author).into_message();
+            }
+            Command::Watch(username, author) => {
+                return RequestRaw::SubscribePresence(username, author).into_message();
+            }
+            Command::Unwatch(username, author) => {
+                return RequestRaw::UnsubscribePresence(username, author).into_message();
+            }
+            Command::Presence(author) => {
+                return RequestRaw::GetPresence(author).into_message();
+            }
+            Command::Status(status, author) => {
+                return RequestRaw::SetStatus(status, author).into_message();
+            }
+            Command::Devices(author) => {
+                return RequestRaw::GetDevices(author).into_message();
+            }
+            Command::Revoke(id, author) => {
+                return RequestRaw::RevokeDevice(id, author).into_message();
+            }
             _ => {
                 return Err(NetCommsError::new(
                     NetCommsErrorKind::UnknownCommand,
@@ -59,11 +253,17 @@ impl IntoMessage<'_, MetaData, Content> for Command {
 /// Creates a [Message] from [Command::Send].
 fn from_send(message_kind: MessageKind,
              author: UserLite, recipients: Vec<String>,
-             content: Bytes, file_name: Option<String>) -> Result<ImplementedMessage, NetCommsError> {
+             content: Bytes, file_name: Option<String>,
+             attachments: Vec<Attachment>,
+             send_at: Option<String>,
+             ttl: Option<u64>) -> Result<ImplementedMessage, NetCommsError> {
 
     let mut message = ImplementedMessage::new();
 
-    let metadata = MetaData::new(&content, message_kind, author, SERVER_ID, recipients, file_name)?;
+    let mut metadata = MetaData::new(&content, message_kind, author, SERVER_ID, recipients, file_name)?;
+    metadata.set_attachments(attachments);
+    metadata.set_send_at(send_at);
+    metadata.set_ttl(ttl);
     message.set_metadata(metadata);
 
     let content = Content::with_data(content.to_string());
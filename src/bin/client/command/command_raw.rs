@@ -1,7 +1,9 @@
 use std::path::Path;
 
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+
 use nardol::{bytes::IntoBytes, error::{NetCommsError, NetCommsErrorKind}};
-use shared::{MessageKind, user::{UserUnchecked, user::UserLite}};
+use shared::{MessageKind, config::{DEFAULT_SEARCH_RESULTS, GROUP_PREFIX}, group::GroupRole, message::{Attachment, DeleteScope}, user::{UserUnchecked, user::UserLite}};
 use utils::input;
 
 use super::Command;
@@ -99,25 +101,107 @@ impl CommandRaw {
                         return Ok(Command::Login(user_unchecked, user.clone()))
                     },
                     "y" => {
-                        // Finish check function
-                        match CommandRaw::check_yes(self) {
-                            Ok(_) => return Ok(Command::Yes(user.clone())),
-                            Err(_) => todo!(),
-                            
-                        };
+                        CommandRaw::check_yes(self)?;
+                        return Ok(Command::Yes(user.clone()))
                     },
                     "n" => {
-                        // Finish check function
-                        match CommandRaw::check_no(self) {
-                            Ok(_) => return Ok(Command::No(user.clone())),
-                            Err(_) => todo!(),
-                            
-                        };
+                        CommandRaw::check_no(self)?;
+                        return Ok(Command::No(user.clone()))
+                    },
+                    "download" => {
+                        let id = CommandRaw::check_id(self, "download")?;
+                        return Ok(Command::Download(id, user.clone()))
                     },
                     "send" => {
                         let send_cmd = CommandRaw::check_send(self, user)?;
                         return Ok(send_cmd)
                     },
+                    "usage" => {
+                        CommandRaw::check_no_arguments(self, "usage")?;
+                        return Ok(Command::Usage(user.clone()))
+                    },
+                    "group" => {
+                        let group_cmd = CommandRaw::check_group(self, user)?;
+                        return Ok(group_cmd)
+                    },
+                    "edit" => {
+                        let (id, text) = CommandRaw::check_id_and_text(self, "edit")?;
+                        return Ok(Command::Edit(id, text, user.clone()))
+                    },
+                    "reply" => {
+                        let (id, text) = CommandRaw::check_id_and_text(self, "reply")?;
+                        return Ok(Command::Reply(id, text, user.clone()))
+                    },
+                    "thread" => {
+                        let id = CommandRaw::check_id(self, "thread")?;
+                        return Ok(Command::Thread(id, user.clone()))
+                    },
+                    "react" => {
+                        let (id, reaction) = CommandRaw::check_id_and_text(self, "react")?;
+                        return Ok(Command::React(id, reaction, true, user.clone()))
+                    },
+                    "unreact" => {
+                        let (id, reaction) = CommandRaw::check_id_and_text(self, "unreact")?;
+                        return Ok(Command::React(id, reaction, false, user.clone()))
+                    },
+                    "search" => {
+                        let search_cmd = CommandRaw::check_search(self, user)?;
+                        return Ok(search_cmd)
+                    },
+                    "history" => {
+                        let (username, count) = CommandRaw::check_history(self)?;
+                        return Ok(Command::History(username, count, user.clone()))
+                    },
+                    "sync" => {
+                        let (peer_or_group, before_id) = CommandRaw::check_sync(self)?;
+                        return Ok(Command::Sync(peer_or_group, before_id, user.clone()))
+                    },
+                    "show" => {
+                        let id = CommandRaw::check_id(self, "show")?;
+                        return Ok(Command::Show(id, user.clone()))
+                    },
+                    "scheduled" => {
+                        CommandRaw::check_no_arguments(self, "scheduled")?;
+                        return Ok(Command::Scheduled(user.clone()))
+                    },
+                    "cancel" => {
+                        let id = CommandRaw::check_id(self, "cancel")?;
+                        return Ok(Command::Cancel(id, user.clone()))
+                    },
+                    "watch" => {
+                        let username = CommandRaw::check_username(self, "watch")?;
+                        return Ok(Command::Watch(username, user.clone()))
+                    },
+                    "unwatch" => {
+                        let username = CommandRaw::check_username(self, "unwatch")?;
+                        return Ok(Command::Unwatch(username, user.clone()))
+                    },
+                    "presence" => {
+                        CommandRaw::check_no_arguments(self, "presence")?;
+                        return Ok(Command::Presence(user.clone()))
+                    },
+                    "status" => {
+                        let status: String = self.vec.into_iter().skip(1).collect();
+                        let status = status.trim().to_string();
+                        let status = if status.is_empty() { None } else { Some(status) };
+                        return Ok(Command::Status(status, user.clone()))
+                    },
+                    "devices" => {
+                        CommandRaw::check_no_arguments(self, "devices")?;
+                        return Ok(Command::Devices(user.clone()))
+                    },
+                    "revoke" => {
+                        let id = CommandRaw::check_device_id(self)?;
+                        return Ok(Command::Revoke(id, user.clone()))
+                    },
+                    "receipts" => {
+                        let enabled = CommandRaw::check_switch(self, "receipts")?;
+                        return Ok(Command::ReadReceipts(enabled, user.clone()))
+                    },
+                    "delete" => {
+                        let (id, scope) = CommandRaw::check_delete(self)?;
+                        return Ok(Command::Delete(id, scope, user.clone()))
+                    },
                     _ => {
                         return Err(NetCommsError::new(
                             NetCommsErrorKind::UnknownCommand,
@@ -195,16 +279,83 @@ impl CommandRaw {
         })
     }
 
-    fn check_yes(_cmd: CommandRaw) -> Result<Command, NetCommsError> {
-        todo!()
-        // Later will perform logic to check if inputted command is a valid yes command.
-        // Ok(Command::Yes)
+    /// Checks if given command is valid yes command, it can not have any other parts.
+    fn check_yes(cmd: CommandRaw) -> Result<(), NetCommsError> {
+        Self::check_no_arguments(cmd, "y")
+    }
+
+    /// Checks if given command is valid no command, it can not have any other parts.
+    fn check_no(cmd: CommandRaw) -> Result<(), NetCommsError> {
+        Self::check_no_arguments(cmd, "n")
     }
 
-    fn check_no(_cmd: CommandRaw) -> Result<Command, NetCommsError> {
-        todo!()
-        // Later will perform logic to check if inputted command is a valid no command.
-        // Ok(Command::No)
+    /// Checks if given command does not have any other parts than its `name`.
+    fn check_no_arguments(cmd: CommandRaw, name: &str) -> Result<(), NetCommsError> {
+
+        if cmd.vec.iter().skip(1).any(|part| !part.trim().is_empty()) {
+            return Err(NetCommsError::new(
+                NetCommsErrorKind::InvalidCommand, 
+                Some(format!("Command {} can not be followed by anything.", name))));
+        }
+
+        Ok(())
+    }
+
+    /// Checks if given command is `<name> <username>` and returns the username.
+    fn check_username(cmd: CommandRaw, name: &str) -> Result<String, NetCommsError> {
+
+        let cmd_vec: Vec<String> = cmd.vec
+                                      .iter()
+                                      .map(|x| x.trim().to_string())
+                                      .filter(|x| !x.is_empty())
+                                      .skip(1)
+                                      .collect();
+
+        match cmd_vec.as_slice() {
+            [username] => Ok(username.clone()),
+            _ => Err(NetCommsError::new(
+                NetCommsErrorKind::InvalidCommand, 
+                Some(format!("Command {} needs to be followed by exactly one username.", name)))),
+        }
+    }
+
+    /// Checks if given command is `revoke <id>` and returns id of the device.
+    fn check_device_id(cmd: CommandRaw) -> Result<usize, NetCommsError> {
+
+        let cmd_vec: Vec<String> = cmd.vec
+                                      .iter()
+                                      .map(|x| x.trim().to_string())
+                                      .filter(|x| !x.is_empty())
+                                      .skip(1)
+                                      .collect();
+
+        match cmd_vec.as_slice() {
+            [id] => id.parse().map_err(|_| NetCommsError::new(
+                NetCommsErrorKind::InvalidCommand, 
+                Some(format!("{} is not a valid device id.", id)))),
+            _ => Err(NetCommsError::new(
+                NetCommsErrorKind::InvalidCommand, 
+                Some("Command revoke needs to be followed by exactly one device id.".to_string()))),
+        }
+    }
+
+    /// Checks if given command is `<name> on` or `<name> off` and returns `true` for `on`.
+    fn check_switch(cmd: CommandRaw, name: &str) -> Result<bool, NetCommsError> {
+
+        let cmd_vec: Vec<String> = cmd.vec
+                                      .iter()
+                                      .map(|x| x.trim().to_string())
+                                      .filter(|x| !x.is_empty())
+                                      .skip(1)
+                                      .collect();
+
+        match cmd_vec.iter().map(|x| x.as_str()).collect::<Vec<&str>>().as_slice() {
+            ["on"] => Ok(true),
+            ["off"] => Ok(false),
+            _ => Err(NetCommsError::new(
+                NetCommsErrorKind::InvalidCommand, 
+                Some(format!("Command {} needs to be followed by on or off.", name)))),
+        }
     }
 
     /// Checks if given command is valid send command.
@@ -219,6 +370,30 @@ impl CommandRaw {
                 None));
         }
 
+        // Optional time the message is scheduled for, `--at <datetime>`,
+        // and its time to live, `--ttl <duration>`, in any order before recipients.
+        let mut cmd_iter = cmd_iter.skip_while(|part| part.trim().is_empty()).peekable();
+        let mut send_at = None;
+        let mut ttl = None;
+        loop {
+            let option = match cmd_iter.peek() {
+                Some(part) if part.trim().starts_with("--") => part.trim().to_string(),
+                _ => break,
+            };
+            cmd_iter.next();
+            let value = cmd_iter.by_ref().find(|part| !part.trim().is_empty());
+            match option.as_str() {
+                "--at" => send_at = Some(Self::check_send_at(value)?),
+                "--ttl" => ttl = Some(Self::check_ttl(value)?),
+                _ => {
+                    return Err(NetCommsError::new(
+                        NetCommsErrorKind::InvalidCommand, 
+                        Some(format!("Unknown option {} in \"send\" command.", option))));
+                },
+            }
+            while cmd_iter.next_if(|part| part.trim().is_empty()).is_some() {}
+        }
+
         // Get all recipients.
         let mut recipients: Vec<String> = Vec::new();
         let mut is_first = true;
@@ -291,54 +466,426 @@ impl CommandRaw {
                 Some("No recipients in \"send\" command.".to_string())));
         }
 
+        // Message sent to a group belongs to its conversation, so it can not be sent to anyone else at the same time.
+        if recipients.len() > 1 && recipients.iter().any(|recipient| recipient.starts_with(GROUP_PREFIX)) {
+            return Err(NetCommsError::new(
+                NetCommsErrorKind::InvalidCommand, 
+                Some("Group has to be the only recipient in \"send\" command.".to_string())));
+        }
+
         // Change content to owned String.
         let cmd_content: String = cmd_iter.map(|string| String::from(string)).collect();
 
-        if cmd_content.is_empty()  {
-            return Err(NetCommsError::new(
-                NetCommsErrorKind::InvalidCommand, 
-                Some("No content in \"send\" command.".to_string())));
+        // Content is written separately, recipients are told that user is typing meanwhile.
+        if cmd_content.trim().is_empty()  {
+            return Ok(Command::Compose(recipients, send_at, ttl, user));
         }
 
+        let (text, paths) = Self::split_attachments(&cmd_content);
+
         let kind: MessageKind;
         let mut file_name: Option<String> = None;
         let mut content = Vec::new();
+        let mut attachments = Vec::new();
 
-        // Check if the content of command is a Path
-        if cmd_content.starts_with('|') {
+        // Single file without any text is sent as a file, so its transfer can be resumed and recipients
+        // can decide if they want to download it.
+        if text.is_empty() && paths.len() == 1 {
             kind = MessageKind::File;
-            let cmd_content = cmd_content.replace("|", "");
-            let path = Path::new(&cmd_content);
-            if path.is_file() {
-                dbg!("is file");
-                match path.to_str() {
-                    Some(path) => file_name = Some(path.to_string()),
-                    None => {
-                        return Err(NetCommsError::new(
-                            NetCommsErrorKind::InvalidCommand, 
-                            Some("Given file does not exist.".to_string())));
-                    }
+            let path = Path::new(&paths[0]);
+            if !path.is_file() {
+                return Err(NetCommsError::new(
+                    NetCommsErrorKind::InvalidCommand, 
+                    Some("Given file does not exist.".to_string())));
+            }
+            match path.to_str() {
+                Some(path) => file_name = Some(path.to_string()),
+                None => {
+                    return Err(NetCommsError::new(
+                        NetCommsErrorKind::InvalidCommand, 
+                        Some("Given file does not exist.".to_string())));
                 }
             }
         } else {
             kind = MessageKind::Text;
-            file_name = None;
-            content = cmd_content.into_buff();
+            content = text.into_buff();
+            for path in paths {
+                match Attachment::from_path(Path::new(&path)) {
+                    Ok(attachment) => attachments.push(attachment),
+                    Err(_) => {
+                        return Err(NetCommsError::new(
+                            NetCommsErrorKind::InvalidCommand, 
+                            Some(format!("Given file {} does not exist.", path))));
+                    },
+                }
+            }
         }
 
         let author = user;
 
-        dbg!(file_name.clone());
-
         Ok(Command::Send(
             kind,
             author,
             recipients,
             content,
             file_name,
+            attachments,
+            send_at,
+            ttl,
         ))
     }
 
+    /// Checks that given duration is a positive number of seconds, optionally followed by `s`, `m`, `h` or `d`
+    /// as in `90`, `30m` or `1d`, and returns it in seconds.
+    fn check_ttl(duration: Option<&String>) -> Result<u64, NetCommsError> {
+
+        let duration = duration.map(|duration| duration.trim()).unwrap_or_default();
+        let (number, multiplier) = match duration.chars().last() {
+            Some('s') => (&duration[..duration.len() - 1], 1),
+            Some('m') => (&duration[..duration.len() - 1], 60),
+            Some('h') => (&duration[..duration.len() - 1], 60 * 60),
+            Some('d') => (&duration[..duration.len() - 1], 24 * 60 * 60),
+            _ => (duration, 1),
+        };
+
+        match number.parse::<u64>() {
+            Ok(number) if number > 0 => Ok(number * multiplier),
+            _ => Err(NetCommsError::new(
+                NetCommsErrorKind::InvalidCommand, 
+                Some(format!("{:?} is not a valid time to live, use for example 90, 30m or 1d.", duration)))),
+        }
+    }
+
+    /// Checks that given datetime, either RFC 3339 or local time as `2026-11-01T09:00`, is in the future
+    /// and returns it as RFC 3339 in UTC.
+    fn check_send_at(datetime: Option<&String>) -> Result<String, NetCommsError> {
+
+        let send_at = Self::check_datetime(datetime, "--at")?;
+
+        if send_at <= Utc::now() {
+            return Err(NetCommsError::new(
+                NetCommsErrorKind::InvalidCommand, 
+                Some(format!("Datetime {} is not in the future.", send_at.with_timezone(&Local).format("%Y-%m-%dT%H:%M:%S")))));
+        }
+
+        Ok(send_at.to_rfc3339_opts(SecondsFormat::Secs, true))
+    }
+
+    /// Checks that value of given option is a datetime, either RFC 3339, local time as `2026-11-01T09:00`
+    /// or a local date as `2026-11-01` which means its midnight, and returns it in UTC.
+    fn check_datetime(datetime: Option<&String>, option: &str) -> Result<DateTime<Utc>, NetCommsError> {
+
+        let datetime = match datetime {
+            Some(datetime) => datetime.trim(),
+            None => {
+                return Err(NetCommsError::new(
+                    NetCommsErrorKind::InvalidCommand, 
+                    Some(format!("Option {} needs to be followed by a datetime.", option))));
+            },
+        };
+
+        let local = NaiveDateTime::parse_from_str(datetime, "%Y-%m-%dT%H:%M")
+                        .or_else(|_| NaiveDateTime::parse_from_str(datetime, "%Y-%m-%dT%H:%M:%S"))
+                        .or_else(|_| NaiveDate::parse_from_str(datetime, "%Y-%m-%d").map(|date| date.and_hms(0, 0, 0)))
+                        .ok()
+                        .and_then(|datetime| Local.from_local_datetime(&datetime).single())
+                        .map(|datetime| datetime.with_timezone(&Utc));
+        match local {
+            Some(datetime) => Ok(datetime),
+            None => match DateTime::parse_from_rfc3339(datetime) {
+                Ok(datetime) => Ok(datetime.with_timezone(&Utc)),
+                Err(_) => Err(NetCommsError::new(
+                    NetCommsErrorKind::InvalidCommand, 
+                    Some(format!("{} is not a valid datetime, use for example 2026-11-01T09:00.", datetime)))),
+            },
+        }
+    }
+
+    /// Checks if given command is valid search command, which is `search [options] <query>` with options
+    /// `--server`, `--peer <username>/#<group>`, `--from <datetime>`, `--to <datetime>`, `--limit <n>` and `--page <n>`.
+    ///
+    /// Without any option it searches messages saved on this device, any option makes server search for them.
+    fn check_search(cmd: CommandRaw, user: UserLite) -> Result<Command, NetCommsError> {
+
+        let mut cmd_iter = cmd.vec
+                              .iter()
+                              .map(|x| x.trim().to_string())
+                              .filter(|x| !x.is_empty())
+                              // Skips first, "search", element.
+                              .skip(1)
+                              .peekable();
+
+        let mut peer = None;
+        let mut from = None;
+        let mut to = None;
+        let mut limit = None;
+        let mut page = 1;
+        let mut server = false;
+        while let Some(option) = cmd_iter.next_if(|part| part.starts_with("--")) {
+            server = true;
+            if option == "--server" {
+                continue;
+            }
+            let value = cmd_iter.next();
+            match option.as_str() {
+                "--peer" => match value {
+                    Some(value) => peer = Some(value),
+                    None => {
+                        return Err(NetCommsError::new(
+                            NetCommsErrorKind::InvalidCommand, 
+                            Some("Option --peer needs to be followed by a username or a group.".to_string())));
+                    },
+                },
+                "--from" => from = Some(Self::check_datetime(value.as_ref(), "--from")?),
+                "--to" => to = Some(Self::check_datetime(value.as_ref(), "--to")?),
+                "--limit" | "--page" => {
+                    let number = match value.as_ref().map(|value| value.parse::<usize>()) {
+                        Some(Ok(number)) if number > 0 => number,
+                        _ => {
+                            return Err(NetCommsError::new(
+                                NetCommsErrorKind::InvalidCommand, 
+                                Some(format!("Option {} needs to be followed by a positive number.", option))));
+                        },
+                    };
+                    if option == "--limit" {
+                        limit = Some(number);
+                    } else {
+                        page = number;
+                    }
+                },
+                _ => {
+                    return Err(NetCommsError::new(
+                        NetCommsErrorKind::InvalidCommand, 
+                        Some(format!("Unknown option {} in \"search\" command.", option))));
+                },
+            }
+        }
+
+        let query = cmd_iter.collect::<Vec<String>>().join(" ");
+        if query.is_empty() {
+            return Err(NetCommsError::new(
+                NetCommsErrorKind::InvalidCommand, 
+                Some("Command search needs to be followed by words to search for.".to_string())));
+        }
+
+        if !server {
+            return Ok(Command::SearchHistory(query, user));
+        }
+
+        let from = from.map(|from| from.to_rfc3339_opts(SecondsFormat::Secs, true));
+        let to = to.map(|to| to.to_rfc3339_opts(SecondsFormat::Secs, true));
+        let offset = (page - 1) * limit.unwrap_or(DEFAULT_SEARCH_RESULTS);
+
+        Ok(Command::Search(query, peer, from, to, limit, offset, user))
+    }
+
+    /// Checks if given command is `history <username> [n]` and returns the username
+    /// and how many messages to show, [None] if it was not given.
+    fn check_history(cmd: CommandRaw) -> Result<(String, Option<usize>), NetCommsError> {
+
+        let cmd_vec: Vec<String> = cmd.vec
+                                      .iter()
+                                      .map(|x| x.trim().to_string())
+                                      .filter(|x| !x.is_empty())
+                                      // Skips first, "history", element.
+                                      .skip(1)
+                                      .collect();
+
+        match cmd_vec.as_slice() {
+            [username] => Ok((username.clone(), None)),
+            [username, count] => match count.parse::<usize>() {
+                Ok(count) if count > 0 => Ok((username.clone(), Some(count))),
+                _ => Err(NetCommsError::new(
+                    NetCommsErrorKind::InvalidCommand, 
+                    Some(format!("{} is not a valid number of messages.", count)))),
+            },
+            _ => Err(NetCommsError::new(
+                NetCommsErrorKind::InvalidCommand, 
+                Some("Command history needs to be followed by a username and optionally a number of messages.".to_string()))),
+        }
+    }
+
+    /// Checks if given command is `sync <username>/#<group> [id]` and returns the username or group
+    /// and id of a message only older messages are synced than, [None] if it was not given.
+    fn check_sync(cmd: CommandRaw) -> Result<(String, Option<usize>), NetCommsError> {
+
+        let cmd_vec: Vec<String> = cmd.vec
+                                      .iter()
+                                      .map(|x| x.trim().to_string())
+                                      .filter(|x| !x.is_empty())
+                                      // Skips first, "sync", element.
+                                      .skip(1)
+                                      .collect();
+
+        match cmd_vec.as_slice() {
+            [peer_or_group] => Ok((peer_or_group.clone(), None)),
+            [peer_or_group, id] => match id.parse() {
+                Ok(id) => Ok((peer_or_group.clone(), Some(id))),
+                Err(_) => Err(NetCommsError::new(
+                    NetCommsErrorKind::InvalidCommand, 
+                    Some(format!("{} is not a valid message id.", id)))),
+            },
+            _ => Err(NetCommsError::new(
+                NetCommsErrorKind::InvalidCommand, 
+                Some("Command sync needs to be followed by a username or a group and optionally a message id.".to_string()))),
+        }
+    }
+
+    /// Checks if given command is valid group command, which is `group <action> <name>` for actions
+    /// `create`, `leave` and `members` or `group <action> <name> <argument>` for the rest of them.
+    fn check_group(cmd: CommandRaw, user: UserLite) -> Result<Command, NetCommsError> {
+
+        let mut cmd_vec: Vec<String> = cmd.vec
+                                      .iter()
+                                      .map(|x| x.trim().to_string())
+                                      .filter(|x| !x.is_empty())
+                                      // Skips first, "group", element.
+                                      .skip(1)
+                                      .collect();
+
+        if cmd_vec.len() < 2 {
+            return Err(NetCommsError::new(
+                NetCommsErrorKind::InvalidCommand, 
+                Some("Command group needs to be followed by an action and a name of the group.".to_string())));
+        }
+
+        let action = cmd_vec.remove(0);
+        let name = cmd_vec.remove(0).trim_start_matches(GROUP_PREFIX).to_string();
+
+        let expected_len = match action.as_str() {
+            "create" | "leave" | "members" => 0,
+            _ => 1,
+        };
+        if cmd_vec.len() != expected_len {
+            return Err(NetCommsError::new(
+                NetCommsErrorKind::InvalidCommand, 
+                Some(format!("Command group {} does not have correct number of parts.", action))));
+        }
+
+        match action.as_str() {
+            "create" => Ok(Command::CreateGroup(name, user)),
+            "invite" => Ok(Command::InviteToGroup(name, cmd_vec.remove(0), user)),
+            "leave" => Ok(Command::LeaveGroup(name, user)),
+            "members" => Ok(Command::GroupMembers(name, user)),
+            "remove" => Ok(Command::RemoveFromGroup(name, cmd_vec.remove(0), user)),
+            "mute" => Ok(Command::MuteInGroup(name, cmd_vec.remove(0), true, user)),
+            "unmute" => Ok(Command::MuteInGroup(name, cmd_vec.remove(0), false, user)),
+            "promote" => Ok(Command::SetGroupRole(name, cmd_vec.remove(0), GroupRole::Moderator, user)),
+            "demote" => Ok(Command::SetGroupRole(name, cmd_vec.remove(0), GroupRole::Member, user)),
+            "owner" => Ok(Command::SetGroupRole(name, cmd_vec.remove(0), GroupRole::Owner, user)),
+            "rename" => Ok(Command::RenameGroup(name, cmd_vec.remove(0), user)),
+            "delete" => match cmd_vec[0].parse() {
+                Ok(message_id) => Ok(Command::DeleteGroupMessage(name, message_id, user)),
+                Err(_) => Err(NetCommsError::new(
+                    NetCommsErrorKind::InvalidCommand, 
+                    Some(format!("{} is not a valid message id.", cmd_vec[0])))),
+            },
+            _ => Err(NetCommsError::new(
+                NetCommsErrorKind::UnknownCommand, 
+                Some(format!("Unknown group action {}.", action)))),
+        }
+    }
+
+    /// Checks if given command is `<name> <id>` and returns its id.
+    fn check_id(cmd: CommandRaw, name: &str) -> Result<usize, NetCommsError> {
+
+        let (id, rest) = Self::check_id_and_rest(cmd, name)?;
+
+        if !rest.is_empty() {
+            return Err(NetCommsError::new(
+                NetCommsErrorKind::InvalidCommand, 
+                Some(format!("Command {} can only be followed by a message id.", name))));
+        }
+
+        Ok(id)
+    }
+
+    /// Checks if given command is `<name> <id> <text>`, for example `edit` or `reply`, and returns its id and text.
+    fn check_id_and_text(cmd: CommandRaw, name: &str) -> Result<(usize, String), NetCommsError> {
+
+        let (id, text) = Self::check_id_and_rest(cmd, name)?;
+
+        if text.is_empty() {
+            return Err(NetCommsError::new(
+                NetCommsErrorKind::InvalidCommand, 
+                Some(format!("No content in \"{}\" command.", name))));
+        }
+
+        Ok((id, text))
+    }
+
+    /// Returns message id that follows name of given command and trimmed rest of the command.
+    fn check_id_and_rest(cmd: CommandRaw, name: &str) -> Result<(usize, String), NetCommsError> {
+
+        let mut cmd_iter = cmd.vec.into_iter().skip(1).skip_while(|part| part.trim().is_empty());
+
+        let id = match cmd_iter.next().map(|id| id.trim().parse()) {
+            Some(Ok(id)) => id,
+            _ => {
+                return Err(NetCommsError::new(
+                    NetCommsErrorKind::InvalidCommand, 
+                    Some(format!("Command {} needs to be followed by a valid message id.", name))));
+            },
+        };
+
+        let rest: String = cmd_iter.collect();
+
+        Ok((id, rest.trim().to_string()))
+    }
+
+    /// Checks if given command is valid delete command, `delete <id>` or `delete <id> all`,
+    /// and returns its id and [DeleteScope].
+    fn check_delete(cmd: CommandRaw) -> Result<(usize, DeleteScope), NetCommsError> {
+
+        let cmd_vec: Vec<String> = cmd.vec
+                                      .iter()
+                                      .map(|x| x.trim().to_string())
+                                      .filter(|x| !x.is_empty())
+                                      // Skips first, "delete", element.
+                                      .skip(1)
+                                      .collect();
+
+        let id = match cmd_vec.get(0).map(|id| id.parse()) {
+            Some(Ok(id)) => id,
+            _ => {
+                return Err(NetCommsError::new(
+                    NetCommsErrorKind::InvalidCommand, 
+                    Some("Command delete needs to be followed by a valid message id.".to_string())));
+            },
+        };
+
+        match cmd_vec.get(1).map(|scope| scope.as_str()) {
+            None if cmd_vec.len() == 1 => Ok((id, DeleteScope::ForMe)),
+            Some("all") if cmd_vec.len() == 2 => Ok((id, DeleteScope::ForEveryone)),
+            _ => Err(NetCommsError::new(
+                NetCommsErrorKind::InvalidCommand, 
+                Some("Command delete can only be followed by a message id and optionally by \"all\".".to_string()))),
+        }
+    }
+
+    /// Splits content of send command to its text and paths of files written between `|`.
+    ///
+    /// Last path does not need to be closed by `|`, so `|<path to file>` is still valid.
+    fn split_attachments(content: &str) -> (String, Vec<String>) {
+
+        let mut text = String::new();
+        let mut paths = Vec::new();
+
+        // Every odd part is between two `|`, so it is a path.
+        for (i, part) in content.split('|').enumerate() {
+            if i % 2 == 0 {
+                text.push_str(part);
+            } else {
+                let path = part.trim();
+                if !path.is_empty() {
+                    paths.push(path.to_string());
+                }
+            }
+        }
+
+        (text.trim().to_string(), paths)
+    }
+
     /// Removes all invalid characters.
     fn remove_invalid(string: String) -> String {
         let invalid_symbols = [" ", ",", "(", ")"];
@@ -5,7 +5,7 @@ use std::sync::mpsc;
 
 mod command;
 use nardol::error::NetCommsError;
-use shared::ImplementedMessage;
+use shared::message::transfer;
 use shared::user::UserLite;
 use utils::input;
 
@@ -21,12 +21,14 @@ fn main() -> Result<(), NetCommsError> {
     // C:\Documents\Rust\net_comms\src\bin\client\client_config.ron
     let config_location = get_config_location();
     let config = ClientConfig::new(&config_location).unwrap();
+    if let Some(max_buffered_bytes) = config.max_buffered_bytes {
+        transfer::set_chunk_size(max_buffered_bytes);
+    }
 
     let mut db_path = config.save_location.clone();
     db_path.push("database.db");
 
     let (output_t, output_r) = mpsc::channel();
-    let (waiting_messages_t, _waiting_messages_r) = mpsc::channel::<ImplementedMessage>();
 
     open_database(&db_path, output_t.clone()).unwrap();
 
@@ -34,16 +36,18 @@ fn main() -> Result<(), NetCommsError> {
 
     let socket = SocketAddrV4::new(ip(&config), config.port);
 
-    let user = get_user(socket, UserLite::default_user(), output_t.clone()).unwrap();
+    let user = get_user(socket, UserLite::default_user(), &db_path, output_t.clone()).unwrap();
 
-    let handle = get_waiting_messages(user.clone(), socket, 
-                                   waiting_messages_t,
+    let handle = get_waiting_messages(user.clone(), socket,
                                                    config.request_incoming_messages_timer,
+                                                   config.auto_download_limit,
                                                    &config.save_location,
                                                    &db_path,
                                                    output_t.clone());
 
-    process_user_input(socket, user, output_t);
+    sweep_expired_messages(&config.save_location, &db_path, output_t.clone());
+
+    process_user_input(socket, user, &config.save_location, &db_path, output_t);
 
     handle.join().unwrap();
 
@@ -1,9 +1,11 @@
 use std::{path::{Path, PathBuf}, sync::mpsc::Sender};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, SecondsFormat, Utc};
 use nardol::{error::NetCommsError, prelude::{Bytes, FromBytes, FromRon, IntoBytes, Packet, PacketKind, ToRon}};
 use rusqlite::{Connection, Row, ToSql, types::ValueRef};
 use shared::{Content, ImplementedMessage, MessageKind, MetaData};
+use shared::message::{Attachment, FileReference};
+use shared::user::PresenceInfo;
 
 use super::Output;
 
@@ -13,7 +15,8 @@ pub fn open_database(db_path: &Path, _output_t: Sender<Output>) -> Result<(), Ne
         
     let db_conn =  Connection::open(db_path).unwrap();
 
-    // id should be later changed to AUTO INCREMENT
+    // Messages are identified by uuid their author generated, id is only local.
+    // Column attachments is left from older versions, attachments are kept in their own table.
     if let Err(_) = db_conn.execute(
         "CREATE TABLE messages (
             id                  INTEGER PRIMARY KEY NOT NULL,
@@ -24,8 +27,65 @@ pub fn open_database(db_path: &Path, _output_t: Sender<Output>) -> Result<(), Ne
             author_username     TEXT NOT NULL,
             recipient_id        INTEGER NOT NULL,
             file_name           TEXT,
-            content             TEXT,
-            end_data            TEXT
+            content             BLOB,
+            end_data            TEXT,
+            group_name          TEXT,
+            server_id           INTEGER,
+            edited              INTEGER NOT NULL DEFAULT 0,
+            deleted             INTEGER NOT NULL DEFAULT 0,
+            reply_to            INTEGER,
+            reactions           TEXT,
+            attachments         TEXT,
+            ttl                 INTEGER,
+            expires_at          TEXT,
+            uuid                TEXT,
+            read                INTEGER NOT NULL DEFAULT 0
+        )", []) {
+        // Falls here if table already exist, check if table has correct structure is necessary.
+    };
+
+    // Attachments of messages, position is their order inside the message, path is where they were saved.
+    if let Err(_) = db_conn.execute(
+        "CREATE TABLE attachments (
+            message_id          INTEGER NOT NULL,
+            position            INTEGER NOT NULL,
+            name                TEXT NOT NULL,
+            size                INTEGER NOT NULL,
+            mime_type           TEXT NOT NULL,
+            digest              TEXT NOT NULL,
+            path                TEXT
+        )", []) {
+        // Falls here if table already exist, check if table has correct structure is necessary.
+    };
+
+    // Files server announced instead of sending them, state is pending until user answers whether to download them.
+    // Attachment is position of the file inside its message, files sent on their own have none.
+    if let Err(_) = db_conn.execute(
+        "CREATE TABLE file_references (
+            message_id          INTEGER NOT NULL,
+            attachment          INTEGER,
+            author_username     TEXT NOT NULL,
+            name                TEXT NOT NULL,
+            size                INTEGER NOT NULL,
+            hash                TEXT NOT NULL,
+            state               TEXT NOT NULL
+        )", []) {
+        // Falls here if table already exist, check if table has correct structure is necessary.
+    };
+
+    // Device id server assigned to this client for each user that logged in from it.
+    if let Err(_) = db_conn.execute(
+        "CREATE TABLE devices (
+            username            TEXT PRIMARY KEY NOT NULL,
+            device_id           INTEGER NOT NULL
+        )", []) {
+        // Falls here if table already exist, check if table has correct structure is necessary.
+    };
+
+    if let Err(_) = db_conn.execute(
+        "CREATE TABLE presence (
+            username            TEXT PRIMARY KEY NOT NULL,
+            info                TEXT NOT NULL
         )", []) {
         // Falls here if table already exist, check if table has correct structure is necessary.
     };
@@ -46,15 +106,113 @@ pub fn open_database(db_path: &Path, _output_t: Sender<Output>) -> Result<(), Ne
         // Falls here if table already exist, check if table has correct structure is necessary.
     };    
 
+    migrate_database(&db_conn);
+
+    // Index is created only after migration, as older databases got uuid column only now.
+    // Uuid is chosen by its author, so it is only unique among messages of the same author.
+    db_conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS messages_uuid ON messages (author_id, uuid)", []).unwrap();
+
     Ok(())
 }
 
-pub fn insert_message(db_conn: &mut Connection, message: ImplementedMessage) {
+/// Version of database structure created by [open_database], it is stored in `PRAGMA user_version`.
+const DATABASE_VERSION: usize = 4;
+
+/// Columns added to tables after those tables were first created, with their definitions.
+const ADDED_COLUMNS: [(&str, &str, &str); 10] = [
+    ("messages", "group_name", "TEXT"),
+    ("messages", "server_id", "INTEGER"),
+    ("messages", "edited", "INTEGER NOT NULL DEFAULT 0"),
+    ("messages", "deleted", "INTEGER NOT NULL DEFAULT 0"),
+    ("messages", "reply_to", "INTEGER"),
+    ("messages", "reactions", "TEXT"),
+    ("messages", "attachments", "TEXT"),
+    ("messages", "ttl", "INTEGER"),
+    ("messages", "expires_at", "TEXT"),
+    ("messages", "uuid", "TEXT"),
+];
+
+/// Internal function used to bring database created by an older version up to [DATABASE_VERSION].
+///
+/// Tables that did not exist were already created with their current structure by [open_database].
+fn migrate_database(db_conn: &Connection) {
+
+    let mut stmt = db_conn.prepare("PRAGMA user_version").unwrap();
+    let version: usize = stmt.query_map([], |row| {
+        let version: usize = row.get(0).unwrap();
+        Ok(version)
+    }).unwrap().next().unwrap().unwrap();
+
+    if version >= DATABASE_VERSION {
+        return;
+    }
+
+    db_conn.execute_batch("BEGIN").unwrap();
+
+    if version < 1 {
+        for &(table, column, definition) in ADDED_COLUMNS.iter() {
+            let mut stmt = db_conn.prepare("SELECT name FROM pragma_table_info(?1) WHERE name=?2").unwrap();
+            if !stmt.exists([table, column]).unwrap() {
+                db_conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), []).unwrap();
+            }
+        }
+
+        // Content used to be stored as text without its kind, all of it was text.
+        db_conn.execute("UPDATE messages
+                             SET content = CAST(x'01' || content AS BLOB)
+                             WHERE typeof(content)='text'", []).unwrap();
+    }
+
+    if version < 2 {
+        // Attachments used to be stored in RON inside messages table, which could not hold their paths.
+        let legacy: Vec<(usize, Vec<Attachment>)> = {
+            let mut stmt = db_conn.prepare("SELECT id, attachments
+                                                         FROM messages
+                                                         WHERE attachments IS NOT NULL").unwrap();
+            let legacy_iter = stmt.query_map([], |row| {
+                let id: usize = row.get(0).unwrap();
+                let attachments: String = row.get(1).unwrap();
+                Ok((id, ron::from_str(&attachments).unwrap_or_default()))
+            }).unwrap();
+
+            legacy_iter.map(|legacy| legacy.unwrap()).collect()
+        };
+
+        for (id, attachments) in legacy {
+            insert_attachments(db_conn, id, &attachments);
+        }
+        db_conn.execute("UPDATE messages SET attachments=NULL", []).unwrap();
+    }
+
+    if version < 3 {
+        let mut stmt = db_conn.prepare("SELECT name FROM pragma_table_info('messages') WHERE name='read'").unwrap();
+        if !stmt.exists([]).unwrap() {
+            db_conn.execute("ALTER TABLE messages ADD COLUMN read INTEGER NOT NULL DEFAULT 0", []).unwrap();
+            // Older versions marked every received message as read as soon as it arrived.
+            db_conn.execute("UPDATE messages SET read=1", []).unwrap();
+        }
+    }
+
+    if version < 4 {
+        // Index used to be on uuid alone, it is created again on author and uuid by open_database.
+        db_conn.execute("DROP INDEX IF EXISTS messages_uuid", []).unwrap();
+    }
+
+    db_conn.execute_batch(&format!("PRAGMA user_version = {}", DATABASE_VERSION)).unwrap();
+    db_conn.execute_batch("COMMIT").unwrap();
+}
+
+/// Saves given message, returns `false` if message with the same author and uuid or id on server is already saved.
+pub fn insert_message(db_conn: &mut Connection, message: ImplementedMessage) -> bool {
 
     let metadata = message.metadata_ref();
 
-    let id = get_new_message_id(db_conn);
-    let id = id.to_sql().unwrap();
+    if let Some(server_id) = metadata.message_id() {
+        let mut stmt = db_conn.prepare("SELECT id FROM messages WHERE server_id=?1 LIMIT 1").unwrap();
+        if stmt.exists([server_id]).unwrap() {
+            return false;
+        }
+    }
 
     let kind = metadata.message_kind().to_ron().unwrap();
     let kind = kind.to_sql().unwrap();
@@ -77,19 +235,44 @@ pub fn insert_message(db_conn: &mut Connection, message: ImplementedMessage) {
     let file_name = metadata.file_name();
     let file_name = file_name.to_sql().unwrap();
 
-    let content = message.content().into_string();
+    let content = message.content().into_bytes().into_vec();
     let content = content.to_sql().unwrap();
 
     // Change this later so it can accommodate also non string data.
     let end_data = message.end_data().content_move().to_string();
     let end_data = end_data.to_sql().unwrap();
 
-    db_conn.execute("INSERT INTO messages
-                            (id, kind, length, datetime, author_id, author_username,
-                            recipient_id, file_name, content, end_data)
-                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+    let group_name = metadata.group();
+    let group_name = group_name.to_sql().unwrap();
+
+    let server_id = metadata.message_id();
+    let server_id = server_id.to_sql().unwrap();
+
+    let edited = metadata.edited();
+    let edited = edited.to_sql().unwrap();
+
+    let reply_to = metadata.reply_to();
+    let reply_to = reply_to.to_sql().unwrap();
+
+    let reactions = ron::to_string(&metadata.reactions()).unwrap();
+    let reactions = reactions.to_sql().unwrap();
+
+    // Time to live counts from the moment message is read, so it expires only once mark_message_read is called.
+    let ttl = metadata.ttl();
+    let ttl = ttl.to_sql().unwrap();
+    let expires_at: Option<String> = None;
+    let expires_at = expires_at.to_sql().unwrap();
+
+    let uuid = metadata.uuid();
+    let uuid = uuid.to_sql().unwrap();
+
+    let inserted = db_conn.execute("INSERT INTO messages
+                            (kind, length, datetime, author_id, author_username,
+                            recipient_id, file_name, content, end_data, group_name, server_id, edited,
+                            reply_to, reactions, ttl, expires_at, uuid)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)
+                         ON CONFLICT(author_id, uuid) DO NOTHING",
                             [
-                                id.clone(),
                                 kind,
                                 length,
                                 datetime,
@@ -99,42 +282,383 @@ pub fn insert_message(db_conn: &mut Connection, message: ImplementedMessage) {
                                 file_name,
                                 content,
                                 end_data,
+                                group_name,
+                                server_id,
+                                edited,
+                                reply_to,
+                                reactions,
+                                ttl,
+                                expires_at,
+                                uuid,
                             ]).unwrap();
 
-    let mut non_existent_recipients = Vec::new();
-    
+    if inserted == 0 {
+        return false;
+    }
+
+    let id = db_conn.last_insert_rowid();
+    insert_attachments(db_conn, id as usize, &metadata.attachments());
+
+    let id = id.to_sql().unwrap();
+
     for recipient in metadata.recipients() {
-        match get_user_id_from_username(db_conn, &recipient) {
-            Ok(recipient_id) => {
-
-                let recipient_id = recipient_id.to_sql().unwrap();
-
-                db_conn.execute("INSERT INTO message_recipients
-                                (message_id, recipient_id)
-                                VALUES (?1, ?2)",
-                                [
-                                    id.clone(),
-                                    recipient_id.clone()
-                                ]).unwrap();
-            },
-            Err(_) => {
-                non_existent_recipients.push(recipient);
-            },
-        }
+        // Client learns about users only from messages, so unknown recipients are remembered.
+        let recipient_id = match get_user_id_from_username(db_conn, &recipient) {
+            Ok(recipient_id) => recipient_id,
+            Err(_) => insert_user(db_conn, &recipient),
+        };
+        let recipient_id = recipient_id.to_sql().unwrap();
+
+        db_conn.execute("INSERT INTO message_recipients
+                        (message_id, recipient_id)
+                        VALUES (?1, ?2)",
+                        [
+                            id.clone(),
+                            recipient_id.clone()
+                        ]).unwrap();
+    }
+
+    true
+}
+
+/// Replaces content of a message with given id on server and marks it as edited.
+///
+/// Returns an error if there is no such message.
+pub fn update_message_content(db_conn: &mut Connection, server_id: usize, content: Content) -> Result<(), ()> {
+
+    let content = content.into_bytes().into_vec();
+
+    match db_conn.execute("UPDATE messages
+                               SET content=?1, edited=1
+                               WHERE server_id=?2",
+                          [content.to_sql().unwrap(), server_id.to_sql().unwrap()]).unwrap() {
+        0 => Err(()),
+        _ => Ok(()),
     }
 }
 
-pub fn get_new_message_id(db_conn: &mut Connection) -> usize {
+/// Hides a message with given id on server, it stays in database but is not shown anymore.
+///
+/// Returns an error if there is no such message.
+pub fn hide_message(db_conn: &mut Connection, server_id: usize) -> Result<(), ()> {
 
-    let mut stmt = db_conn.prepare("SELECT MAX(id) FROM messages LIMIT 1").unwrap();
-    let mut id = stmt.query_map([], |row| {
-        let id: usize = match row.get(0) {
-            Ok(id) => id,
-            Err(_) => 0,
-        };
-        Ok(id)
-    }).unwrap().next().unwrap().unwrap();
-    id += 1;
+    match db_conn.execute("UPDATE messages
+                               SET deleted=1
+                               WHERE server_id=?1", [server_id]).unwrap() {
+        0 => Err(()),
+        _ => Ok(()),
+    }
+}
+
+/// Marks a message with given id on server as read by the user,
+/// if it has time to live, it starts counting down now.
+///
+/// Returns `false` if there is no such message or it was already read.
+pub fn mark_message_read(db_conn: &mut Connection, server_id: usize) -> bool {
+
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+
+    db_conn.execute("UPDATE messages
+                         SET read=1,
+                             expires_at=CASE WHEN ttl IS NULL THEN NULL
+                                             ELSE strftime('%Y-%m-%dT%H:%M:%SZ', ?2, '+' || ttl || ' seconds')
+                                        END
+                         WHERE server_id=?1 AND read=0",
+                        [server_id.to_sql().unwrap(), now.to_sql().unwrap()]).unwrap() != 0
+}
+
+/// Replaces reactions of a message with given id on server.
+///
+/// Returns an error if there is no such message.
+pub fn update_message_reactions(db_conn: &mut Connection, server_id: usize, reactions: &Vec<(String, usize)>) -> Result<(), ()> {
+
+    let reactions = ron::to_string(reactions).unwrap();
+
+    match db_conn.execute("UPDATE messages
+                               SET reactions=?1
+                               WHERE server_id=?2",
+                          [reactions.to_sql().unwrap(), server_id.to_sql().unwrap()]).unwrap() {
+        0 => Err(()),
+        _ => Ok(()),
+    }
+}
+
+/// Saves last known [PresenceInfo] of a user, replacing the previous one.
+pub fn update_presence(db_conn: &mut Connection, presence: &PresenceInfo) {
+
+    let info = presence.to_ron().unwrap();
+
+    db_conn.execute("INSERT INTO presence (username, info)
+                         VALUES (?1, ?2)
+                         ON CONFLICT(username) DO UPDATE SET info=?2",
+                        [presence.username(), info]).unwrap();
+}
+
+/// Returns last known [PresenceInfo] of user with given username, [None] if user is not watched.
+pub fn get_presence(db_conn: &mut Connection, username: &str) -> Option<PresenceInfo> {
+
+    let mut stmt = db_conn.prepare("SELECT info FROM presence WHERE username=?1").unwrap();
+    let mut presence_iter = stmt.query_map([username], |row| {
+        let info: String = row.get(0).unwrap();
+        Ok(info)
+    }).unwrap();
+
+    PresenceInfo::from_ron(&presence_iter.next()?.unwrap()).ok()
+}
+
+/// Saves id of the device server assigned to this client for user with given username.
+pub fn set_device_id(db_conn: &mut Connection, username: &str, device_id: usize) {
+
+    db_conn.execute("INSERT INTO devices (username, device_id)
+                         VALUES (?1, ?2)
+                         ON CONFLICT(username) DO UPDATE SET device_id=?2",
+                        [username.to_sql().unwrap(), device_id.to_sql().unwrap()]).unwrap();
+}
+
+/// Returns id of the device server assigned to this client for user with given username,
+/// [None] if the user never logged in from it.
+pub fn get_device_id(db_conn: &mut Connection, username: &str) -> Option<usize> {
+
+    let mut stmt = db_conn.prepare("SELECT device_id FROM devices WHERE username=?1").unwrap();
+    let mut device_iter = stmt.query_map([username], |row| {
+        let device_id: usize = row.get(0).unwrap();
+        Ok(device_id)
+    }).unwrap();
+
+    Some(device_iter.next()?.unwrap())
+}
+
+/// Forgets presence of user with given username.
+pub fn delete_presence(db_conn: &mut Connection, username: &str) {
+    db_conn.execute("DELETE FROM presence WHERE username=?1", [username]).unwrap();
+}
+
+/// Saves a [FileReference] from user with given username in given state, pending ones are asked about
+/// even after restart.
+///
+/// Returns `false` if it is already saved, that is when server sent it again.
+pub fn insert_file_reference(db_conn: &mut Connection,
+                             file_reference: &FileReference,
+                             author_username: &str,
+                             state: &str) -> bool {
+
+    db_conn.execute("INSERT INTO file_references
+                            (message_id, attachment, author_username, name, size, hash, state)
+                         SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7
+                         WHERE NOT EXISTS (SELECT 1 FROM file_references WHERE message_id=?1 AND attachment IS ?2)",
+                        [
+                            file_reference.message_id().to_sql().unwrap(),
+                            file_reference.attachment().to_sql().unwrap(),
+                            author_username.to_sql().unwrap(),
+                            file_reference.name().to_sql().unwrap(),
+                            file_reference.size().to_sql().unwrap(),
+                            file_reference.hash().to_sql().unwrap(),
+                            state.to_sql().unwrap(),
+                        ]).unwrap() != 0
+}
+
+/// Returns every [FileReference] user was not asked about yet or did not answer, oldest first,
+/// each with username of its author.
+pub fn get_pending_file_references(db_conn: &mut Connection) -> Vec<(FileReference, String)> {
+
+    let mut stmt = db_conn.prepare("SELECT message_id, name, size, hash, attachment, author_username
+                                                 FROM file_references
+                                                 WHERE state='pending'
+                                                 ORDER BY rowid").unwrap();
+
+    let references_iter = stmt.query_map([], |row| {
+        let author_username: String = row.get(5).unwrap();
+        Ok((file_reference_from_row(row), author_username))
+    }).unwrap();
+
+    references_iter.map(|reference| reference.unwrap()).collect()
+}
+
+/// Returns every [FileReference] of a message with given id on server in any state,
+/// that is its file or its attachments in their order.
+pub fn get_file_references(db_conn: &mut Connection, message_id: usize) -> Vec<FileReference> {
+
+    let mut stmt = db_conn.prepare("SELECT message_id, name, size, hash, attachment
+                                                 FROM file_references
+                                                 WHERE message_id=?1
+                                                 ORDER BY attachment").unwrap();
+
+    let references_iter = stmt.query_map([message_id], |row| {
+        Ok(file_reference_from_row(row))
+    }).unwrap();
+
+    references_iter.map(|reference| reference.unwrap()).collect()
+}
+
+/// Sets state of given [FileReference], it is pending, accepted or declined.
+pub fn set_file_reference_state(db_conn: &mut Connection, file_reference: &FileReference, state: &str) {
+
+    db_conn.execute("UPDATE file_references SET state=?1 WHERE message_id=?2 AND attachment IS ?3",
+                        [
+                            state.to_sql().unwrap(),
+                            file_reference.message_id().to_sql().unwrap(),
+                            file_reference.attachment().to_sql().unwrap(),
+                        ]).unwrap();
+}
+
+/// Deletes given [FileReference], after its file was downloaded.
+pub fn delete_file_reference(db_conn: &mut Connection, file_reference: &FileReference) {
+
+    db_conn.execute("DELETE FROM file_references WHERE message_id=?1 AND attachment IS ?2",
+                        [
+                            file_reference.message_id().to_sql().unwrap(),
+                            file_reference.attachment().to_sql().unwrap(),
+                        ]).unwrap();
+}
+
+/// Returns [FileReference] stored in the first five columns of a row from file_references table.
+fn file_reference_from_row(row: &Row) -> FileReference {
+
+    let attachment: Option<usize> = row.get(4).unwrap();
+    match attachment {
+        Some(position) => FileReference::for_attachment(row.get(0).unwrap(),
+                                                        position,
+                                                        row.get(1).unwrap(),
+                                                        row.get(2).unwrap(),
+                                                        row.get(3).unwrap()),
+        None => FileReference::new(row.get(0).unwrap(),
+                                   row.get(1).unwrap(),
+                                   row.get(2).unwrap(),
+                                   row.get(3).unwrap()),
+    }
+}
+
+/// Returns reactions stored in given column of a row from messages table.
+fn reactions_from_row(row: &Row, index: usize) -> Vec<(String, usize)> {
+
+    let reactions: Option<String> = row.get(index).unwrap();
+    match reactions {
+        Some(reactions) => ron::from_str(&reactions).unwrap_or_default(),
+        None => Vec::new(),
+    }
+}
+
+/// Saves attachments of a message with given id together with their paths.
+fn insert_attachments(db_conn: &Connection, message_id: usize, attachments: &[Attachment]) {
+
+    for (position, attachment) in attachments.iter().enumerate() {
+        add_attachment(db_conn, message_id, position, attachment);
+    }
+}
+
+/// Saves attachment at given position of a message with given id together with its path,
+/// replacing one saved there before, used for attachments downloaded after their message.
+pub fn add_attachment(db_conn: &Connection, message_id: usize, position: usize, attachment: &Attachment) {
+
+    let path = attachment.path().map(|path| path.to_string_lossy().to_string());
+
+    db_conn.execute("DELETE FROM attachments WHERE message_id=?1 AND position=?2", [message_id, position]).unwrap();
+    db_conn.execute("INSERT INTO attachments
+                        (message_id, position, name, size, mime_type, digest, path)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        [
+                            message_id.to_sql().unwrap(),
+                            position.to_sql().unwrap(),
+                            attachment.name().to_sql().unwrap(),
+                            attachment.size().to_sql().unwrap(),
+                            attachment.mime_type().to_sql().unwrap(),
+                            attachment.digest().to_sql().unwrap(),
+                            path.to_sql().unwrap(),
+                        ]).unwrap();
+}
+
+/// Returns attachments of a message with given id, with paths where they were saved.
+pub fn get_message_attachments(db_conn: &mut Connection, message_id: usize) -> Vec<Attachment> {
+
+    let mut stmt = db_conn.prepare("SELECT name, size, mime_type, digest, path
+                                                 FROM attachments
+                                                 WHERE message_id=?1
+                                                 ORDER BY position").unwrap();
+
+    let attachments_iter = stmt.query_map([message_id], |row| {
+        let path: Option<String> = row.get(4).unwrap();
+
+        Ok(Attachment::from_data(row.get(0).unwrap(),
+                                 row.get(1).unwrap(),
+                                 row.get(2).unwrap(),
+                                 row.get(3).unwrap(),
+                                 path.map(PathBuf::from)))
+    }).unwrap();
+
+    attachments_iter.map(|attachment| attachment.unwrap()).collect()
+}
+
+/// Returns messages whose time to live ended before given datetime, each as its id, id on server
+/// and paths of its files, that is received file and attachments.
+pub fn get_expired_messages(db_conn: &mut Connection, datetime: DateTime<Utc>) -> Vec<(usize, Option<usize>, Vec<PathBuf>)> {
+
+    let datetime = datetime.to_rfc3339_opts(SecondsFormat::Secs, true);
+
+    let messages: Vec<(usize, Option<usize>, Option<String>)> = {
+        let mut stmt = db_conn.prepare("SELECT id, server_id, file_name
+                                                     FROM messages
+                                                     WHERE expires_at IS NOT NULL AND expires_at<=?1").unwrap();
+
+        let messages_iter = stmt.query_map([datetime], |row| {
+            Ok((row.get(0).unwrap(), row.get(1).unwrap(), row.get(2).unwrap()))
+        }).unwrap();
+
+        messages_iter.map(|message| message.unwrap()).collect()
+    };
+
+    messages.into_iter().map(|(id, server_id, file_name)| {
+        let files = file_name.map(PathBuf::from)
+                             .into_iter()
+                             .chain(get_message_attachments(db_conn, id).iter().filter_map(|attachment| attachment.path()))
+                             .collect();
+        (id, server_id, files)
+    }).collect()
+}
+
+/// Deletes a message with given id from database.
+pub fn delete_message(db_conn: &mut Connection, message_id: usize) {
+
+    db_conn.execute("DELETE FROM message_recipients WHERE message_id=?1", [message_id]).unwrap();
+    db_conn.execute("DELETE FROM attachments WHERE message_id=?1", [message_id]).unwrap();
+    db_conn.execute("DELETE FROM messages WHERE id=?1", [message_id]).unwrap();
+}
+
+/// Returns author and text of a message with given id on server, shortened to `length` characters,
+/// [None] if there is no such message or it was deleted.
+pub fn get_message_snippet(db_conn: &mut Connection, server_id: usize, length: usize) -> Option<(String, String)> {
+
+    let mut stmt = db_conn.prepare("SELECT author_username, content
+                                                 FROM messages
+                                                 WHERE server_id=?1 AND deleted=0
+                                                 LIMIT 1").unwrap();
+
+    let mut snippet_iter = stmt.query_map([server_id], |row| {
+        let author: String = row.get(0).unwrap();
+        let content: Vec<u8> = row.get(1).unwrap();
+        Ok((author, Content::from_buff(&content).unwrap()))
+    }).unwrap();
+
+    let (author, content) = snippet_iter.next()?.unwrap();
+    let text = match content.as_text() {
+        Some(text) if text.chars().count() > length => format!("{}...", text.chars().take(length).collect::<String>()),
+        Some(text) => text.to_string(),
+        None => format!("{} bytes of binary data", content.data().len()),
+    };
+
+    Some((author, text))
+}
+
+/// Saves user with given username under a new local id and returns that id.
+pub fn insert_user(db_conn: &mut Connection, username: &str) -> usize {
+
+    let id = {
+        let mut stmt = db_conn.prepare("SELECT MAX(id) FROM users LIMIT 1").unwrap();
+        let id: Option<usize> = stmt.query_row([], |row| row.get(0)).unwrap();
+        id.unwrap_or(0) + 1
+    };
+
+    db_conn.execute("INSERT INTO users (id, username) VALUES (?1, ?2)",
+                        [id.to_sql().unwrap(), username.to_sql().unwrap()]).unwrap();
 
     id
 }
@@ -159,58 +683,15 @@ pub fn get_user_id_from_username(db_conn: &mut Connection,
 
 pub fn get_message_by_id(db_conn: &mut Connection, message_id: usize) -> Result<ImplementedMessage, ()> {
 
-    let recipients = get_message_recipients(db_conn, message_id).unwrap();
-    let recipients: Vec<String> = recipients.iter()
-                                            .map(|recip| format!("{}", recip))
-                                            .collect();
+    let recipients = get_message_recipients(db_conn, message_id)?;
+    let attachments = get_message_attachments(db_conn, message_id);
 
     let mut stmt = db_conn.prepare("SELECT *
                                                  FROM messages
                                                  WHERE id=?1").unwrap();
 
     let mut message_iter = stmt.query_map([message_id], |row| {
-
-        let mut message = ImplementedMessage::new();
-
-        let kind: String = row.get(1).unwrap();
-        let kind = MessageKind::from_ron(&kind).unwrap();
-
-        let datetime: String = row.get(3).unwrap();
-        let datetime = DateTime::parse_from_rfc3339(&datetime).unwrap();
-        let datetime = datetime.with_timezone(&Utc);
-
-        let file_name = match row.get_ref_unwrap(7) {
-            ValueRef::Null => None,
-            ValueRef::Text(path) => {
-                let path_string = String::from_buff(path).unwrap();
-                Some(path_string)
-            },
-            _ => panic!()
-        };
-
-        let metadata = MetaData::from_data(
-            kind,
-            row.get(2).unwrap(),
-            datetime.into_bytes(),
-            row.get(4).unwrap(),
-            row.get(5).unwrap(),
-            row.get(6).unwrap(),
-            recipients.clone(),
-            file_name,
-        );
-        
-        let content = row.get(8).unwrap();
-        let content = Content::with_data(content);
-
-        let end_data: String = row.get(9).unwrap();
-        let end_data = Packet::new(PacketKind::End, Bytes::from_vec(end_data.into_bytes()));
-
-        message.set_metadata(metadata);
-        message.set_content(content);
-        message.set_end_data(end_data);
-
-        Ok(message)
-
+        Ok(create_message_from_row(row, recipients.clone(), attachments.clone()))
     }).unwrap();
 
     match message_iter.next() {
@@ -219,59 +700,157 @@ pub fn get_message_by_id(db_conn: &mut Connection, message_id: usize) -> Result<
     }
 }
 
-// Not finished.
-pub fn get_message_by_author(db_location: PathBuf,
-                             author_username: String,
-                             limit: Option<u64>) -> Result<Vec<ImplementedMessage>, ()> {
-
-    let db_conn = Connection::open(&db_location).unwrap();
+/// Returns id of a message that has given id on server, hidden messages are not found.
+pub fn get_message_id_from_server_id(db_conn: &mut Connection, server_id: usize) -> Result<usize, ()> {
 
-    let mut stmt = db_conn.prepare("SELECT *
-                                    FROM messages
-                                    WHERE author_username=?1
-                                    LIMIT ?2").unwrap(); 
+    let mut stmt = db_conn.prepare("SELECT id FROM messages WHERE server_id=?1 AND deleted=0 LIMIT 1").unwrap();
+    let mut id_iter = stmt.query_map([server_id], |row| {
+        let id: usize = row.get(0).unwrap();
+        Ok(id)
+    }).unwrap();
 
-    let params = match limit {
-        Some(limit) => {
-            [author_username, limit.to_string()]
-        },
-        None => [author_username, String::from("1")],
-    };  
+    match id_iter.next() {
+        Some(id) => return Ok(id.unwrap()),
+        None => return Err(()),
+    }
+}
 
-    // I believe that sql can take integer argument as a string, but I may be wrong in which case this is gonna be a lot of work.
-    let mut messages_iter = stmt.query_map(params, |row| {
+/// Returns messages with given ids in the same order, those that do not exist are skipped.
+fn get_messages_by_ids(db_conn: &mut Connection, messages_ids: Vec<usize>) -> Vec<ImplementedMessage> {
+    messages_ids.into_iter()
+                .filter_map(|message_id| get_message_by_id(db_conn, message_id).ok())
+                .collect()
+}
 
-        let message_id = row.get(0).unwrap();
+/// Returns up to `limit` newest messages from user with given username, oldest first, by default only the newest one.
+pub fn get_message_by_author(db_conn: &mut Connection,
+                             author_username: &str,
+                             limit: Option<usize>) -> Result<Vec<ImplementedMessage>, ()> {
+
+    let messages_ids: Vec<usize> = {
+        let mut stmt = db_conn.prepare("SELECT id
+                                                     FROM messages
+                                                     WHERE author_username=?1 AND deleted=0
+                                                     ORDER BY datetime DESC, id DESC
+                                                     LIMIT ?2").unwrap();
+
+        let params = [author_username.to_sql().unwrap(), limit.unwrap_or(1).to_sql().unwrap()];
+        let ids_iter = stmt.query_map(params, |row| {
+            let id: usize = row.get(0).unwrap();
+            Ok(id)
+        }).unwrap();
+
+        ids_iter.map(|id| id.unwrap()).collect()
+    };
 
-        let mut db_conn_recipients = Connection::open(db_location.clone()).unwrap();
+    Ok(get_messages_by_ids(db_conn, messages_ids.into_iter().rev().collect()))
+}
 
-        let recipients = get_message_recipients(&mut db_conn_recipients, message_id).unwrap();
+/// Returns the last message sent at or before given datetime.
+pub fn get_message_by_time(db_conn: &mut Connection, datetime: DateTime<Utc>) -> Result<ImplementedMessage, ()> {
 
-        let message = create_message_from_row(row, recipients);
-        
-        Ok(message)
-        
-    }).unwrap();
+    // Datetimes are stored by DateTime::to_rfc3339, so they can be compared as text.
+    let datetime = datetime.to_rfc3339();
+
+    let message_id = {
+        let mut stmt = db_conn.prepare("SELECT id
+                                                     FROM messages
+                                                     WHERE datetime<=?1 AND deleted=0
+                                                     ORDER BY datetime DESC, id DESC
+                                                     LIMIT 1").unwrap();
+        let mut id_iter = stmt.query_map([datetime], |row| {
+            let id: usize = row.get(0).unwrap();
+            Ok(id)
+        }).unwrap();
+
+        match id_iter.next() {
+            Some(id) => id.unwrap(),
+            None => return Err(()),
+        }
+    };
 
-    let mut messages = Vec::new();
+    get_message_by_id(db_conn, message_id)
+}
 
-    for message in messages_iter.into_iter() {
-        messages.push(message.unwrap())
-    }
+/// Returns up to `limit` newest messages of direct conversation of local user with username `own_username`
+/// with user with given username, oldest first.
+///
+/// Those are messages the user sent and messages local user sent to it, group messages are not included.
+pub fn get_conversation(db_conn: &mut Connection, own_username: &str, username: &str, limit: usize) -> Vec<ImplementedMessage> {
+
+    let messages_ids: Vec<usize> = {
+        let mut stmt = db_conn.prepare("SELECT id
+                                                     FROM messages
+                                                     WHERE deleted=0 AND group_name IS NULL
+                                                     AND (author_username=?1
+                                                          OR (author_username=?2
+                                                              AND id IN (SELECT message_recipients.message_id
+                                                                         FROM message_recipients
+                                                                         INNER JOIN users ON users.id = message_recipients.recipient_id
+                                                                         WHERE users.username=?1)))
+                                                     ORDER BY datetime DESC, id DESC
+                                                     LIMIT ?3").unwrap();
+
+        let params = [username.to_sql().unwrap(), own_username.to_sql().unwrap(), limit.to_sql().unwrap()];
+        let ids_iter = stmt.query_map(params, |row| {
+            let id: usize = row.get(0).unwrap();
+            Ok(id)
+        }).unwrap();
+
+        ids_iter.map(|id| id.unwrap()).collect()
+    };
 
-    Ok(messages)
+    get_messages_by_ids(db_conn, messages_ids.into_iter().rev().collect())
 }
 
-pub fn get_message_by_time(db_conn: &mut Connection, datetime: DateTime<Utc>) -> Result<ImplementedMessage, ()> {
-    Err(())
+/// Returns up to `limit` newest messages whose text contains given text, ignoring case of ASCII letters, newest first.
+pub fn search_messages(db_conn: &mut Connection, text: &str, limit: usize) -> Vec<ImplementedMessage> {
+
+    // Characters with special meaning in LIKE pattern are matched literally.
+    let pattern = format!("%{}%", text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+
+    let messages_ids: Vec<usize> = {
+        // Content is stored with one byte of its kind first, binary content has kind 2.
+        let mut stmt = db_conn.prepare("SELECT id
+                                                     FROM messages
+                                                     WHERE deleted=0
+                                                     AND substr(content, 1, 1)<>x'02'
+                                                     AND CAST(substr(content, 2) AS TEXT) LIKE ?1 ESCAPE '\\'
+                                                     ORDER BY datetime DESC, id DESC
+                                                     LIMIT ?2").unwrap();
+
+        let params = [pattern.to_sql().unwrap(), limit.to_sql().unwrap()];
+        let ids_iter = stmt.query_map(params, |row| {
+            let id: usize = row.get(0).unwrap();
+            Ok(id)
+        }).unwrap();
+
+        ids_iter.map(|id| id.unwrap()).collect()
+    };
+
+    get_messages_by_ids(db_conn, messages_ids)
 }
 
+/// Returns usernames of recipients of a message with given id.
 pub fn get_message_recipients(db_conn: &mut Connection, message_id: usize) -> Result<Vec<String>, ()> {
-    Err(())
+
+    let mut stmt = db_conn.prepare("SELECT users.username
+                                                 FROM message_recipients
+                                                 INNER JOIN users ON users.id = message_recipients.recipient_id
+                                                 WHERE message_recipients.message_id=?1").unwrap();
+
+    let recipients_iter = stmt.query_map([message_id], |row| {
+        let username: String = row.get(0).unwrap();
+        Ok(username)
+    }).map_err(|_| ())?;
+
+    recipients_iter.map(|username| username.map_err(|_| ())).collect()
 }
 
 
-pub fn create_message_from_row(row: &rusqlite::Row<'_>, recipients: Vec<String>) -> ImplementedMessage {
+pub fn create_message_from_row(row: &rusqlite::Row<'_>,
+                               recipients: Vec<String>,
+                               attachments: Vec<Attachment>) -> ImplementedMessage {
 
     let mut message = ImplementedMessage::new();
 
@@ -291,7 +870,7 @@ pub fn create_message_from_row(row: &rusqlite::Row<'_>, recipients: Vec<String>)
         _ => panic!()
     };
 
-    let metadata = MetaData::from_data(
+    let mut metadata = MetaData::from_data(
         kind,
         row.get(2).unwrap(),
         datetime.into_bytes(),
@@ -301,9 +880,17 @@ pub fn create_message_from_row(row: &rusqlite::Row<'_>, recipients: Vec<String>)
         recipients.clone(),
         file_name,
     );
+    metadata.set_group(row.get(10).unwrap());
+    metadata.set_message_id(row.get(11).unwrap());
+    metadata.set_edited(row.get(12).unwrap());
+    metadata.set_reply_to(row.get(14).unwrap());
+    metadata.set_reactions(reactions_from_row(row, 15));
+    metadata.set_attachments(attachments);
+    metadata.set_ttl(row.get(17).unwrap());
+    metadata.set_uuid(row.get(19).unwrap());
         
-    let content = row.get(8).unwrap();
-    let content = Content::with_data(content);
+    let content: Vec<u8> = row.get(8).unwrap();
+    let content = Content::from_buff(&content).unwrap();
 
     let end_data: String = row.get(9).unwrap();
     let end_data = Packet::new(PacketKind::End, Bytes::from_vec(end_data.into_bytes()));
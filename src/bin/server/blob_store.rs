@@ -0,0 +1,60 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use nardol::error::{NetCommsError, NetCommsErrorKind};
+
+
+/// Name of directory inside `save_location` where are stored all received files.
+pub const BLOBS_DIR: &str = "blobs";
+
+/// Returns location of a file with given `hash` inside blob store of given `location`.
+///
+/// Files are split to directories by first two characters of their hash, so no directory grows too big.
+pub fn blob_location(location: &Path, hash: &str) -> PathBuf {
+
+    let mut path = PathBuf::from(location);
+    path.push(BLOBS_DIR);
+    path.push(hash.get(..2).unwrap_or(hash));
+    path.push(hash);
+
+    path
+}
+
+/// Moves a received file at `path` to blob store of given `location` and returns its new location.
+///
+/// If a file with the same `hash` is already stored, received file is only removed.
+pub fn store_blob(location: &Path, path: &Path, hash: &str) -> Result<PathBuf, NetCommsError> {
+
+    let blob_path = blob_location(location, hash);
+
+    if blob_path.is_file() {
+        let _ = fs::remove_file(path);
+        return Ok(blob_path);
+    }
+
+    if let Some(dir) = blob_path.parent() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            return Err(NetCommsError::new(
+                NetCommsErrorKind::OpeningFileFailed,
+                Some(format!("Failed to create directory {:?}. ({})", dir, e))));
+        }
+    }
+
+    if let Err(e) = fs::rename(path, &blob_path) {
+        return Err(NetCommsError::new(
+            NetCommsErrorKind::WritingToFileFailed,
+            Some(format!("Failed to move {:?} to blob store. ({})", path, e))));
+    }
+
+    // Removes now empty directory the file arrived in, it is not an error if it fails.
+    if let Some(dir) = path.parent() {
+        let _ = fs::remove_dir(dir);
+    }
+
+    Ok(blob_path)
+}
+
+/// Removes a file with given `hash` from blob store of given `location`.
+pub fn remove_blob(location: &Path, hash: &str) {
+    let _ = fs::remove_file(blob_location(location, hash));
+}
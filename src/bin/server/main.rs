@@ -10,7 +10,9 @@ use utils::input;
 
 use server::*;
 
-use server::sql::open_database;
+use server::sql::{open_database, set_motd};
+use server::typing::TypingRelay;
+use shared::message::transfer;
 
 // mod database;
 // ERROR HANDLING
@@ -20,6 +22,9 @@ fn main() -> Result<(), NetCommsError> {
     // C:\Documents\Rust\net_comms\src\bin\server\server_config.ron
     let config_location = get_config_location();
     let config = ServerConfig::new(&config_location)?;
+    if let Some(max_buffered_bytes) = config.max_buffered_bytes {
+        transfer::set_chunk_size(max_buffered_bytes);
+    }
 
     let (can_start_t, can_start_r) = mpsc::channel::<bool>(); 
     let (allowance_t, allowance_r) = mpsc::channel::<bool>(); 
@@ -36,11 +41,19 @@ fn main() -> Result<(), NetCommsError> {
     db_path.push("database.db");
 
     open_database(&db_path, output_t.clone()).unwrap();
-    server_input(output_t.clone());
+    if let Some(motd) = config.motd.clone() {
+        let mut db_conn = rusqlite::Connection::open(&db_path).unwrap();
+        set_motd(&mut db_conn, Some(motd));
+    }
+    server_input(&db_path, &config.save_location, output_t.clone());
+    scheduler(&db_path, &config.save_location, output_t.clone());
 
     check_maximum_active_connections(config.maximum_active_connections.clone(),
                                          can_start_r, allowance_t, finished_r);
 
+    // Shared by every connection, typing events are only held in memory.
+    let typing = TypingRelay::new();
+
     let output_t_listener = output_t.clone();
     let listener_handle = thread::Builder::new().name("listener".to_string()).spawn(move || {
             loop {
@@ -50,6 +63,7 @@ fn main() -> Result<(), NetCommsError> {
                                           can_start_t.clone(), &allowance_r, finished_t.clone(),
                                           output_t_listener.clone(),
                                           &config,
+                                          &typing,
                                           &db_path
                         );
                     }
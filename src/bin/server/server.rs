@@ -13,24 +13,43 @@ use std::net::{Ipv4Addr, SocketAddrV4, TcpListener, TcpStream};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::mpsc::{Receiver, Sender};
+use std::time::{Duration, Instant};
 
 use nardol::error::{NetCommsError, NetCommsErrorKind};
 use nardol::ron::{FromRon, ToRon};
-use nardol::message::Message;
+use nardol::message::{ContentType, Message, MetaDataType};
 use nardol::bytes::{Bytes, FromBytes, IntoBytes};
 use nardol::message::IntoMessage;
 use nardol::packet::{Packet, PacketKind};
 
-use shared::message::{Content, MessageKind, MetaData, ServerReplyRaw};
-use shared::user::{Password, User, UserLite, UserUnchecked};
+use shared::message::{Content, DeleteScope, FileReference, MessageKind, MetaData, Notification, ReceiptKind, ServerError, ServerReplyRaw};
+use shared::message::transfer;
+use shared::config::{AWAY_TIMEOUT, DEFAULT_HISTORY_PAGE, DEFAULT_SEARCH_RESULTS, GROUP_PREFIX, MAX_HISTORY_PAGE, MAX_REACTION_LENGTH,
+                     MAX_SEARCH_RESULTS, MAX_STATUS_LENGTH, ONLINE_TIMEOUT, SERVER_ID, UNKNOWN_USER_ID};
+use shared::group::GroupRole;
+use shared::user::{Password, Presence, PresenceInfo, User, UserLite, UserUnchecked};
 use shared::{ImplementedMessage, Request};
 
 #[path ="./sql/mod.rs"]
 pub(crate) mod sql;
 use sql::*;
 
+#[path ="./blob_store.rs"]
+pub(crate) mod blob_store;
+
+#[path ="./typing.rs"]
+pub(crate) mod typing;
+use typing::{TypingRelay, TYPING_INTERVAL};
+
 use utils::input;
 
+/// How often scheduler checks for scheduled messages that should be sent.
+pub const SCHEDULER_INTERVAL: Duration = Duration::from_secs(1);
+/// Time after which a device that did not contact server is revoked, so messages stop waiting for it.
+pub const DEVICE_EXPIRY: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// How often scheduler checks for devices that were not seen for [DEVICE_EXPIRY].
+pub const DEVICE_EXPIRY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 pub enum Output {
     Error(String),
     FromRun(String),
@@ -44,6 +63,25 @@ pub struct ServerConfig {
     pub port: u16,
     pub maximum_active_connections: u16,
     pub save_location: PathBuf,
+    /// Maximum declared size of one message in bytes, [None] means there is no limit.
+    #[serde(default)]
+    pub max_message_size: Option<u64>,
+    /// Maximum number of bytes stored on server for each user, [None] means there is no quota.
+    #[serde(default)]
+    pub user_storage_quota: Option<u64>,
+    /// Maximum number of bytes of a file held in memory at once during its transfer,
+    /// if [None] [FILE_CHUNK_SIZE](shared::message::transfer::FILE_CHUNK_SIZE) is used.
+    #[serde(default)]
+    pub max_buffered_bytes: Option<usize>,
+    /// Number of seconds since a user was last seen for which it is online, if [None] [ONLINE_TIMEOUT] is used.
+    #[serde(default)]
+    pub online_timeout: Option<u64>,
+    /// Number of seconds since a user was last seen for which it is away, if [None] [AWAY_TIMEOUT] is used.
+    #[serde(default)]
+    pub away_timeout: Option<u64>,
+    /// Message of the day sent to users after they log in, if [Some] it replaces the one set from console on start.
+    #[serde(default)]
+    pub motd: Option<String>,
 }
 
 impl ToRon for ServerConfig {}
@@ -148,17 +186,128 @@ pub fn output(output_r: Receiver<Output>) {
     }).unwrap();
 }
 
-pub fn server_input(output_t: Sender<Output>) {
-        
+/// Reads administrator commands from console.
+///
+/// * `announce <text>` -- sends text to every registered user, offline users get it when they connect.
+/// * `motd <text>` -- sets message of the day sent after every login, `motd clear` removes it.
+pub fn server_input(db_path: &Path, location: &Path, output_t: Sender<Output>) {
+
+    let db_location = db_path.to_owned();
+    let location = location.to_owned();
+
     thread::Builder::new().name("input".to_string()).spawn(move|| {
+
+        let mut db_conn = Connection::open(db_location).unwrap();
+
         loop {
             let input = input("").unwrap();
-            // Later handle input.
-            output_t.send(Output::FromUserInput(format!("input: {:?}", input))).unwrap();
+            let input = input.trim();
+            let (command, text) = match input.split_once(' ') {
+                Some((command, text)) => (command, text.trim()),
+                None => (input, ""),
+            };
+
+            let output = match command {
+                "announce" if !text.is_empty() => {
+                    let count = announce(&mut db_conn, text.to_string(), &location);
+                    Output::FromUserInput(format!("Announcement sent to {} users.", count))
+                },
+                "motd" => match text {
+                    "" => match get_motd(&mut db_conn) {
+                        Some(motd) => Output::FromUserInput(format!("Message of the day: {}", motd)),
+                        None => Output::FromUserInput("There is no message of the day.".to_string()),
+                    },
+                    "clear" => {
+                        set_motd(&mut db_conn, None);
+                        Output::FromUserInput("Message of the day was cleared.".to_string())
+                    },
+                    text => {
+                        set_motd(&mut db_conn, Some(text.to_string()));
+                        Output::FromUserInput("Message of the day was set.".to_string())
+                    },
+                },
+                _ => Output::Error(format!("Unknown command: {:?}, use announce <text> or motd [<text>|clear].", input)),
+            };
+            output_t.send(output).unwrap();
+        }
+    }).unwrap();
+}
+
+/// Starts a thread that every [SCHEDULER_INTERVAL] sends scheduled messages whose time came
+/// and deletes messages whose time to live ended, together with their files,
+/// every [DEVICE_EXPIRY_INTERVAL] devices not seen for [DEVICE_EXPIRY] are revoked.
+///
+/// Both are kept in database, so messages that should have been sent or deleted while server was down
+/// are handled right after it starts.
+pub fn scheduler(db_path: &Path, location: &Path, output_t: Sender<Output>) {
+
+    let db_location = db_path.to_owned();
+    let location = location.to_owned();
+
+    thread::Builder::new().name("scheduler".to_string()).spawn(move || {
+
+        let mut db_conn = Connection::open(db_location).unwrap();
+        let mut last_device_expiry: Option<Instant> = None;
+
+        loop {
+            for message_id in get_due_messages_ids(&mut db_conn, Utc::now()) {
+                release_message(&mut db_conn, message_id);
+                output_t.send(Output::FromRun(format!("Sent scheduled message {}.", message_id))).unwrap();
+            }
+            for message_id in get_expired_messages_ids(&mut db_conn, Utc::now()) {
+                if delete_message(&mut db_conn, message_id, &location).is_ok() {
+                    output_t.send(Output::FromRun(format!("Deleted expired message {}.", message_id))).unwrap();
+                }
+            }
+            if !matches!(last_device_expiry, Some(last) if last.elapsed() < DEVICE_EXPIRY_INTERVAL) {
+                // Failed expiry is only reported, it is tried again after next interval.
+                match expire_devices(&mut db_conn, device_expiry_datetime()) {
+                    Ok(0) => {},
+                    Ok(expired) => {
+                        output_t.send(Output::FromRun(format!("Revoked {} devices that were not seen for too long.", expired))).unwrap();
+                    },
+                    Err(e) => output_t.send(Output::Error(format!("Failed to revoke expired devices.\n{}", e))).unwrap(),
+                }
+                last_device_expiry = Some(Instant::now());
+            }
+            thread::sleep(SCHEDULER_INTERVAL);
         }
     }).unwrap();
 }
 
+/// Returns datetime before which devices that did not contact server are expired.
+fn device_expiry_datetime() -> DateTime<Utc> {
+    Utc::now() - chrono::Duration::from_std(DEVICE_EXPIRY).unwrap()
+}
+
+/// Sends text from server to every registered user as [MessageKind::SeverReply],
+/// it is stored like any other message, so offline users get it later.
+///
+/// Returns number of users the announcement was sent to.
+fn announce(db_conn: &mut Connection, text: String, location: &Path) -> usize {
+
+    let recipients = get_all_usernames(db_conn);
+    let count = recipients.len();
+    if count == 0 {
+        return 0;
+    }
+
+    let content = Content::text(text).into_bytes();
+    let metadata = MetaData::new(&content, MessageKind::SeverReply,
+                                 UserLite::default_server(),
+                                 SERVER_ID, recipients, None).unwrap();
+
+    let mut message = ImplementedMessage::new();
+    message.set_metadata(metadata);
+    message.set_content(Content::from_bytes(content).unwrap());
+    message.set_end_data(Packet::new(PacketKind::End, Bytes::new()));
+
+    // Announcement holds no files, so there is nothing that could fail to be stored.
+    insert_message_into_database(message, db_conn, location).unwrap();
+
+    count
+}
+
 pub fn create_listener(config: &ServerConfig) -> TcpListener {
 
     let socket = SocketAddrV4::new(ip(config), config.port);
@@ -181,10 +330,13 @@ pub fn handle_connection(mut stream: TcpStream,
     finished: Sender<bool>,
     output: Sender<Output>,
     config: &ServerConfig,
+    typing: &TypingRelay,
     db_path: &Path) {
 
     let location = config.save_location.clone();
     let db_location = db_path.to_owned();
+    let config = config.clone();
+    let typing = typing.clone();
 
     loop {
         if let Err(e) = can_start.send(true) {
@@ -206,23 +358,35 @@ pub fn handle_connection(mut stream: TcpStream,
 
             let mut db_conn = Connection::open(db_location).unwrap();
 
-            match Message::receive(&mut stream, Some(location.clone())) {
+            match receive_message(&mut stream, &location, &mut db_conn, &config) {
 
-                Ok(message) => {                        
+                // Message was rejected, author already got a reply why.
+                Ok(None) => {},
+                Ok(Some(message)) => {                        
 
                     let metadata: MetaData = message.metadata();
                     let message_kind: MessageKind = metadata.message_kind();
+
+                    // Every message or request of a known user counts as its activity.
+                    if metadata.author_id() != UNKNOWN_USER_ID && metadata.author_id() != SERVER_ID {
+                        update_last_seen(&mut db_conn, metadata.author_id() as usize, &Utc::now().to_rfc3339());
+                        if let Some(device_id) = metadata.device_id() {
+                            update_device_last_seen(&mut db_conn, device_id, &Utc::now().to_rfc3339());
+                        }
+                    }
                     // let mut location = metadata.get_message_location(&location);
                     // location.push("message.ron");
                     // message.save(&location);
 
                     match message_kind {
                         MessageKind::Text | MessageKind::File => {
-                            let _ = insert_message_into_database(message, &mut db_conn);    
+                            if let Err(e) = insert_message_into_database(message, &mut db_conn, &location) {
+                                output.send(Output::Error(format!("Failed to save a message.\n{}", e))).unwrap();
+                            }
                         },
                         MessageKind::Request => {
                             // Maybe should create a database to store those requests as well?
-                            receive_request(message, stream, &mut db_conn, output);
+                            receive_request(message, stream, &mut db_conn, &location, &config, &typing, output);
                         },
                         _ => {}
                     }
@@ -250,9 +414,150 @@ pub fn handle_connection(mut stream: TcpStream,
     }
 }
 
+/// Receives a [Message](ImplementedMessage), declared size of messages from user to user is checked against
+/// limits from `config` before their content is accepted.
+///
+/// Returns [None] if message was rejected, in which case its author gets [ServerError] why.
+fn receive_message(stream: &mut TcpStream,
+                   location: &Path,
+                   db_conn: &mut Connection,
+                   config: &ServerConfig) -> Result<Option<ImplementedMessage>, NetCommsError> {
+
+    let mut metadata = MetaData::receive(stream, Some(location.to_path_buf()))?;
+
+    if let MessageKind::Text | MessageKind::File = metadata.message_kind() {
+        let checked = resolve_reply_recipients(&mut metadata, db_conn)
+                        .and_then(|_| check_limits(&metadata, db_conn, config, location))
+                        .and_then(|_| check_group_recipient(&metadata, db_conn));
+        if let Err(server_error) = checked {
+            let author = UserLite::new(metadata.author_id(), metadata.author_username());
+            let server_reply = ServerReplyRaw::Rejected(server_error, author);
+            server_reply.into_message()?.send(stream)?;
+            return Ok(None);
+        }
+    }
+
+    // Whatever was declared, files of a user can not take more than what is left of its quota.
+    let budget = match (metadata.message_kind(), config.user_storage_quota) {
+        (MessageKind::Text | MessageKind::File, Some(quota)) if metadata.author_id() != SERVER_ID => {
+            let usage = get_user_storage_usage(db_conn, metadata.author_id() as usize, location);
+            Some(quota.saturating_sub(usage))
+        },
+        _ => None,
+    };
+
+    let (content, end_data) = Content::receive_limited(stream, &metadata, location.to_path_buf(), budget)?;
+
+    let mut message = ImplementedMessage::new();
+    message.set_metadata(metadata);
+    message.set_content(content);
+    message.set_end_data(end_data);
+
+    Ok(Some(message))
+}
+
+/// Checks declared size of a message against maximum message size and storage quota of its author.
+fn check_limits(metadata: &MetaData,
+                db_conn: &mut Connection,
+                config: &ServerConfig,
+                location: &Path) -> Result<(), ServerError> {
+
+    // Part of a resumed transfer that is already staged is counted in usage.
+    let staged = metadata.transfer().map(|transfer| transfer.staged_bytes(location)).unwrap_or(0);
+
+    check_size(metadata.declared_size(), staged, metadata.author_id() as usize, db_conn, config, location)
+}
+
+/// Checks `size` in bytes against maximum message size and storage quota of user with given id,
+/// `staged` bytes of it are already counted in usage of that user.
+fn check_size(size: u64,
+              staged: u64,
+              user_id: usize,
+              db_conn: &mut Connection,
+              config: &ServerConfig,
+              location: &Path) -> Result<(), ServerError> {
+
+    if let Some(limit) = config.max_message_size {
+        if size > limit {
+            return Err(ServerError::MessageTooLarge { size, limit });
+        }
+    }
+
+    if let Some(quota) = config.user_storage_quota {
+        let usage = get_user_storage_usage(db_conn, user_id, location);
+        if usage + size.saturating_sub(staged) > quota {
+            return Err(ServerError::QuotaExceeded { usage, size, quota });
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks if a reply replies to a message its author can see.
+///
+/// Reply without recipients is sent to the conversation of that message, which is its group,
+/// or its author and the rest of its recipients.
+fn resolve_reply_recipients(metadata: &mut MetaData, db_conn: &mut Connection) -> Result<(), ServerError> {
+
+    let reply_to = match metadata.reply_to() {
+        Some(reply_to) => reply_to,
+        None => return Ok(()),
+    };
+    let author_id = metadata.author_id() as usize;
+
+    if !is_message_visible(db_conn, reply_to, author_id) {
+        return Err(ServerError::MessageNotFound { id: reply_to });
+    }
+
+    if !metadata.recipients().is_empty() {
+        return Ok(());
+    }
+
+    let recipients = match get_message_group(db_conn, reply_to) {
+        Some(group) => vec![group],
+        None => {
+            let mut ids = get_message_recipients_ids(db_conn, reply_to).unwrap_or_default();
+            ids.extend(get_message_author_id(db_conn, reply_to));
+            ids.into_iter()
+               .filter(|id| *id != author_id)
+               .filter_map(|id| get_username_from_id(db_conn, id).ok())
+               .collect()
+        },
+    };
+    metadata.set_recipients(recipients);
+
+    Ok(())
+}
+
+/// Checks if author of a message sent to a group is its member and was not muted there.
+fn check_group_recipient(metadata: &MetaData, db_conn: &mut Connection) -> Result<(), ServerError> {
+
+    let name = match metadata.recipients()
+                             .iter()
+                             .find_map(|recipient| recipient.strip_prefix(GROUP_PREFIX)) {
+        Some(name) => name.to_string(),
+        None => return Ok(()),
+    };
+    let group = format!("{}{}", GROUP_PREFIX, name);
+    let author_id = metadata.author_id() as usize;
+
+    match get_group_id(db_conn, &name) {
+        Ok(group_id) if is_group_member(db_conn, group_id, author_id) => {
+            if is_group_member_muted(db_conn, group_id, author_id) {
+                return Err(ServerError::MutedInGroup { group });
+            }
+            Ok(())
+        },
+        _ => Err(ServerError::NotGroupMember { group }),
+    }
+}
+
 fn receive_request(message: ImplementedMessage,
-                   stream: TcpStream, 
+                   mut stream: TcpStream, 
                    db_conn: &mut Connection, 
+                   location: &Path,
+                   config: &ServerConfig,
+                   typing: &TypingRelay,
                    output: Sender<Output>) {  
 
     let metadata = message.metadata();
@@ -260,22 +565,130 @@ fn receive_request(message: ImplementedMessage,
 
     let content = message.content_move();
 
-    let author = UserLite::new(metadata.author_id(),
+    let mut author = UserLite::new(metadata.author_id(),
                                            metadata.author_username());
+    author.set_device_id(metadata.device_id());
+
+    let request = Request::from_ron(&content.into_string()).unwrap();
 
-    let request = Request::from_ron(&String::from_buff(&content.into_buff())
-                                        .unwrap())
-                                        .unwrap();
+    // Revoked device can only log in again, which registers it as a new device.
+    if let Some(device_id) = author.device_id() {
+        if !matches!(request, Request::Register(_) | Request::Login(_))
+        && !is_device_active(db_conn, device_id, author.id() as usize) {
+            let server_reply = ServerReplyRaw::Error("This device was revoked, log in again.".to_string(), author);
+            send_server_reply(&mut stream, server_reply, &output);
+            return;
+        }
+    }
 
     match request {
         Request::Register(user_unchecked) => {
             user_register(stream, db_conn, user_unchecked, output);
         },
         Request::Login(user_unchecked) => {
-            user_login(stream, db_conn, user_unchecked, output).unwrap();
+            user_login(stream, db_conn, user_unchecked, author.device_id(), output).unwrap();
         },
         Request::GetWaitingMessagesAuto => {
-            return_waiting_messages(stream, db_conn, author, output);
+            return_waiting_messages(stream, db_conn, author, location, config, typing, output);
+        },
+        Request::GetTransferOffset(transfer_id) => {
+            return_transfer_offset(stream, transfer_id, author, location, output);
+        },
+        Request::DownloadFile(message_id) => {
+            download_file(stream, db_conn, message_id, author, location, output);
+        },
+        Request::DownloadAttachment(message_id, position) => {
+            download_attachment(stream, db_conn, message_id, position, author, location, output);
+        },
+        Request::GetStorageUsage => {
+            return_storage_usage(stream, db_conn, author, location, config, output);
+        },
+        Request::ResumeTransfer(transfer_id, offset) => {
+            resume_transfer(stream, db_conn, transfer_id, offset, author, location, output);
+        },
+        Request::CreateGroup(name) => {
+            create_group(stream, db_conn, name, author, output);
+        },
+        Request::InviteToGroup(name, username) => {
+            invite_to_group(stream, db_conn, name, username, author, output);
+        },
+        Request::LeaveGroup(name) => {
+            leave_group(stream, db_conn, name, author, output);
+        },
+        Request::GetGroupMembers(name) => {
+            return_group_members(stream, db_conn, name, author, output);
+        },
+        Request::RemoveFromGroup(name, username) => {
+            remove_from_group(stream, db_conn, name, username, author, location, output);
+        },
+        Request::MuteInGroup(name, username, muted) => {
+            mute_in_group(stream, db_conn, name, username, muted, author, location, output);
+        },
+        Request::SetGroupRole(name, username, role) => {
+            set_group_role(stream, db_conn, name, username, role, author, location, output);
+        },
+        Request::RenameGroup(name, new_name) => {
+            rename_group(stream, db_conn, name, new_name, author, location, output);
+        },
+        Request::DeleteGroupMessage(name, message_id) => {
+            delete_group_message(stream, db_conn, name, message_id, author, location, output);
+        },
+        Request::EditMessage { id, new_content } => {
+            edit_message(stream, db_conn, id, new_content, author, location, config, output);
+        },
+        Request::DeleteMessage { id, scope } => {
+            delete_message_request(stream, db_conn, id, scope, author, location, output);
+        },
+        Request::GetThread(message_id) => {
+            return_thread(stream, db_conn, message_id, author, location, output);
+        },
+        Request::React { id, reaction, add } => {
+            react(stream, db_conn, id, reaction, add, author, output);
+        },
+        Request::AckMessages(messages_ids) => {
+            acknowledge_messages(stream, db_conn, messages_ids, author, output);
+        },
+        Request::AckNotifications(notifications_ids) => {
+            acknowledge_notifications(stream, db_conn, notifications_ids, author, output);
+        },
+        Request::MarkRead(messages_ids) => {
+            mark_read(stream, db_conn, messages_ids, author, output);
+        },
+        Request::SetReadReceipts(enabled) => {
+            set_read_receipts_request(stream, db_conn, enabled, author, output);
+        },
+        Request::SubscribePresence(username) => {
+            subscribe_presence(stream, db_conn, username, author, config, output);
+        },
+        Request::UnsubscribePresence(username) => {
+            unsubscribe_presence(stream, db_conn, username, author, output);
+        },
+        Request::GetPresence => {
+            return_presence(stream, db_conn, author, config, output);
+        },
+        Request::SetStatus(status) => {
+            set_status(stream, db_conn, status, author, output);
+        },
+        Request::GetDevices => {
+            return_devices(stream, db_conn, author, output);
+        },
+        Request::RevokeDevice(device_id) => {
+            revoke_device_request(stream, db_conn, device_id, author, output);
+        },
+        Request::Typing(recipients) => {
+            relay_typing(stream, db_conn, recipients, author, config, typing, output);
+        },
+        Request::GetScheduledMessages => {
+            return_scheduled_messages(stream, db_conn, author, location, output);
+        },
+        Request::CancelScheduledMessage(message_id) => {
+            cancel_scheduled_message(stream, db_conn, message_id, author, location, output);
+        },
+        Request::GetHistory { peer_or_group, before_id, limit } => {
+            return_history(stream, db_conn, peer_or_group, before_id, limit, author, location, output);
+        },
+        Request::Search { query, peer, from, to, limit, offset } => {
+            search_messages(stream, db_conn, query, peer, from, to, limit, offset, author, location, output);
         },
         Request::Unknown => todo!(),
     }
@@ -314,9 +727,12 @@ fn user_register(mut stream: TcpStream,
     }
 }
 
+/// Logs user in from a device, device with id `device_id` is reused if it is an active device of the user,
+/// otherwise a new one is registered, its id is returned together with the user.
 fn user_login(mut stream: TcpStream,
                   db_conn: &mut Connection,
                   user_unchecked: UserUnchecked,
+                  device_id: Option<usize>,
                   _output: Sender<Output>) -> Result<(), ()> {
 
     let UserUnchecked {username, password} = user_unchecked;
@@ -338,10 +754,23 @@ fn user_login(mut stream: TcpStream,
             };
             let correct_password = Password::from_hash(correct_password);
             if correct_password.verify(provided_password) {
-                let user_lite = UserLite::new(id as u32, username);
+                // Device that was not seen for too long is registered again,
+                // if expiry fails now, scheduler revokes it later.
+                let _ = expire_devices(db_conn, device_expiry_datetime());
+                let device_id = match device_id {
+                    Some(device_id) if is_device_active(db_conn, device_id, id) => device_id,
+                    _ => register_device(db_conn, id, &Utc::now().to_rfc3339()),
+                };
+                let mut user_lite = UserLite::new(id as u32, username);
+                user_lite.set_device_id(Some(device_id));
                 let server_reply = ServerReplyRaw::User(user_lite, UserLite::default_user());
                 let message = server_reply.into_message().unwrap();
                 message.send(&mut stream).unwrap();
+
+                // Delivered with waiting messages, so login reply stays the only answer.
+                if let Some(motd) = get_motd(db_conn) {
+                    insert_device_notification(db_conn, id, Some(device_id), &Notification::MessageOfTheDay(motd));
+                }
             } else {
                 let server_reply = ServerReplyRaw::Error(
                     "Incorrect password.".to_string(),
@@ -366,17 +795,1303 @@ fn user_login(mut stream: TcpStream,
 fn return_waiting_messages(mut stream: TcpStream,
                            db_conn: &mut Connection, 
                            author: UserLite,
+                           location: &Path,
+                           config: &ServerConfig,
+                           typing: &TypingRelay,
                            _output: Sender<Output>) {
 
-    let messages = match get_waiting_messages_ids(db_conn, author.id() as usize) {
+    let messages = match get_waiting_messages_ids(db_conn, author.id() as usize, author.device_id()) {
         Ok(messages) => messages,
         Err(_) => Vec::new(),
     };
 
     for message_id in messages {
-        let message = get_message(db_conn, message_id).unwrap();
-        message.send(&mut stream).unwrap();
-        delete_waiting_message(db_conn, author.id() as usize).unwrap();
+        // Message could have expired or been deleted since its id was read.
+        let message = match get_message(db_conn, message_id, location) {
+            Ok(message) => message,
+            Err(_) => continue,
+        };
+        // Messages keep waiting until client acknowledges them, so those lost here are sent again next time.
+        for message in announce_files(message, message_id) {
+            if message.send(&mut stream).is_err() {
+                return;
+            }
+        }
+    }
+
+    // Typing events are sent right away, they are never queued in database.
+    for event in typing.take(author.id() as usize, author.device_id()) {
+        let notification = Notification::Typing {
+            username: event.username(),
+            group: event.group(),
+        };
+        if notification.into_message(&author).unwrap().send(&mut stream).is_err() {
+            return;
+        }
+    }
+
+    queue_presence_changes(db_conn, &author, config);
+
+    // Notifications also keep waiting until client acknowledges them.
+    for (notification_id, notification) in get_notifications(db_conn, author.id() as usize, author.device_id()) {
+        let message = notification.into_queued_message(notification_id, &author).unwrap();
+        if message.send(&mut stream).is_err() {
+            return;
+        }
+    }
+}
+
+/// Records that message with given id was delivered to or read by given user,
+/// if it was not recorded yet its author gets [Notification::Receipt].
+fn record_receipt(db_conn: &mut Connection, message_id: usize, user: &UserLite, kind: ReceiptKind) {
+
+    let datetime = Utc::now().to_rfc3339();
+    if !set_message_receipt(db_conn, message_id, user.id() as usize, kind, &datetime) {
+        return;
+    }
+
+    match get_message_author_id(db_conn, message_id) {
+        Ok(author_id) if author_id != SERVER_ID as usize => {
+            let notification = Notification::Receipt {
+                message_id,
+                username: user.username(),
+                kind,
+                datetime,
+            };
+            insert_notification(db_conn, author_id, &notification);
+        },
+        _ => {},
+    }
+}
+
+/// Stops messages with given ids from waiting for the author's device, its client stored them.
+///
+/// Delivered receipts are recorded only now, as before that message can still get lost.
+fn acknowledge_messages(mut stream: TcpStream,
+                        db_conn: &mut Connection,
+                        messages_ids: Vec<usize>,
+                        author: UserLite,
+                        output: Sender<Output>) {
+
+    for message_id in messages_ids {
+        if delete_waiting_message(db_conn, message_id, author.id() as usize, author.device_id()) {
+            record_receipt(db_conn, message_id, &author, ReceiptKind::Delivered);
+        }
+    }
+
+    send_server_reply(&mut stream, ServerReplyRaw::Success("Messages acknowledged.".to_string(), author), &output);
+}
+
+/// Deletes notifications with given ids that were waiting for device of the author, as it processed them.
+fn acknowledge_notifications(mut stream: TcpStream,
+                             db_conn: &mut Connection,
+                             notifications_ids: Vec<usize>,
+                             author: UserLite,
+                             output: Sender<Output>) {
+
+    for notification_id in notifications_ids {
+        delete_notification(db_conn, notification_id, author.id() as usize, author.device_id());
+    }
+
+    send_server_reply(&mut stream, ServerReplyRaw::Success("Notifications acknowledged.".to_string(), author), &output);
+}
+
+/// Marks messages with given ids that were sent to the author as read,
+/// nothing is recorded if the author turned read receipts off.
+fn mark_read(mut stream: TcpStream,
+             db_conn: &mut Connection,
+             messages_ids: Vec<usize>,
+             author: UserLite,
+             output: Sender<Output>) {
+
+    if read_receipts_enabled(db_conn, author.id() as usize) {
+        for message_id in messages_ids {
+            if is_message_recipient(db_conn, message_id, author.id() as usize) {
+                record_receipt(db_conn, message_id, &author, ReceiptKind::Read);
+            }
+        }
+    }
+
+    send_server_reply(&mut stream, ServerReplyRaw::Success("Messages marked as read.".to_string(), author), &output);
+}
+
+/// Turns sending of read receipts of the author on or off.
+fn set_read_receipts_request(mut stream: TcpStream,
+                             db_conn: &mut Connection,
+                             enabled: bool,
+                             author: UserLite,
+                             output: Sender<Output>) {
+
+    set_read_receipts(db_conn, author.id() as usize, enabled);
+
+    let text = match enabled {
+        true => "Read receipts are turned on.",
+        false => "Read receipts are turned off.",
+    };
+    send_server_reply(&mut stream, ServerReplyRaw::Success(text.to_string(), author), &output);
+}
+
+/// Returns current [PresenceInfo] of user with given id, derived from when it was last seen.
+fn get_presence_info(db_conn: &mut Connection, user_id: usize, username: String, config: &ServerConfig) -> PresenceInfo {
+
+    let last_seen = get_last_seen(db_conn, user_id);
+    let elapsed = last_seen.as_ref()
+                           .and_then(|last_seen| DateTime::parse_from_rfc3339(last_seen).ok())
+                           .map(|last_seen| (Utc::now() - last_seen.with_timezone(&Utc)).num_seconds().max(0) as u64);
+
+    let presence = Presence::from_elapsed(elapsed,
+                                          config.online_timeout.unwrap_or(ONLINE_TIMEOUT),
+                                          config.away_timeout.unwrap_or(AWAY_TIMEOUT));
+
+    PresenceInfo::new(username, presence, last_seen, get_user_status(db_conn, user_id))
+}
+
+/// Queues [Notification::Presence] for every user given user subscribed to whose presence or status changed
+/// since the user was told about it last time.
+fn queue_presence_changes(db_conn: &mut Connection, subscriber: &UserLite, config: &ServerConfig) {
+
+    let subscriber_id = subscriber.id() as usize;
+
+    for (user_id, last_presence) in get_presence_subscriptions(db_conn, subscriber_id) {
+        let username = match get_username_from_id(db_conn, user_id) {
+            Ok(username) => username,
+            Err(_) => continue,
+        };
+        let presence = get_presence_info(db_conn, user_id, username, config);
+
+        // Last seen time changes with every request, only changes of presence itself or status are reported.
+        let changed = match last_presence {
+            Some(last_presence) => last_presence.presence() != presence.presence()
+                                   || last_presence.status() != presence.status(),
+            None => true,
+        };
+        if changed {
+            set_subscription_presence(db_conn, subscriber_id, user_id, &presence);
+            insert_notification(db_conn, subscriber_id, &Notification::Presence(presence));
+        }
+    }
+}
+
+/// Subscribes the author to presence of user with given username and answers with its current presence.
+fn subscribe_presence(mut stream: TcpStream,
+                      db_conn: &mut Connection,
+                      username: String,
+                      author: UserLite,
+                      config: &ServerConfig,
+                      output: Sender<Output>) {
+
+    let user_id = match get_user_id_from_username(db_conn, &username) {
+        Ok(user_id) => user_id,
+        Err(_) => {
+            let server_reply = ServerReplyRaw::Error(format!("User {} does not exist.", username), author);
+            send_server_reply(&mut stream, server_reply, &output);
+            return;
+        },
+    };
+
+    add_presence_subscription(db_conn, author.id() as usize, user_id);
+    let presence = get_presence_info(db_conn, user_id, username, config);
+    set_subscription_presence(db_conn, author.id() as usize, user_id, &presence);
+
+    send_server_reply(&mut stream, ServerReplyRaw::Presence(vec![presence], author), &output);
+}
+
+/// Cancels subscription of the author to presence of user with given username.
+fn unsubscribe_presence(mut stream: TcpStream,
+                        db_conn: &mut Connection,
+                        username: String,
+                        author: UserLite,
+                        output: Sender<Output>) {
+
+    let removed = match get_user_id_from_username(db_conn, &username) {
+        Ok(user_id) => remove_presence_subscription(db_conn, author.id() as usize, user_id),
+        Err(_) => false,
+    };
+
+    let server_reply = match removed {
+        true => ServerReplyRaw::Success(format!("You will not get presence of {} anymore.", username), author),
+        false => ServerReplyRaw::Error(format!("You are not subscribed to {}.", username), author),
+    };
+    send_server_reply(&mut stream, server_reply, &output);
+}
+
+/// Answers with presence of every user the author subscribed to.
+fn return_presence(mut stream: TcpStream,
+                   db_conn: &mut Connection,
+                   author: UserLite,
+                   config: &ServerConfig,
+                   output: Sender<Output>) {
+
+    let subscriber_id = author.id() as usize;
+    let mut presences = Vec::new();
+
+    for (user_id, _) in get_presence_subscriptions(db_conn, subscriber_id) {
+        if let Ok(username) = get_username_from_id(db_conn, user_id) {
+            let presence = get_presence_info(db_conn, user_id, username, config);
+            set_subscription_presence(db_conn, subscriber_id, user_id, &presence);
+            presences.push(presence);
+        }
+    }
+
+    send_server_reply(&mut stream, ServerReplyRaw::Presence(presences, author), &output);
+}
+
+/// Relays a typing event of the author to those of given recipients that are online.
+///
+/// Recipients are usernames or a single group, author has to be a member of the group.
+fn relay_typing(mut stream: TcpStream,
+                db_conn: &mut Connection,
+                recipients: Vec<String>,
+                author: UserLite,
+                config: &ServerConfig,
+                typing: &TypingRelay,
+                output: Sender<Output>) {
+
+    if !typing.try_start(author.id() as usize) {
+        let server_error = ServerError::RateLimited { retry_after: TYPING_INTERVAL.as_secs() };
+        send_server_reply(&mut stream, ServerReplyRaw::Rejected(server_error, author), &output);
+        return;
+    }
+
+    let mut group = None;
+    let mut recipients_ids = Vec::new();
+    for recipient in recipients {
+        match recipient.strip_prefix(GROUP_PREFIX) {
+            Some(name) => {
+                match get_group_id(db_conn, name) {
+                    Ok(group_id) if is_group_member(db_conn, group_id, author.id() as usize) => {
+                        recipients_ids.extend(get_group_members(db_conn, group_id)
+                                                .into_iter()
+                                                .map(|(user_id, member)| (user_id, member.username())));
+                        group = Some(recipient.clone());
+                    },
+                    _ => {
+                        let server_error = ServerError::NotGroupMember { group: recipient };
+                        send_server_reply(&mut stream, ServerReplyRaw::Rejected(server_error, author), &output);
+                        return;
+                    },
+                }
+            },
+            None => {
+                if let Ok(user_id) = get_user_id_from_username(db_conn, &recipient) {
+                    recipients_ids.push((user_id, recipient));
+                }
+            },
+        }
+    }
+
+    for (user_id, username) in recipients_ids {
+        if user_id != author.id() as usize
+        && get_presence_info(db_conn, user_id, username, config).presence() == Presence::Online {
+            for device_id in get_delivery_devices_ids(db_conn, user_id) {
+                typing.relay(user_id, device_id, author.username(), group.clone());
+            }
+        }
+    }
+
+    send_server_reply(&mut stream, ServerReplyRaw::Success("Typing.".to_string(), author), &output);
+}
+
+/// Answers with every scheduled message of the author that was not sent yet.
+fn return_scheduled_messages(mut stream: TcpStream,
+                             db_conn: &mut Connection,
+                             author: UserLite,
+                             location: &Path,
+                             output: Sender<Output>) {
+
+    let messages_ids = get_scheduled_messages_ids(db_conn, author.id() as usize);
+
+    if let Err(e) = send_messages(&mut stream, db_conn, messages_ids, author, location) {
+        output.send(Output::Error(format!("Failed to send scheduled messages.\n{}", e))).unwrap();
     }
 }
 
+/// Deletes scheduled message with given id before it is sent, only its author can do that.
+fn cancel_scheduled_message(mut stream: TcpStream,
+                            db_conn: &mut Connection,
+                            message_id: usize,
+                            author: UserLite,
+                            location: &Path,
+                            output: Sender<Output>) {
+
+    let server_reply = match delete_scheduled_message(db_conn, message_id, author.id() as usize, location) {
+        Ok(_) => ServerReplyRaw::Success(format!("Scheduled message {} was cancelled.", message_id), author),
+        Err(_) => ServerReplyRaw::Error(format!("You do not have scheduled message {}.", message_id), author),
+    };
+    send_server_reply(&mut stream, server_reply, &output);
+}
+
+/// Returns id of a user with given username, or of a group if it starts with [GROUP_PREFIX], the other one is [None].
+fn get_peer_ids(db_conn: &mut Connection, peer: &str) -> Result<(Option<usize>, Option<usize>), ()> {
+    match peer.strip_prefix(GROUP_PREFIX) {
+        Some(name) => get_group_id(db_conn, name).map(|group_id| (None, Some(group_id))),
+        None => get_user_id_from_username(db_conn, peer).map(|user_id| (Some(user_id), None)),
+    }
+}
+
+/// Answers with a page of messages of the author's conversation with a user or a group, so a new device
+/// can fill its history, those are the newest messages older than message with id `before_id`, oldest first.
+fn return_history(mut stream: TcpStream,
+                  db_conn: &mut Connection,
+                  peer_or_group: String,
+                  before_id: Option<usize>,
+                  limit: Option<usize>,
+                  author: UserLite,
+                  location: &Path,
+                  output: Sender<Output>) {
+
+    let (peer_id, group_id) = match get_peer_ids(db_conn, &peer_or_group) {
+        Ok(ids) => ids,
+        Err(_) => {
+            let server_reply = ServerReplyRaw::Error(format!("{} does not exist.", peer_or_group), author);
+            send_server_reply(&mut stream, server_reply, &output);
+            return;
+        },
+    };
+
+    let limit = limit.unwrap_or(DEFAULT_HISTORY_PAGE).min(MAX_HISTORY_PAGE);
+
+    let messages_ids = get_history_ids(db_conn, author.id() as usize, peer_id, group_id, before_id, limit);
+
+    if let Err(e) = send_messages(&mut stream, db_conn, messages_ids, author, location) {
+        output.send(Output::Error(format!("Failed to send history.\n{}", e))).unwrap();
+    }
+}
+
+/// Answers with messages the author sent or received whose text contains every word of the query, newest first.
+///
+/// Each word is searched for as a whole, so FTS5 query syntax inside the query has no special meaning.
+fn search_messages(mut stream: TcpStream,
+                   db_conn: &mut Connection,
+                   query: String,
+                   peer: Option<String>,
+                   from: Option<String>,
+                   to: Option<String>,
+                   limit: Option<usize>,
+                   offset: usize,
+                   author: UserLite,
+                   location: &Path,
+                   output: Sender<Output>) {
+
+    let fts_query = query.split_whitespace()
+                         .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+                         .collect::<Vec<String>>()
+                         .join(" ");
+    if fts_query.is_empty() {
+        let server_reply = ServerReplyRaw::Error("Search query can not be empty.".to_string(), author);
+        send_server_reply(&mut stream, server_reply, &output);
+        return;
+    }
+
+    let (peer_id, group_id) = match &peer {
+        Some(peer) => match get_peer_ids(db_conn, peer) {
+            Ok(ids) => ids,
+            Err(_) => {
+                let server_reply = ServerReplyRaw::Error(format!("{} does not exist.", peer), author);
+                send_server_reply(&mut stream, server_reply, &output);
+                return;
+            },
+        },
+        None => (None, None),
+    };
+
+    // Datetimes are compared as text, so they have to be in the same form as those stored with messages.
+    let normalize = |datetime: Option<String>| match datetime {
+        Some(datetime) => match DateTime::parse_from_rfc3339(&datetime) {
+            Ok(parsed) => Ok(Some(parsed.with_timezone(&Utc).to_rfc3339())),
+            Err(_) => Err(datetime),
+        },
+        None => Ok(None),
+    };
+    let (from, to) = match (normalize(from), normalize(to)) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(datetime), _) | (_, Err(datetime)) => {
+            let server_reply = ServerReplyRaw::Error(format!("{} is not a valid RFC 3339 datetime.", datetime), author);
+            send_server_reply(&mut stream, server_reply, &output);
+            return;
+        },
+    };
+
+    let limit = limit.unwrap_or(DEFAULT_SEARCH_RESULTS).min(MAX_SEARCH_RESULTS);
+
+    let messages_ids = search_messages_ids(db_conn, author.id() as usize, &fts_query,
+                                           peer_id, group_id, from, to, limit, offset);
+
+    if let Err(e) = send_messages(&mut stream, db_conn, messages_ids, author, location) {
+        output.send(Output::Error(format!("Failed to send search results.\n{}", e))).unwrap();
+    }
+}
+
+/// Answers with every device the author is logged in from.
+fn return_devices(mut stream: TcpStream,
+                  db_conn: &mut Connection,
+                  author: UserLite,
+                  output: Sender<Output>) {
+
+    let devices = get_devices(db_conn, author.id() as usize, author.device_id());
+
+    send_server_reply(&mut stream, ServerReplyRaw::Devices(devices, author), &output);
+}
+
+/// Revokes device of the author with given id, the device stops receiving messages and has to log in again.
+fn revoke_device_request(mut stream: TcpStream,
+                         db_conn: &mut Connection,
+                         device_id: usize,
+                         author: UserLite,
+                         output: Sender<Output>) {
+
+    let server_reply = if revoke_device(db_conn, device_id, author.id() as usize) {
+        ServerReplyRaw::Success(format!("Device {} was revoked.", device_id), author)
+    } else {
+        ServerReplyRaw::Error(format!("You do not have device {}.", device_id), author)
+    };
+    send_server_reply(&mut stream, server_reply, &output);
+}
+
+/// Sets status message of the author, its subscribers are told about it when they ask for waiting messages.
+fn set_status(mut stream: TcpStream,
+              db_conn: &mut Connection,
+              status: Option<String>,
+              author: UserLite,
+              output: Sender<Output>) {
+
+    let status = status.map(|status| status.trim().to_string())
+                       .filter(|status| !status.is_empty());
+
+    if let Some(status) = &status {
+        if status.chars().count() > MAX_STATUS_LENGTH {
+            let server_reply = ServerReplyRaw::Error(
+                format!("Status can have at most {} characters.", MAX_STATUS_LENGTH),
+                author,
+            );
+            send_server_reply(&mut stream, server_reply, &output);
+            return;
+        }
+    }
+
+    let text = match &status {
+        Some(status) => format!("Your status is \"{}\".", status),
+        None => "Your status was cleared.".to_string(),
+    };
+    set_user_status(db_conn, author.id() as usize, status);
+
+    send_server_reply(&mut stream, ServerReplyRaw::Success(text, author), &output);
+}
+
+/// Answers how many bytes of given transfer are already stored in staging directory,
+/// so client can continue sending the file from there.
+fn return_transfer_offset(mut stream: TcpStream,
+                          transfer_id: String,
+                          author: UserLite,
+                          location: &Path,
+                          output: Sender<Output>) {
+
+    let offset = transfer::staged_bytes(location, &transfer_id);
+
+    let server_reply = ServerReplyRaw::TransferOffset(transfer_id, offset, author);
+    let message = server_reply.into_message().unwrap();
+    if let Err(e) = message.send(&mut stream) {
+        output.send(Output::Error(format!("Failed to send transfer offset.\n{}", e))).unwrap();
+    }
+}
+
+/// Sends file of given transfer again to one of its recipients, starting from `offset`.
+fn resume_transfer(mut stream: TcpStream,
+                   db_conn: &mut Connection,
+                   transfer_id: String,
+                   offset: u64,
+                   author: UserLite,
+                   location: &Path,
+                   output: Sender<Output>) {
+
+    let message_id = match get_message_id_by_transfer(db_conn, &transfer_id, author.id() as usize) {
+        Ok(message_id) => message_id,
+        Err(_) => {
+            let server_reply = ServerReplyRaw::Error(
+                format!("Transfer {} does not exist.", transfer_id),
+                author,
+            );
+            let message = server_reply.into_message().unwrap();
+            message.send(&mut stream).unwrap();
+            return;
+        },
+    };
+
+    let mut message = get_message(db_conn, message_id, location).unwrap();
+    let mut metadata = message.metadata();
+    if let Some(mut file_transfer) = metadata.transfer() {
+        file_transfer.set_offset(offset);
+        metadata.set_transfer(Some(file_transfer));
+    }
+    message.set_metadata(metadata);
+
+    if let Err(e) = message.send(&mut stream) {
+        output.send(Output::Error(format!("Failed to resume transfer {}.\n{}", transfer_id, e))).unwrap();
+    }
+}
+
+/// Returns messages that are sent instead of given `message` stored with given id,
+/// files inside it are only announced, recipient downloads them with [Request::DownloadFile]
+/// or [Request::DownloadAttachment] if it wants to.
+///
+/// Message with a file is replaced by its [FileReference], message with attachments is sent without them,
+/// followed by [FileReference] to each of them.
+fn announce_files(message: ImplementedMessage, message_id: usize) -> Vec<ImplementedMessage> {
+
+    let metadata = message.metadata();
+
+    if let MessageKind::File = metadata.message_kind() {
+        let file_transfer = metadata.transfer().unwrap();
+        let file_reference = FileReference::new(message_id,
+                                                metadata.file_name().unwrap(),
+                                                file_transfer.size(),
+                                                file_transfer.hash());
+        return vec![file_reference_message(metadata, file_reference)];
+    }
+
+    let attachments = metadata.attachments();
+    if attachments.is_empty() {
+        return vec![message];
+    }
+
+    let mut messages = Vec::new();
+    let mut message = message;
+    let mut text_metadata = metadata.clone();
+    text_metadata.set_attachments(Vec::new());
+    message.set_metadata(text_metadata);
+    messages.push(message);
+
+    for (position, attachment) in attachments.into_iter().enumerate() {
+        let file_reference = FileReference::for_attachment(message_id,
+                                                           position,
+                                                           attachment.name(),
+                                                           attachment.size(),
+                                                           attachment.digest());
+        messages.push(file_reference_message(metadata.clone(), file_reference));
+    }
+
+    messages
+}
+
+/// Creates a [Message](ImplementedMessage) with given [FileReference] from `metadata` of the message holding the file.
+fn file_reference_message(mut metadata: MetaData, file_reference: FileReference) -> ImplementedMessage {
+
+    let content = Content::ron(file_reference.to_ron().unwrap());
+    let content_buff = content.into_bytes();
+
+    metadata.set_message_kind(MessageKind::FileReference);
+    metadata.set_file_name(None);
+    metadata.set_transfer(None);
+    metadata.set_attachments(Vec::new());
+    let metadata = metadata.with_content_length(content_buff.len());
+
+    let mut message = ImplementedMessage::new();
+    message.set_metadata(metadata);
+    message.set_content(Content::from_bytes(content_buff).unwrap());
+    message.set_end_data(Packet::new(PacketKind::End, Bytes::new()));
+
+    message
+}
+
+/// Sends a file from message with given id to one of its recipients.
+fn download_file(mut stream: TcpStream,
+                 db_conn: &mut Connection,
+                 message_id: usize,
+                 author: UserLite,
+                 location: &Path,
+                 output: Sender<Output>) {
+
+    if !is_message_recipient(db_conn, message_id, author.id() as usize) {
+        let server_reply = ServerReplyRaw::Error(
+            format!("File from message {} does not exist.", message_id),
+            author,
+        );
+        let message = server_reply.into_message().unwrap();
+        message.send(&mut stream).unwrap();
+        return;
+    }
+
+    let message = get_message(db_conn, message_id, location).unwrap();
+    if let Err(e) = message.send(&mut stream) {
+        output.send(Output::Error(format!("Failed to send a file from message {}.\n{}", message_id, e))).unwrap();
+    }
+}
+
+/// Sends message with given id to one of its recipients only with its attachment at given position.
+fn download_attachment(mut stream: TcpStream,
+                       db_conn: &mut Connection,
+                       message_id: usize,
+                       position: usize,
+                       author: UserLite,
+                       location: &Path,
+                       output: Sender<Output>) {
+
+    let mut message = match is_message_recipient(db_conn, message_id, author.id() as usize) {
+        true => get_message(db_conn, message_id, location).unwrap(),
+        false => {
+            let server_reply = ServerReplyRaw::Error(format!("Message {} does not exist.", message_id), author);
+            send_server_reply(&mut stream, server_reply, &output);
+            return;
+        },
+    };
+
+    let mut metadata = message.metadata();
+    let attachment = match metadata.attachments().into_iter().nth(position) {
+        Some(attachment) => attachment,
+        None => {
+            let server_reply = ServerReplyRaw::Error(
+                format!("Message {} has no attachment {}.", message_id, position),
+                author,
+            );
+            send_server_reply(&mut stream, server_reply, &output);
+            return;
+        },
+    };
+    metadata.set_attachments(vec![attachment]);
+    message.set_metadata(metadata);
+
+    if let Err(e) = message.send(&mut stream) {
+        output.send(Output::Error(format!("Failed to send an attachment from message {}.\n{}", message_id, e))).unwrap();
+    }
+}
+
+/// Answers how many bytes are stored on server for given user and what is its quota.
+fn return_storage_usage(mut stream: TcpStream,
+                        db_conn: &mut Connection,
+                        author: UserLite,
+                        location: &Path,
+                        config: &ServerConfig,
+                        output: Sender<Output>) {
+
+    let usage = get_user_storage_usage(db_conn, author.id() as usize, location);
+
+    let server_reply = ServerReplyRaw::StorageUsage(usage, config.user_storage_quota, author);
+    let message = server_reply.into_message().unwrap();
+    if let Err(e) = message.send(&mut stream) {
+        output.send(Output::Error(format!("Failed to send storage usage.\n{}", e))).unwrap();
+    }
+}
+
+/// Sends given `server_reply` to `stream`, failure is only reported to `output`.
+fn send_server_reply(stream: &mut TcpStream, server_reply: ServerReplyRaw, output: &Sender<Output>) {
+
+    let message = server_reply.into_message().unwrap();
+    if let Err(e) = message.send(stream) {
+        output.send(Output::Error(format!("Failed to send a server reply.\n{}", e))).unwrap();
+    }
+}
+
+/// Returns id of a group with given name and [GroupRole] of given user inside it if the user is its member,
+/// otherwise an error that can be sent back.
+fn get_member_group(db_conn: &mut Connection, name: &str, user: &UserLite) -> Result<(usize, GroupRole), String> {
+
+    if let Ok(group_id) = get_group_id(db_conn, name) {
+        if let Some(role) = get_group_member_role(db_conn, group_id, user.id() as usize) {
+            return Ok((group_id, role));
+        }
+    }
+
+    Err(format!("You are not a member of group {}{}.", GROUP_PREFIX, name))
+}
+
+/// Returns id of a group with given name and id of its member with given username if `moderator` can moderate
+/// that member, otherwise an error that can be sent back.
+fn get_moderated_member(db_conn: &mut Connection,
+                        name: &str,
+                        username: &str,
+                        moderator: &UserLite) -> Result<(usize, usize), String> {
+
+    let (group_id, role) = get_member_group(db_conn, name, moderator)?;
+    if !role.can_moderate() {
+        return Err(format!("Only moderators of group {}{} can do that.", GROUP_PREFIX, name));
+    }
+
+    let user_id = match get_user_id_from_username(db_conn, username) {
+        Ok(user_id) => user_id,
+        Err(_) => return Err(format!("User with username: {} does not exist", username)),
+    };
+
+    match get_group_member_role(db_conn, group_id, user_id) {
+        Some(member_role) if role.outranks(member_role) => Ok((group_id, user_id)),
+        Some(member_role) => Err(format!("You can not moderate {} of group {}{}.", member_role, GROUP_PREFIX, name)),
+        None => Err(format!("{} is not a member of group {}{}.", username, GROUP_PREFIX, name)),
+    }
+}
+
+/// Checks if given name can be used as a name of a group.
+fn check_group_name(name: &str) -> Result<(), String> {
+
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err("Name of a group can not be empty or contain whitespaces.".to_string());
+    }
+
+    Ok(())
+}
+
+/// Stores a message from server to group with given name, so every member can see what happened inside the group.
+fn send_group_notice(db_conn: &mut Connection, name: &str, notice: String, location: &Path) {
+
+    let content = Content::text(notice).into_bytes();
+    let recipients = vec![format!("{}{}", GROUP_PREFIX, name)];
+
+    let metadata = MetaData::new(&content, MessageKind::Text,
+                                 UserLite::default_server(),
+                                 SERVER_ID, recipients, None).unwrap();
+
+    let mut message = ImplementedMessage::new();
+    message.set_metadata(metadata);
+    message.set_content(Content::from_bytes(content).unwrap());
+    message.set_end_data(Packet::new(PacketKind::End, Bytes::new()));
+
+    insert_message_into_database(message, db_conn, location).unwrap();
+}
+
+/// Creates a new group with requesting user as its only member.
+fn create_group(mut stream: TcpStream,
+                db_conn: &mut Connection,
+                name: String,
+                author: UserLite,
+                output: Sender<Output>) {
+
+    let name = name.trim_start_matches(GROUP_PREFIX).to_string();
+
+    let server_reply = match check_group_name(&name) {
+        Ok(_) => match sql::create_group(db_conn, &name, author.id() as usize) {
+            Ok(_) => ServerReplyRaw::Success(format!("Created group {}{}.", GROUP_PREFIX, name), author),
+            Err(_) => ServerReplyRaw::Error(format!("Group {}{} already exists.", GROUP_PREFIX, name), author),
+        },
+        Err(error) => ServerReplyRaw::Error(error, author),
+    };
+
+    send_server_reply(&mut stream, server_reply, &output);
+}
+
+/// Adds a user to a group, every message that was already sent to that group is queued for it,
+/// so it can see the whole conversation.
+fn invite_to_group(mut stream: TcpStream,
+                   db_conn: &mut Connection,
+                   name: String,
+                   username: String,
+                   author: UserLite,
+                   output: Sender<Output>) {
+
+    let name = name.trim_start_matches(GROUP_PREFIX).to_string();
+
+    let group_id = match get_member_group(db_conn, &name, &author) {
+        Ok((group_id, _)) => group_id,
+        Err(error) => {
+            send_server_reply(&mut stream, ServerReplyRaw::Error(error, author), &output);
+            return;
+        },
+    };
+
+    let user_id = match get_user_id_from_username(db_conn, &username) {
+        Ok(user_id) => user_id,
+        Err(_) => {
+            let server_reply = ServerReplyRaw::Error(
+                format!("User with username: {} does not exist", username),
+                author,
+            );
+            send_server_reply(&mut stream, server_reply, &output);
+            return;
+        },
+    };
+
+    add_group_member(db_conn, group_id, user_id, GroupRole::Member);
+    for message_id in get_missing_group_messages_ids(db_conn, group_id, user_id) {
+        add_message_recipient(db_conn, message_id.to_sql().unwrap(), user_id);
+    }
+
+    let server_reply = ServerReplyRaw::Success(
+        format!("Added {} to group {}{}.", username, GROUP_PREFIX, name),
+        author,
+    );
+    send_server_reply(&mut stream, server_reply, &output);
+}
+
+/// Removes requesting user from a group.
+fn leave_group(mut stream: TcpStream,
+               db_conn: &mut Connection,
+               name: String,
+               author: UserLite,
+               output: Sender<Output>) {
+
+    let name = name.trim_start_matches(GROUP_PREFIX).to_string();
+
+    let server_reply = match get_member_group(db_conn, &name, &author) {
+        Ok((group_id, _)) => {
+            remove_group_member(db_conn, group_id, author.id() as usize).unwrap();
+            ServerReplyRaw::Success(format!("Left group {}{}.", GROUP_PREFIX, name), author)
+        },
+        Err(error) => ServerReplyRaw::Error(error, author),
+    };
+
+    send_server_reply(&mut stream, server_reply, &output);
+}
+
+/// Answers usernames of all members of a group, only its members can ask for them.
+fn return_group_members(mut stream: TcpStream,
+                        db_conn: &mut Connection,
+                        name: String,
+                        author: UserLite,
+                        output: Sender<Output>) {
+
+    let name = name.trim_start_matches(GROUP_PREFIX).to_string();
+
+    let server_reply = match get_member_group(db_conn, &name, &author) {
+        Ok((group_id, _)) => {
+            let members = get_group_members(db_conn, group_id)
+                            .into_iter()
+                            .map(|(_, member)| member)
+                            .collect();
+            ServerReplyRaw::GroupMembers(format!("{}{}", GROUP_PREFIX, name), members, author)
+        },
+        Err(error) => ServerReplyRaw::Error(error, author),
+    };
+
+    send_server_reply(&mut stream, server_reply, &output);
+}
+
+/// Removes a member from a group, requesting user needs to be a moderator that outranks that member.
+fn remove_from_group(mut stream: TcpStream,
+                     db_conn: &mut Connection,
+                     name: String,
+                     username: String,
+                     author: UserLite,
+                     location: &Path,
+                     output: Sender<Output>) {
+
+    let name = name.trim_start_matches(GROUP_PREFIX).to_string();
+
+    let server_reply = match get_moderated_member(db_conn, &name, &username, &author) {
+        Ok((group_id, user_id)) => {
+            // Notice is sent before removing, so the removed member also knows what happened.
+            send_group_notice(db_conn, &name,
+                              format!("{} removed {} from the group.", author.username(), username),
+                              location);
+            remove_group_member(db_conn, group_id, user_id).unwrap();
+            ServerReplyRaw::Success(format!("Removed {} from group {}{}.", username, GROUP_PREFIX, name), author)
+        },
+        Err(error) => ServerReplyRaw::Error(error, author),
+    };
+
+    send_server_reply(&mut stream, server_reply, &output);
+}
+
+/// Mutes or unmutes a member of a group, requesting user needs to be a moderator that outranks that member.
+fn mute_in_group(mut stream: TcpStream,
+                 db_conn: &mut Connection,
+                 name: String,
+                 username: String,
+                 muted: bool,
+                 author: UserLite,
+                 location: &Path,
+                 output: Sender<Output>) {
+
+    let name = name.trim_start_matches(GROUP_PREFIX).to_string();
+    let action = if muted { "muted" } else { "unmuted" };
+
+    let server_reply = match get_moderated_member(db_conn, &name, &username, &author) {
+        Ok((group_id, user_id)) => {
+            set_group_member_muted(db_conn, group_id, user_id, muted);
+            send_group_notice(db_conn, &name, format!("{} {} {}.", author.username(), action, username), location);
+            ServerReplyRaw::Success(format!("{} {} in group {}{}.", username, action, GROUP_PREFIX, name), author)
+        },
+        Err(error) => ServerReplyRaw::Error(error, author),
+    };
+
+    send_server_reply(&mut stream, server_reply, &output);
+}
+
+/// Sets [GroupRole] of a member of a group, only the owner can do that.
+///
+/// If the owner gives its role to someone else, it becomes a moderator.
+fn set_group_role(mut stream: TcpStream,
+                  db_conn: &mut Connection,
+                  name: String,
+                  username: String,
+                  role: GroupRole,
+                  author: UserLite,
+                  location: &Path,
+                  output: Sender<Output>) {
+
+    let name = name.trim_start_matches(GROUP_PREFIX).to_string();
+
+    let server_reply = match get_moderated_member(db_conn, &name, &username, &author) {
+        Ok((group_id, user_id)) => {
+            if get_group_member_role(db_conn, group_id, author.id() as usize) != Some(GroupRole::Owner) {
+                ServerReplyRaw::Error(format!("Only owner of group {}{} can change roles.", GROUP_PREFIX, name), author)
+            } else {
+                set_group_member_role(db_conn, group_id, user_id, role);
+                if role == GroupRole::Owner {
+                    set_group_member_role(db_conn, group_id, author.id() as usize, GroupRole::Moderator);
+                }
+                send_group_notice(db_conn, &name,
+                                  format!("{} made {} {} of the group.", author.username(), username, role),
+                                  location);
+                ServerReplyRaw::Success(format!("{} is now {} of group {}{}.", username, role, GROUP_PREFIX, name), author)
+            }
+        },
+        Err(error) => ServerReplyRaw::Error(error, author),
+    };
+
+    send_server_reply(&mut stream, server_reply, &output);
+}
+
+/// Renames a group, requesting user needs to be its moderator.
+fn rename_group(mut stream: TcpStream,
+                db_conn: &mut Connection,
+                name: String,
+                new_name: String,
+                author: UserLite,
+                location: &Path,
+                output: Sender<Output>) {
+
+    let name = name.trim_start_matches(GROUP_PREFIX).to_string();
+    let new_name = new_name.trim_start_matches(GROUP_PREFIX).to_string();
+
+    let checked = get_member_group(db_conn, &name, &author)
+                    .and_then(|(group_id, role)| match role.can_moderate() {
+                        true => Ok(group_id),
+                        false => Err(format!("Only moderators of group {}{} can do that.", GROUP_PREFIX, name)),
+                    })
+                    .and_then(|group_id| check_group_name(&new_name).map(|_| group_id));
+
+    let server_reply = match checked {
+        Ok(group_id) => match sql::rename_group(db_conn, group_id, &new_name) {
+            Ok(_) => {
+                send_group_notice(db_conn, &new_name,
+                                  format!("{} renamed the group from {}{} to {}{}.",
+                                          author.username(), GROUP_PREFIX, name, GROUP_PREFIX, new_name),
+                                  location);
+                ServerReplyRaw::Success(format!("Renamed group {}{} to {}{}.", GROUP_PREFIX, name, GROUP_PREFIX, new_name), author)
+            },
+            Err(_) => ServerReplyRaw::Error(format!("Group {}{} already exists.", GROUP_PREFIX, new_name), author),
+        },
+        Err(error) => ServerReplyRaw::Error(error, author),
+    };
+
+    send_server_reply(&mut stream, server_reply, &output);
+}
+
+/// Deletes a message sent to a group, messages of other members can only be deleted by a moderator
+/// that outranks their author.
+fn delete_group_message(mut stream: TcpStream,
+                        db_conn: &mut Connection,
+                        name: String,
+                        message_id: usize,
+                        author: UserLite,
+                        location: &Path,
+                        output: Sender<Output>) {
+
+    let name = name.trim_start_matches(GROUP_PREFIX).to_string();
+
+    let (group_id, role) = match get_member_group(db_conn, &name, &author) {
+        Ok(group) => group,
+        Err(error) => {
+            send_server_reply(&mut stream, ServerReplyRaw::Error(error, author), &output);
+            return;
+        },
+    };
+
+    let message = match get_message(db_conn, message_id, location) {
+        Ok(message) if get_message_group_id(db_conn, message_id) == Some(group_id) => message,
+        _ => {
+            let server_reply = ServerReplyRaw::Error(
+                format!("Message {} was not sent to group {}{}.", message_id, GROUP_PREFIX, name),
+                author,
+            );
+            send_server_reply(&mut stream, server_reply, &output);
+            return;
+        },
+    };
+    let message_author = UserLite::new(message.metadata().author_id(), message.metadata().author_username());
+
+    let is_author = message_author.id() == author.id();
+    let can_delete = is_author || can_moderate_message(db_conn, group_id, role, &message_author);
+
+    let server_reply = if can_delete {
+        delete_for_everyone(db_conn, message_id, location);
+        if !is_author {
+            send_group_notice(db_conn, &name,
+                              format!("{} deleted a message from {}.", author.username(), message_author.username()),
+                              location);
+        }
+        ServerReplyRaw::Success(format!("Deleted message {}.", message_id), author)
+    } else {
+        ServerReplyRaw::Error(format!("You can not delete message {}.", message_id), author)
+    };
+
+    send_server_reply(&mut stream, server_reply, &output);
+}
+
+/// Replaces content of a text message by the new one, only its author can do that.
+///
+/// Every recipient gets [Notification::Edited], so it can update its copy of the message.
+fn edit_message(mut stream: TcpStream,
+                db_conn: &mut Connection,
+                message_id: usize,
+                new_content: Content,
+                author: UserLite,
+                location: &Path,
+                config: &ServerConfig,
+                output: Sender<Output>) {
+
+    let message = match get_message(db_conn, message_id, location) {
+        Ok(message) if message.metadata().author_id() == author.id() => message,
+        _ => {
+            let server_reply = ServerReplyRaw::Error(
+                format!("You did not send message {}.", message_id),
+                author,
+            );
+            send_server_reply(&mut stream, server_reply, &output);
+            return;
+        },
+    };
+
+    if let MessageKind::File = message.metadata().message_kind() {
+        let server_reply = ServerReplyRaw::Error("Files can not be edited.".to_string(), author);
+        send_server_reply(&mut stream, server_reply, &output);
+        return;
+    }
+
+    // New content is limited the same way as content of a new message.
+    let size = new_content.data().len() as u64;
+    if let Err(server_error) = check_size(size, 0, author.id() as usize, db_conn, config, location) {
+        send_server_reply(&mut stream, ServerReplyRaw::Rejected(server_error, author), &output);
+        return;
+    }
+
+    let datetime = Utc::now().to_rfc3339();
+    edit_message_content(db_conn, message_id, new_content.clone(), &datetime);
+
+    let notification = Notification::Edited {
+        message_id,
+        content: new_content,
+        datetime,
+    };
+    for recipient_id in get_message_recipients_ids(db_conn, message_id).unwrap_or_default() {
+        insert_notification(db_conn, recipient_id, &notification);
+    }
+
+    let server_reply = ServerReplyRaw::Success(format!("Edited message {}.", message_id), author);
+    send_server_reply(&mut stream, server_reply, &output);
+}
+
+/// Adds or removes a reaction of the author to a message with given id that the author can see.
+///
+/// Author and every recipient of the message, except the reacting user, get [Notification::Reaction].
+fn react(mut stream: TcpStream,
+         db_conn: &mut Connection,
+         message_id: usize,
+         reaction: String,
+         add: bool,
+         author: UserLite,
+         output: Sender<Output>) {
+
+    let reaction = reaction.trim().to_string();
+    if reaction.is_empty()
+    || reaction.chars().count() > MAX_REACTION_LENGTH
+    || reaction.chars().any(char::is_whitespace) {
+        let server_reply = ServerReplyRaw::Error(
+            format!("Reaction has to be a single emoji or a code of at most {} characters.", MAX_REACTION_LENGTH),
+            author,
+        );
+        send_server_reply(&mut stream, server_reply, &output);
+        return;
+    }
+
+    let user_id = author.id() as usize;
+    if !is_message_visible(db_conn, message_id, user_id) {
+        let server_reply = ServerReplyRaw::Error(format!("Message {} does not exist.", message_id), author);
+        send_server_reply(&mut stream, server_reply, &output);
+        return;
+    }
+
+    let changed = match add {
+        true => add_reaction(db_conn, message_id, user_id, &reaction),
+        false => remove_reaction(db_conn, message_id, user_id, &reaction),
+    };
+    if !changed {
+        let text = match add {
+            true => format!("You already reacted with {} to message {}.", reaction, message_id),
+            false => format!("You did not react with {} to message {}.", reaction, message_id),
+        };
+        send_server_reply(&mut stream, ServerReplyRaw::Error(text, author), &output);
+        return;
+    }
+
+    let reactions = get_message_reactions(db_conn, message_id);
+    let notification = Notification::Reaction {
+        message_id,
+        username: author.username(),
+        reaction: reaction.clone(),
+        added: add,
+        reactions: reactions.clone(),
+    };
+
+    let mut users_ids = get_message_recipients_ids(db_conn, message_id).unwrap_or_default();
+    if let Ok(author_id) = get_message_author_id(db_conn, message_id) {
+        users_ids.push(author_id);
+    }
+    for user_id in users_ids {
+        // Users that still wait for the message get its reactions with it.
+        if user_id != author.id() as usize
+        && user_id != SERVER_ID as usize
+        && !is_message_waiting(db_conn, message_id, user_id) {
+            insert_notification(db_conn, user_id, &notification);
+        }
+    }
+
+    let reactions: Vec<String> = reactions.iter()
+                                          .map(|(reaction, count)| format!("{} {}", reaction, count))
+                                          .collect();
+    let server_reply = ServerReplyRaw::Success(
+        format!("Reactions to message {}: {}", message_id, if reactions.is_empty() { "none".to_string() } else { reactions.join(", ") }),
+        author,
+    );
+    send_server_reply(&mut stream, server_reply, &output);
+}
+
+/// Returns `true` if member of group with given id with given [GroupRole] can delete messages of `message_author`.
+fn can_moderate_message(db_conn: &mut Connection, group_id: usize, role: GroupRole, message_author: &UserLite) -> bool {
+
+    // Notices from server are not moderated, former members can not be outranked,
+    // but their messages can still be moderated.
+    if message_author.id() == SERVER_ID {
+        return false;
+    }
+
+    role.can_moderate() && match get_group_member_role(db_conn, group_id, message_author.id() as usize) {
+        Some(author_role) => role.outranks(author_role),
+        None => true,
+    }
+}
+
+/// Deletes message with given id from server, its recipients that already have it get [Notification::Deleted].
+fn delete_for_everyone(db_conn: &mut Connection, message_id: usize, location: &Path) {
+
+    let notification = Notification::Deleted { message_id };
+    for recipient_id in get_message_recipients_ids(db_conn, message_id).unwrap_or_default() {
+        if !is_message_waiting(db_conn, message_id, recipient_id) {
+            insert_notification(db_conn, recipient_id, &notification);
+        }
+    }
+
+    delete_message(db_conn, message_id, location).unwrap();
+}
+
+/// Deletes a message for requesting user only or for everyone.
+///
+/// Message can be deleted for everyone by its author or by a moderator of group it was sent to.
+fn delete_message_request(mut stream: TcpStream,
+                          db_conn: &mut Connection,
+                          message_id: usize,
+                          scope: DeleteScope,
+                          author: UserLite,
+                          location: &Path,
+                          output: Sender<Output>) {
+
+    let user_id = author.id() as usize;
+
+    let message = match get_message(db_conn, message_id, location) {
+        Ok(message) if message.metadata().author_id() == author.id()
+                    || is_message_recipient(db_conn, message_id, user_id) => message,
+        _ => {
+            let server_reply = ServerReplyRaw::Error(
+                format!("Message {} does not exist.", message_id),
+                author,
+            );
+            send_server_reply(&mut stream, server_reply, &output);
+            return;
+        },
+    };
+    let message_author = UserLite::new(message.metadata().author_id(), message.metadata().author_username());
+    let is_author = message_author.id() == author.id();
+
+    let server_reply = match scope {
+        DeleteScope::ForMe => {
+            // Recipient that already has the message needs to hide its own copy as well.
+            if !is_author && !is_message_waiting(db_conn, message_id, user_id) {
+                insert_notification(db_conn, user_id, &Notification::Deleted { message_id });
+            }
+            hide_message(db_conn, message_id, user_id);
+            ServerReplyRaw::Success(format!("Deleted message {} for you.", message_id), author)
+        },
+        DeleteScope::ForEveryone => {
+            let group = match get_message_group_id(db_conn, message_id) {
+                Some(group_id) => get_group_member_role(db_conn, group_id, user_id)
+                                    .map(|role| (group_id, role)),
+                None => None,
+            };
+            let can_delete = is_author || match group {
+                Some((group_id, role)) => can_moderate_message(db_conn, group_id, role, &message_author),
+                None => false,
+            };
+
+            if can_delete {
+                delete_for_everyone(db_conn, message_id, location);
+                if let (false, Some(name)) = (is_author, message.metadata().group()) {
+                    send_group_notice(db_conn, name.trim_start_matches(GROUP_PREFIX),
+                                      format!("{} deleted a message from {}.", author.username(), message_author.username()),
+                                      location);
+                }
+                ServerReplyRaw::Success(format!("Deleted message {} for everyone.", message_id), author)
+            } else {
+                ServerReplyRaw::Error(format!("You can not delete message {} for everyone.", message_id), author)
+            }
+        },
+    };
+
+    send_server_reply(&mut stream, server_reply, &output);
+}
+
+/// Sends [ServerReply::Messages](shared::message::ServerReply::Messages) followed by messages with given ids,
+/// files inside them are only announced by [FileReference].
+fn send_messages(stream: &mut TcpStream,
+                 db_conn: &mut Connection,
+                 messages_ids: Vec<usize>,
+                 recipient: UserLite,
+                 location: &Path) -> Result<(), NetCommsError> {
+
+    // Count has to include every announced attachment and leave out messages deleted meanwhile,
+    // so they are prepared first.
+    let messages: Vec<ImplementedMessage> = messages_ids.into_iter()
+        .filter_map(|message_id| get_message(db_conn, message_id, location).ok().map(|message| (message, message_id)))
+        .flat_map(|(message, message_id)| announce_files(message, message_id))
+        .collect();
+
+    ServerReplyRaw::Messages(messages.len(), recipient).into_message()?.send(stream)?;
+
+    for message in messages {
+        message.send(stream)?;
+    }
+
+    Ok(())
+}
+
+/// Sends every message of a thread that message with given id belongs to, which requesting user can see.
+fn return_thread(mut stream: TcpStream,
+                 db_conn: &mut Connection,
+                 message_id: usize,
+                 author: UserLite,
+                 location: &Path,
+                 output: Sender<Output>) {
+
+    let user_id = author.id() as usize;
+
+    if !is_message_visible(db_conn, message_id, user_id) {
+        let server_reply = ServerReplyRaw::Error(format!("Message {} does not exist.", message_id), author);
+        send_server_reply(&mut stream, server_reply, &output);
+        return;
+    }
+
+    let messages_ids: Vec<usize> = get_thread_messages_ids(db_conn, message_id)
+                                    .into_iter()
+                                    .filter(|id| is_message_visible(db_conn, *id, user_id))
+                                    .collect();
+
+    if let Err(e) = send_messages(&mut stream, db_conn, messages_ids, author, location) {
+        output.send(Output::Error(format!("Failed to send thread of message {}.\n{}", message_id, e))).unwrap();
+    }
+}
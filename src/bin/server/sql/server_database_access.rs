@@ -1,11 +1,15 @@
-use std::{path::Path, sync::mpsc::Sender};
+use std::{fs, path::{Path, PathBuf}, sync::mpsc::Sender};
 
-use chrono::{DateTime, Utc};
-use nardol::{error::NetCommsError, prelude::{Bytes, FromBytes, FromRon, IntoBytes, Packet, PacketKind, ToRon}};
-use rusqlite::{Connection, ToSql, types::ValueRef};
-use shared::{Content, ImplementedMessage, MessageKind, MetaData, user::User};
+use chrono::{DateTime, SecondsFormat, Utc};
+use nardol::{error::{NetCommsError, NetCommsErrorKind}, prelude::{Bytes, FromBytes, FromRon, IntoBytes, Packet, PacketKind, ToRon}};
+use rusqlite::{Connection, ToSql, types::{ToSqlOutput, ValueRef}};
+use shared::{Content, ImplementedMessage, MessageKind, MetaData, config::{GROUP_PREFIX, SERVER_ID, UNKNOWN_USER_ID}, user::User};
+use shared::group::{GroupMember, GroupRole};
+use shared::user::{DeviceInfo, PresenceInfo};
+use shared::message::{Attachment, FileTransfer, Notification, ReceiptKind, transfer};
 
 use crate::server::Output;
+use crate::server::blob_store;
 
 
 pub fn open_database(db_path: &Path, _output_t: Sender<Output>) -> Result<(), NetCommsError> {
@@ -13,30 +17,188 @@ pub fn open_database(db_path: &Path, _output_t: Sender<Output>) -> Result<(), Ne
         
     let db_conn =  Connection::open(db_path).unwrap();
 
+    create_tables(&db_conn);
+
+    let first_id = 2;
+
+    db_conn.execute("INSERT INTO available_ids (id, last) VALUES (?1, ?2)", [first_id, 1]).unwrap();
+
+    Ok(())
+}
+
+/// Columns of messages table, id is never reused, so a deleted message can not be confused with a new one.
+const MESSAGES_COLUMNS: &str = "
+            id                  INTEGER PRIMARY KEY AUTOINCREMENT,
+            kind                TEXT NOT NULL,
+            length              INTEGER NOT NULL,
+            datetime            TEXT NOT NULL,
+            author_id           INTEGER,
+            author_username     TEXT NOT NULL,
+            recipient_id        INTEGER NOT NULL,
+            file_name           TEXT,
+            content             BLOB,
+            end_data            TEXT,
+            transfer_id         TEXT,
+            transfer            TEXT,
+            blob_hash           TEXT,
+            group_id            INTEGER,
+            reply_to            INTEGER,
+            release_at          TEXT,
+            ttl                 INTEGER,
+            expires_at          TEXT,
+            uuid                TEXT
+    ";
+
+/// Internal function used by [open_database] to create every table that does not exist yet
+/// and bring those that do up to [DATABASE_VERSION].
+fn create_tables(db_conn: &Connection) {
+
     if let Err(_) = db_conn.execute(
         "CREATE TABLE users (
             id                  INTEGER NOT NULL,
             username            TEXT NOT NULL,
             password            TEXT NOT NULL,
             registration_date   TEXT NOT NULL,
-            auth_token          TEXT DEFAULT NULL
+            auth_token          TEXT DEFAULT NULL,
+            last_seen           TEXT DEFAULT NULL,
+            status              TEXT DEFAULT NULL
         )", []) {
         // Falls here if table already exist, check if table has correct structure is necessary.
     }
 
-    // id should be later changed to AUTO INCREMENT
+    // Devices users logged in from, every active device gets its own copy of waiting messages and notifications.
     if let Err(_) = db_conn.execute(
-        "CREATE TABLE messages (
-            id                  INTEGER PRIMARY KEY NOT NULL,
-            kind                TEXT NOT NULL,
-            length              INTEGER NOT NULL,
+        "CREATE TABLE devices (
+            id                  INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id             INTEGER NOT NULL,
+            registered_at       TEXT NOT NULL,
+            last_seen           TEXT DEFAULT NULL,
+            revoked             INTEGER NOT NULL DEFAULT 0
+        )", []) {
+        // Falls here if table already exist, check if table has correct structure is necessary.
+    }
+
+    // Messages are identified by uuid their author generated, so a retried send is saved only once.
+    if let Err(_) = db_conn.execute(&format!("CREATE TABLE messages ({})", MESSAGES_COLUMNS), []) {
+        // Falls here if table already exist, check if table has correct structure is necessary.
+    };
+
+    // Files attached to messages, position is their order inside the message.
+    if let Err(_) = db_conn.execute(
+        "CREATE TABLE attachments (
+            message_id          INTEGER NOT NULL,
+            position            INTEGER NOT NULL,
+            name                TEXT NOT NULL,
+            size                INTEGER NOT NULL,
+            mime_type           TEXT NOT NULL,
+            digest              TEXT NOT NULL
+    )", []) {
+        // Falls here if table already exist, check if table has correct structure is necessary.
+    };
+
+    // Files stored in blob store, ref_count is number of messages referencing each of them.
+    if let Err(_) = db_conn.execute(
+        "CREATE TABLE blobs (
+            hash                TEXT PRIMARY KEY NOT NULL,
+            size                INTEGER NOT NULL,
+            ref_count           INTEGER NOT NULL
+    )", []) {
+        // Falls here if table already exist, check if table has correct structure is necessary.
+    };
+
+    // Previous contents of edited messages, datetime is when was each of them replaced.
+    if let Err(_) = db_conn.execute(
+        "CREATE TABLE message_edits (
+            message_id          INTEGER NOT NULL,
             datetime            TEXT NOT NULL,
-            author_id           INTEGER,
-            author_username     TEXT NOT NULL,
+            content             BLOB
+    )", []) {
+        // Falls here if table already exist, check if table has correct structure is necessary.
+    };
+
+    // Notifications in RON format waiting to be sent to their recipients.
+    if let Err(_) = db_conn.execute(
+        "CREATE TABLE notifications (
+            id                  INTEGER PRIMARY KEY AUTOINCREMENT,
             recipient_id        INTEGER NOT NULL,
-            file_name           TEXT,
-            content             TEXT,
-            end_data            TEXT
+            notification        TEXT NOT NULL,
+            device_id           INTEGER
+    )", []) {
+        // Falls here if table already exist, check if table has correct structure is necessary.
+    };
+
+    // Messages that were deleted only for one of their author or recipients.
+    if let Err(_) = db_conn.execute(
+        "CREATE TABLE deleted_messages (
+            message_id          INTEGER NOT NULL,
+            user_id             INTEGER NOT NULL
+    )", []) {
+        // Falls here if table already exist, check if table has correct structure is necessary.
+    };
+
+    if let Err(_) = db_conn.execute(
+        "CREATE TABLE reactions (
+            message_id          INTEGER NOT NULL,
+            user_id             INTEGER NOT NULL,
+            reaction            TEXT NOT NULL,
+            UNIQUE(message_id, user_id, reaction)
+    )", []) {
+        // Falls here if table already exist, check if table has correct structure is necessary.
+    };
+
+    if let Err(_) = db_conn.execute(
+        "CREATE TABLE receipts (
+            message_id          INTEGER NOT NULL,
+            user_id             INTEGER NOT NULL,
+            delivered           TEXT,
+            read                TEXT,
+            PRIMARY KEY(message_id, user_id)
+    )", []) {
+        // Falls here if table already exist, check if table has correct structure is necessary.
+    };
+
+    if let Err(_) = db_conn.execute(
+        "CREATE TABLE user_settings (
+            user_id             INTEGER PRIMARY KEY NOT NULL,
+            read_receipts       INTEGER NOT NULL DEFAULT 1
+    )", []) {
+        // Falls here if table already exist, check if table has correct structure is necessary.
+    };
+
+    if let Err(_) = db_conn.execute(
+        "CREATE TABLE server_settings (
+            key                 TEXT PRIMARY KEY NOT NULL,
+            value               TEXT
+    )", []) {
+        // Falls here if table already exist, check if table has correct structure is necessary.
+    };
+
+    if let Err(_) = db_conn.execute(
+        "CREATE TABLE presence_subscriptions (
+            subscriber_id       INTEGER NOT NULL,
+            user_id             INTEGER NOT NULL,
+            last_presence       TEXT,
+            UNIQUE(subscriber_id, user_id)
+    )", []) {
+        // Falls here if table already exist, check if table has correct structure is necessary.
+    };
+
+    if let Err(_) = db_conn.execute(
+        "CREATE TABLE groups (
+            id                  INTEGER PRIMARY KEY NOT NULL,
+            name                TEXT UNIQUE NOT NULL,
+            created             TEXT NOT NULL
+    )", []) {
+        // Falls here if table already exist, check if table has correct structure is necessary.
+    };
+
+    if let Err(_) = db_conn.execute(
+        "CREATE TABLE group_members (
+            group_id            INTEGER NOT NULL,
+            user_id             INTEGER NOT NULL,
+            joined              TEXT NOT NULL,
+            role                TEXT NOT NULL,
+            muted               INTEGER NOT NULL DEFAULT 0
     )", []) {
         // Falls here if table already exist, check if table has correct structure is necessary.
     };
@@ -52,7 +214,8 @@ pub fn open_database(db_path: &Path, _output_t: Sender<Output>) -> Result<(), Ne
     if let Err(_) = db_conn.execute(
         "CREATE TABLE waiting_messages (
             message_id          INTEGER NOT NULL,
-            recipient_id        INTEGER NOT NULL
+            recipient_id        INTEGER NOT NULL,
+            device_id           INTEGER
         )", []) {
         // Falls here if table already exist, check if table has correct structure is necessary.
     };
@@ -66,27 +229,96 @@ pub fn open_database(db_path: &Path, _output_t: Sender<Output>) -> Result<(), Ne
         // Falls here if table already exist, check if table has correct structure is necessary.
     };
 
-    let first_id = 2;
+    migrate_database(db_conn);
 
-    db_conn.execute("INSERT INTO available_ids (id, last) VALUES (?1, ?2)", [first_id, 1]).unwrap();
+    // Index is created only after migration, as older databases got uuid column only now.
+    // Uuid is chosen by its author, so it is only unique among messages of the same author.
+    db_conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS messages_uuid ON messages (author_id, uuid)", []).unwrap();
 
-    Ok(())
+    // Full-text index of text messages, rowid of each row is id of its message.
+    if let Ok(_) = db_conn.execute(
+        "CREATE VIRTUAL TABLE messages_fts USING fts5 (
+            content
+    )", []) {
+        // Messages stored before the index existed are indexed once, binary content starts with its kind byte 2.
+        db_conn.execute("INSERT INTO messages_fts (rowid, content)
+                             SELECT id, CAST(substr(content, 2) AS TEXT)
+                             FROM messages
+                             WHERE length(content) > 1 AND substr(content, 1, 1)<>x'02'", []).unwrap();
+    };
 }
 
 
-pub fn get_new_message_id(db_conn: &mut Connection) -> usize {
-
-    let mut stmt = db_conn.prepare("SELECT MAX(id) FROM messages LIMIT 1").unwrap();
-    let mut id = stmt.query_map([], |row| {
-        let id: usize = match row.get(0) {
-            Ok(id) => id,
-            Err(_) => 0,
-        };
-        Ok(id)
+/// Version of database structure created by [open_database], it is stored in `PRAGMA user_version`.
+const DATABASE_VERSION: usize = 2;
+
+/// Columns added to tables after those tables were first created, with their definitions.
+const ADDED_COLUMNS: [(&str, &str, &str); 15] = [
+    ("users", "last_seen", "TEXT DEFAULT NULL"),
+    ("users", "status", "TEXT DEFAULT NULL"),
+    ("messages", "transfer_id", "TEXT"),
+    ("messages", "transfer", "TEXT"),
+    ("messages", "blob_hash", "TEXT"),
+    ("messages", "group_id", "INTEGER"),
+    ("messages", "reply_to", "INTEGER"),
+    ("messages", "release_at", "TEXT"),
+    ("messages", "ttl", "INTEGER"),
+    ("messages", "expires_at", "TEXT"),
+    ("messages", "uuid", "TEXT"),
+    ("group_members", "role", "TEXT NOT NULL DEFAULT 'Member'"),
+    ("group_members", "muted", "INTEGER NOT NULL DEFAULT 0"),
+    ("notifications", "device_id", "INTEGER"),
+    ("waiting_messages", "device_id", "INTEGER"),
+];
+
+/// Internal function used to bring database created by an older version up to [DATABASE_VERSION].
+///
+/// Tables that did not exist were already created with their current structure by [open_database].
+fn migrate_database(db_conn: &Connection) {
+
+    let mut stmt = db_conn.prepare("PRAGMA user_version").unwrap();
+    let version: usize = stmt.query_map([], |row| {
+        let version: usize = row.get(0).unwrap();
+        Ok(version)
     }).unwrap().next().unwrap().unwrap();
-    id += 1;
 
-    id
+    if version >= DATABASE_VERSION {
+        return;
+    }
+
+    db_conn.execute_batch("BEGIN").unwrap();
+
+    if version < 1 {
+        for &(table, column, definition) in ADDED_COLUMNS.iter() {
+            let mut stmt = db_conn.prepare("SELECT name FROM pragma_table_info(?1) WHERE name=?2").unwrap();
+            if !stmt.exists([table, column]).unwrap() {
+                db_conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), []).unwrap();
+            }
+        }
+
+        // Content used to be stored as text without its kind, all of it was text.
+        db_conn.execute("UPDATE messages
+                             SET content = CAST(x'01' || content AS BLOB)
+                             WHERE typeof(content)='text'", []).unwrap();
+    }
+
+    if version < 2 {
+        // Ids of deleted messages used to be reused, table is created again with AUTOINCREMENT keeping every id.
+        db_conn.execute(&format!("CREATE TABLE messages_new ({})", MESSAGES_COLUMNS), []).unwrap();
+        db_conn.execute("INSERT INTO messages_new
+                             (id, kind, length, datetime, author_id, author_username,
+                             recipient_id, file_name, content, end_data, transfer_id, transfer, blob_hash,
+                             group_id, reply_to, release_at, ttl, expires_at, uuid)
+                         SELECT id, kind, length, datetime, author_id, author_username,
+                             recipient_id, file_name, content, end_data, transfer_id, transfer, blob_hash,
+                             group_id, reply_to, release_at, ttl, expires_at, uuid
+                         FROM messages", []).unwrap();
+        db_conn.execute("DROP TABLE messages", []).unwrap();
+        db_conn.execute("ALTER TABLE messages_new RENAME TO messages", []).unwrap();
+    }
+
+    db_conn.execute_batch(&format!("PRAGMA user_version = {}", DATABASE_VERSION)).unwrap();
+    db_conn.execute_batch("COMMIT").unwrap();
 }
 
 pub fn get_user_id_from_username(db_conn: &mut Connection,
@@ -107,6 +339,142 @@ pub fn get_user_id_from_username(db_conn: &mut Connection,
     }
 }
 
+/// Returns usernames of every registered user.
+pub fn get_all_usernames(db_conn: &mut Connection) -> Vec<String> {
+
+    let mut stmt = db_conn.prepare("SELECT username
+                                                 FROM users
+                                                 WHERE id NOT IN (?1, ?2)
+                                                 ORDER BY id").unwrap();
+
+    let usernames_iter = stmt.query_map([SERVER_ID, UNKNOWN_USER_ID], |row| {
+        let username: String = row.get(0).unwrap();
+        Ok(username)
+    }).unwrap();
+
+    usernames_iter.map(|username| username.unwrap()).collect()
+}
+
+/// Sets message of the day sent to users after they log in, [None] clears it.
+pub fn set_motd(db_conn: &mut Connection, motd: Option<String>) {
+
+    db_conn.execute("INSERT INTO server_settings (key, value)
+                         VALUES ('motd', ?1)
+                         ON CONFLICT(key) DO UPDATE SET value=?1", [motd]).unwrap();
+}
+
+/// Returns message of the day, [None] if there is none.
+pub fn get_motd(db_conn: &mut Connection) -> Option<String> {
+
+    let mut stmt = db_conn.prepare("SELECT value FROM server_settings WHERE key='motd'").unwrap();
+    let mut motd_iter = stmt.query_map([], |row| {
+        let motd: Option<String> = row.get(0).unwrap();
+        Ok(motd)
+    }).unwrap();
+
+    motd_iter.next()?.unwrap()
+}
+
+/// Sets RFC 3339 datetime of when user with given id last contacted server.
+pub fn update_last_seen(db_conn: &mut Connection, user_id: usize, datetime: &str) {
+
+    db_conn.execute("UPDATE users SET last_seen=?1 WHERE id=?2",
+                        [datetime.to_sql().unwrap(), user_id.to_sql().unwrap()]).unwrap();
+}
+
+/// Returns RFC 3339 datetime of when user with given id last contacted server, [None] if it never did.
+pub fn get_last_seen(db_conn: &mut Connection, user_id: usize) -> Option<String> {
+
+    let mut stmt = db_conn.prepare("SELECT last_seen FROM users WHERE id=?1").unwrap();
+    let mut last_seen_iter = stmt.query_map([user_id], |row| {
+        let last_seen: Option<String> = row.get(0).unwrap();
+        Ok(last_seen)
+    }).unwrap();
+
+    last_seen_iter.next()?.unwrap()
+}
+
+/// Sets status message of user with given id, [None] clears it.
+pub fn set_user_status(db_conn: &mut Connection, user_id: usize, status: Option<String>) {
+
+    db_conn.execute("UPDATE users SET status=?1 WHERE id=?2",
+                        [status.to_sql().unwrap(), user_id.to_sql().unwrap()]).unwrap();
+}
+
+/// Returns status message of user with given id, [None] if it did not set any.
+pub fn get_user_status(db_conn: &mut Connection, user_id: usize) -> Option<String> {
+
+    let mut stmt = db_conn.prepare("SELECT status FROM users WHERE id=?1").unwrap();
+    let mut status_iter = stmt.query_map([user_id], |row| {
+        let status: Option<String> = row.get(0).unwrap();
+        Ok(status)
+    }).unwrap();
+
+    status_iter.next()?.unwrap()
+}
+
+/// Subscribes user with id `subscriber_id` to presence of user with id `user_id`.
+///
+/// Returns `false` if it was already subscribed.
+pub fn add_presence_subscription(db_conn: &mut Connection, subscriber_id: usize, user_id: usize) -> bool {
+
+    db_conn.execute("INSERT OR IGNORE INTO presence_subscriptions (subscriber_id, user_id)
+                         VALUES (?1, ?2)", [subscriber_id, user_id]).unwrap() != 0
+}
+
+/// Cancels subscription of user with id `subscriber_id` to presence of user with id `user_id`.
+///
+/// Returns `false` if there was no such subscription.
+pub fn remove_presence_subscription(db_conn: &mut Connection, subscriber_id: usize, user_id: usize) -> bool {
+
+    db_conn.execute("DELETE FROM presence_subscriptions
+                         WHERE subscriber_id=?1 AND user_id=?2", [subscriber_id, user_id]).unwrap() != 0
+}
+
+/// Returns ids of users that user with given id subscribed to
+/// with [PresenceInfo] it was told about last time, [None] if it was not told anything yet.
+pub fn get_presence_subscriptions(db_conn: &mut Connection, subscriber_id: usize) -> Vec<(usize, Option<PresenceInfo>)> {
+
+    let mut stmt = db_conn.prepare("SELECT user_id, last_presence
+                                                 FROM presence_subscriptions
+                                                 WHERE subscriber_id=?1
+                                                 ORDER BY rowid").unwrap();
+
+    let subscriptions_iter = stmt.query_map([subscriber_id], |row| {
+        let user_id: usize = row.get(0).unwrap();
+        let last_presence: Option<String> = row.get(1).unwrap();
+        Ok((user_id, last_presence.and_then(|presence| PresenceInfo::from_ron(&presence).ok())))
+    }).unwrap();
+
+    subscriptions_iter.map(|subscription| subscription.unwrap()).collect()
+}
+
+/// Remembers [PresenceInfo] of user with id `user_id` that subscriber with id `subscriber_id` was told about.
+pub fn set_subscription_presence(db_conn: &mut Connection, subscriber_id: usize, user_id: usize, presence: &PresenceInfo) {
+
+    let presence = presence.to_ron().unwrap();
+
+    db_conn.execute("UPDATE presence_subscriptions
+                         SET last_presence=?1
+                         WHERE subscriber_id=?2 AND user_id=?3",
+                        [presence.to_sql().unwrap(), subscriber_id.to_sql().unwrap(), user_id.to_sql().unwrap()]).unwrap();
+}
+
+/// Returns username of user with given id.
+pub fn get_username_from_id(db_conn: &mut Connection, user_id: usize) -> Result<String, ()> {
+
+    let mut stmt = db_conn.prepare("SELECT username FROM users WHERE id=?1 LIMIT 1").unwrap();
+    let mut username_iter = stmt.query_map([user_id], |row| {
+        let username: String = row.get(0).unwrap();
+        Ok(username)
+    }).unwrap();
+
+    match username_iter.next() {
+        Some(username) => return Ok(username.unwrap()),
+        None => return Err(()),
+    }
+}
+
 pub fn get_available_id(db_conn: &mut Connection) -> usize {
 
     let mut stmt = db_conn.prepare("SELECT id, last FROM available_ids LIMIT 1").unwrap();
@@ -146,14 +514,19 @@ pub fn get_user_password(db_conn: &mut Connection, user_id: usize) -> Result<Str
     }
 }
 
+/// Returns ids of messages waiting for user with given id on device with given id,
+/// [None] device is used by users that did not log in from any device yet.
 pub fn get_waiting_messages_ids(db_conn: &mut Connection,
-                            user_id: usize) -> Result<Vec<usize>, ()> {
+                            user_id: usize,
+                            device_id: Option<usize>) -> Result<Vec<usize>, ()> {
 
     let mut stmt = db_conn.prepare("SELECT message_id
                                              FROM waiting_messages
-                                             WHERE recipient_id=?1").unwrap();
+                                             WHERE recipient_id=?1 AND device_id IS ?2
+                                             ORDER BY message_id").unwrap();
 
-    let messages_ids_iter = stmt.query_map([user_id], |row| {
+    let params = [user_id.to_sql().unwrap(), device_id.to_sql().unwrap()];
+    let messages_ids_iter = stmt.query_map(params, |row| {
         let message_id: usize = row.get(0).unwrap(); 
 
         Ok(message_id)
@@ -168,13 +541,20 @@ pub fn get_waiting_messages_ids(db_conn: &mut Connection,
     }
 }
 
-pub fn get_message(db_conn: &mut Connection, message_id: usize) -> Result<ImplementedMessage, ()> {
+/// Returns a message with given id, if it holds a file, its [FileTransfer] source is set to file location in blob store.
+///
+/// Returns an error if there is no such message or it has no recipients.
+pub fn get_message(db_conn: &mut Connection, message_id: usize, location: &Path) -> Result<ImplementedMessage, ()> {
 
-    let recipients = get_message_recipients_ids(db_conn, message_id).unwrap();
+    let recipients = get_message_recipients_ids(db_conn, message_id)?;
     let recipients: Vec<String> = recipients.iter()
                                             .map(|recip| format!("{}", recip))
                                             .collect();
 
+    let attachments = get_message_attachments(db_conn, message_id, location);
+    let group = get_message_group(db_conn, message_id);
+    let edited = is_message_edited(db_conn, message_id);
+    let reactions = get_message_reactions(db_conn, message_id);
 
     let mut stmt = db_conn.prepare("SELECT *
                                                  FROM messages
@@ -200,7 +580,7 @@ pub fn get_message(db_conn: &mut Connection, message_id: usize) -> Result<Implem
             _ => panic!()
         };
 
-        let metadata = MetaData::from_data(
+        let mut metadata = MetaData::from_data(
             kind,
             row.get(2).unwrap(),
             datetime.into_bytes(),
@@ -210,9 +590,32 @@ pub fn get_message(db_conn: &mut Connection, message_id: usize) -> Result<Implem
             recipients.clone(),
             file_name,
         );
+
+        let transfer = match row.get_ref_unwrap(11) {
+            ValueRef::Text(transfer) => {
+                let transfer = String::from_buff(transfer).unwrap();
+                let mut transfer = FileTransfer::from_ron(&transfer).unwrap();
+                if let ValueRef::Text(hash) = row.get_ref_unwrap(12) {
+                    let hash = String::from_buff(hash).unwrap();
+                    transfer.set_source(Some(blob_store::blob_location(location, &hash)));
+                }
+                Some(transfer)
+            },
+            _ => None,
+        };
+        metadata.set_transfer(transfer);
+        metadata.set_attachments(attachments.clone());
+        metadata.set_group(group.clone());
+        metadata.set_message_id(Some(message_id));
+        metadata.set_edited(edited);
+        metadata.set_reply_to(row.get(14).unwrap());
+        metadata.set_reactions(reactions.clone());
+        metadata.set_send_at(row.get(15).unwrap());
+        metadata.set_ttl(row.get(16).unwrap());
+        metadata.set_uuid(row.get(18).unwrap());
         
-        let content = row.get(8).unwrap();
-        let content = Content::with_data(content);
+        let content: Vec<u8> = row.get(8).unwrap();
+        let content = Content::from_buff(&content).unwrap();
 
         let end_data: String = row.get(9).unwrap();
         let end_data = Packet::new(PacketKind::End, Bytes::from_vec(end_data.into_bytes()));
@@ -231,6 +634,55 @@ pub fn get_message(db_conn: &mut Connection, message_id: usize) -> Result<Implem
     }
 }
 
+pub fn get_message_id_by_transfer(db_conn: &mut Connection,
+                                  transfer_id: &str,
+                                  recipient_id: usize) -> Result<usize, ()> {
+
+    let mut stmt = db_conn.prepare("SELECT messages.id
+                                                 FROM messages
+                                                 INNER JOIN message_recipients
+                                                 ON messages.id = message_recipients.message_id
+                                                 WHERE messages.transfer_id=?1
+                                                 AND message_recipients.recipient_id=?2
+                                                 LIMIT 1").unwrap();
+
+    let mut id_iter = stmt.query_map([transfer_id.to_sql().unwrap(), recipient_id.to_sql().unwrap()], |row| {
+        let id: usize = row.get(0).unwrap();
+        Ok(id)
+    }).unwrap();
+
+    match id_iter.next() {
+        Some(id) => return Ok(id.unwrap()),
+        None => return Err(()),
+    }
+}
+
+/// Returns id of author of message with given id.
+pub fn get_message_author_id(db_conn: &mut Connection, message_id: usize) -> Result<usize, ()> {
+
+    let mut stmt = db_conn.prepare("SELECT author_id FROM messages WHERE id=?1").unwrap();
+    let mut id_iter = stmt.query_map([message_id], |row| {
+        let id: usize = row.get(0).unwrap();
+        Ok(id)
+    }).unwrap();
+
+    match id_iter.next() {
+        Some(id) => return Ok(id.unwrap()),
+        None => return Err(()),
+    }
+}
+
+/// Returns `true` if user with given id is one of recipients of message with given id.
+pub fn is_message_recipient(db_conn: &mut Connection, message_id: usize, user_id: usize) -> bool {
+
+    let mut stmt = db_conn.prepare("SELECT message_id
+                                                 FROM message_recipients
+                                                 WHERE message_id=?1 AND recipient_id=?2
+                                                 LIMIT 1").unwrap();
+
+    stmt.exists([message_id, user_id]).unwrap()
+}
+
 pub fn get_message_recipients_ids(db_conn: &mut Connection, message_id: usize) -> Result<Vec<usize>, ()> {
 
     let mut stmt = db_conn.prepare("SELECT recipient_id
@@ -281,12 +733,48 @@ pub fn insert_new_user(db_conn: &mut Connection, user: &User) {
                         ]).unwrap();
 }
 
-pub fn insert_message_into_database(message: ImplementedMessage, db_conn: &mut Connection) -> Vec<String> {
+/// Inserts a message into database, if it holds a file, the file is moved to blob store
+/// and only its name is saved to `file_name`.
+///
+/// Message with the same uuid as an already saved one is a retried send and is not saved again.
+///
+/// Returns usernames of recipients that do not exist.
+///
+/// # Errors
+///
+/// * Returns an error if file or attachments of the message can not be moved to blob store,
+/// nothing is then saved.
+pub fn insert_message_into_database(message: ImplementedMessage,
+                                    db_conn: &mut Connection,
+                                    location: &Path) -> Result<Vec<String>, NetCommsError> {
+
+    // Write lock is held until files are stored and referenced,
+    // so a message deleted in the meantime can not remove a blob this one relies on.
+    db_conn.execute_batch("BEGIN IMMEDIATE").unwrap();
+
+    let result = insert_message(message, db_conn, location);
+    match result {
+        Ok(_) => db_conn.execute_batch("COMMIT").unwrap(),
+        Err(_) => db_conn.execute_batch("ROLLBACK").unwrap(),
+    }
+
+    result
+}
+
+/// Internal function used to insert a message inside a transaction started by [insert_message_into_database].
+fn insert_message(message: ImplementedMessage,
+                  db_conn: &mut Connection,
+                  location: &Path) -> Result<Vec<String>, NetCommsError> {
 
     let metadata = message.metadata_ref();
 
-    let id = get_new_message_id(db_conn);
-    let id = id.to_sql().unwrap();
+    if let Some(uuid) = metadata.uuid() {
+        let mut stmt = db_conn.prepare("SELECT id FROM messages WHERE author_id=?1 AND uuid=?2 LIMIT 1").unwrap();
+        if stmt.exists([metadata.author_id().to_sql().unwrap(), uuid.to_sql().unwrap()]).unwrap() {
+            remove_received_files(metadata);
+            return Ok(Vec::new());
+        }
+    }
 
     let kind = metadata.message_kind().to_ron().unwrap();
     let kind = kind.to_sql().unwrap();
@@ -306,22 +794,91 @@ pub fn insert_message_into_database(message: ImplementedMessage, db_conn: &mut C
     let recipient_id = metadata.recipient_id();
     let recipient_id = recipient_id.to_sql().unwrap();
 
-    let file_name = metadata.file_name();
+    // Received file is moved to blob store once the message is inserted, so the same file is stored only once.
+    let file = match metadata.file_name() {
+        Some(path) => {
+            let path = PathBuf::from(path);
+            let hash = match metadata.transfer() {
+                Some(transfer) => transfer.hash(),
+                None => transfer::file_hash(&path)?,
+            };
+            Some((path, hash))
+        },
+        None => None,
+    };
+    let file_name = file.as_ref()
+                        .and_then(|(path, _)| path.file_name())
+                        .map(|name| name.to_string_lossy().to_string());
     let file_name = file_name.to_sql().unwrap();
+    let blob_hash = file.as_ref().map(|(_, hash)| hash.clone());
+    let blob_hash = blob_hash.to_sql().unwrap();
 
-    let content = message.content().into_string();
+    let content = message.content().into_bytes().into_vec();
     let content = content.to_sql().unwrap();
 
     // Change this later so it can accommodate also non string data.
     let end_data = message.end_data().content_move().to_string();
     let end_data = end_data.to_sql().unwrap();
 
-    db_conn.execute("INSERT INTO messages
-                            (id, kind, length, datetime, author_id, author_username,
-                            recipient_id, file_name, content, end_data)
-                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+    // Offset is only valid for the connection it arrived in, every later sending starts from the beginning.
+    let (transfer_id, transfer) = match metadata.transfer() {
+        Some(mut transfer) => {
+            transfer.set_offset(0);
+            (Some(transfer.id()), Some(transfer.to_ron().unwrap()))
+        },
+        None => (None, None),
+    };
+    let transfer_id = transfer_id.to_sql().unwrap();
+    let transfer = transfer.to_sql().unwrap();
+
+    // Only the first group in recipients is used and only if author is its member or server.
+    let group = match metadata.recipients()
+                              .iter()
+                              .find_map(|recipient| recipient.strip_prefix(GROUP_PREFIX)) {
+        Some(name) => match get_group_id(db_conn, name) {
+            Ok(group_id) if metadata.author_id() == SERVER_ID
+                         || is_group_member(db_conn, group_id, metadata.author_id() as usize) => {
+                Some((group_id, format!("{}{}", GROUP_PREFIX, name)))
+            },
+            _ => None,
+        },
+        None => None,
+    };
+    let group_id = group.as_ref().map(|(group_id, _)| *group_id);
+    let group_id = group_id.to_sql().unwrap();
+
+    let reply_to = metadata.reply_to();
+    let reply_to = reply_to.to_sql().unwrap();
+
+    // Messages scheduled for the future wait for the scheduler, others are sent right away.
+    let release_at = metadata.send_at()
+                             .and_then(|send_at| DateTime::parse_from_rfc3339(&send_at).ok())
+                             .map(|send_at| send_at.with_timezone(&Utc))
+                             .filter(|send_at| *send_at > Utc::now());
+
+    // Time to live counts from the moment message is sent, for scheduled ones that is their release.
+    let ttl = metadata.ttl();
+    let expires_at = ttl.map(|ttl| {
+        let sent = release_at.unwrap_or_else(Utc::now);
+        (sent + chrono::Duration::seconds(ttl as i64)).to_rfc3339_opts(SecondsFormat::Secs, true)
+    });
+    let ttl = ttl.to_sql().unwrap();
+    let expires_at = expires_at.to_sql().unwrap();
+
+    let release_at = release_at.map(|send_at| send_at.to_rfc3339_opts(SecondsFormat::Secs, true));
+    let release_at = release_at.to_sql().unwrap();
+
+    let uuid = metadata.uuid();
+    let uuid = uuid.to_sql().unwrap();
+
+    // Another connection could have saved the same message in the meantime, id is assigned by the database.
+    let inserted = db_conn.execute("INSERT INTO messages
+                            (kind, length, datetime, author_id, author_username,
+                            recipient_id, file_name, content, end_data, transfer_id, transfer, blob_hash,
+                            group_id, reply_to, release_at, ttl, expires_at, uuid)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)
+                         ON CONFLICT(author_id, uuid) DO NOTHING",
                             [
-                                id.clone(),
                                 kind,
                                 length,
                                 datetime,
@@ -331,44 +888,1160 @@ pub fn insert_message_into_database(message: ImplementedMessage, db_conn: &mut C
                                 file_name,
                                 content,
                                 end_data,
+                                transfer_id,
+                                transfer,
+                                blob_hash,
+                                group_id,
+                                reply_to,
+                                release_at,
+                                ttl,
+                                expires_at,
+                                uuid,
                             ]).unwrap();
 
+    if inserted == 0 {
+        remove_received_files(metadata);
+        return Ok(Vec::new());
+    }
+
+    let id = db_conn.last_insert_rowid();
+    let id = id.to_sql().unwrap();
+
+    if let Some((path, hash)) = file {
+        let size = fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0);
+        blob_store::store_blob(location, &path, &hash)?;
+        add_blob_reference(db_conn, &hash, size);
+    }
+
+    insert_attachments(db_conn, message.metadata().attachments(), id.clone(), location)?;
+
+    if let Some(text) = message.content().as_text() {
+        db_conn.execute("INSERT INTO messages_fts (rowid, content) VALUES (?1, ?2)",
+                            [id.clone(), text.to_sql().unwrap()]).unwrap();
+    }
+
     let mut non_existent_recipients = Vec::new();
     
     for recipient in metadata.recipients() {
+        if recipient.starts_with(GROUP_PREFIX) {
+            match &group {
+                // Every member gets the message, except its author.
+                Some((group_id, name)) if *name == recipient => {
+                    for (member_id, _) in get_group_members(db_conn, *group_id) {
+                        if member_id != metadata.author_id() as usize {
+                            add_message_recipient(db_conn, id.clone(), member_id);
+                        }
+                    }
+                },
+                _ => non_existent_recipients.push(recipient),
+            }
+            continue;
+        }
+
         match get_user_id_from_username(db_conn, &recipient) {
-            Ok(recipient_id) => {
-
-                let recipient_id = recipient_id.to_sql().unwrap();
-
-                db_conn.execute("INSERT INTO message_recipients
-                                (message_id, recipient_id)
-                                VALUES (?1, ?2)",
-                                [
-                                    id.clone(),
-                                    recipient_id.clone()
-                                ]).unwrap();
-                db_conn.execute("INSERT INTO waiting_messages
-                                (message_id, recipient_id)
-                                VALUES (?1, ?2)",
-                                [
-                                    id.clone(),
-                                    recipient_id
-                                ]).unwrap();
-            },
+            Ok(recipient_id) => add_message_recipient(db_conn, id.clone(), recipient_id),
             Err(_) => {
                 non_existent_recipients.push(recipient);
             },
         }
     }
 
-    non_existent_recipients
+    Ok(non_existent_recipients)
+}
+
+/// Removes files received with a message that was not saved, because the same message was already saved.
+fn remove_received_files(metadata: &MetaData) {
+
+    if let Some(path) = metadata.file_name() {
+        let _ = fs::remove_file(path);
+    }
+    for path in metadata.attachments().into_iter().filter_map(|attachment| attachment.path()) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Replaces content of message with given id, previous content is kept in `message_edits`.
+pub fn edit_message_content(db_conn: &mut Connection, message_id: usize, content: Content, datetime: &str) {
+
+    db_conn.execute("INSERT INTO message_edits (message_id, datetime, content)
+                         SELECT id, ?2, content FROM messages WHERE id=?1",
+                        [message_id.to_sql().unwrap(), datetime.to_sql().unwrap()]).unwrap();
+
+    db_conn.execute("DELETE FROM messages_fts WHERE rowid=?1", [message_id]).unwrap();
+    if let Some(text) = content.as_text() {
+        db_conn.execute("INSERT INTO messages_fts (rowid, content) VALUES (?1, ?2)",
+                            [message_id.to_sql().unwrap(), text.to_sql().unwrap()]).unwrap();
+    }
+
+    let content = content.into_bytes().into_vec();
+
+    db_conn.execute("UPDATE messages SET content=?1 WHERE id=?2",
+                        [content.to_sql().unwrap(), message_id.to_sql().unwrap()]).unwrap();
+}
+
+/// Returns ids of messages user with given id can see whose text matches FTS5 `query`, newest first.
+///
+/// Messages can be limited to a conversation with user with id `peer_id` or group with id `group_id`
+/// and to those sent at or after RFC 3339 datetime `from` and before `to`.
+pub fn search_messages_ids(db_conn: &mut Connection,
+                           user_id: usize,
+                           query: &str,
+                           peer_id: Option<usize>,
+                           group_id: Option<usize>,
+                           from: Option<String>,
+                           to: Option<String>,
+                           limit: usize,
+                           offset: usize) -> Vec<usize> {
+
+    let mut stmt = db_conn.prepare("SELECT messages.id
+                                                 FROM messages_fts
+                                                 INNER JOIN messages ON messages.id = messages_fts.rowid
+                                                 WHERE messages_fts MATCH ?1
+                                                 AND (messages.author_id=?2
+                                                      OR (messages.release_at IS NULL
+                                                          AND messages.id IN (SELECT message_id
+                                                                              FROM message_recipients
+                                                                              WHERE recipient_id=?2)))
+                                                 AND messages.id NOT IN (SELECT message_id
+                                                                         FROM deleted_messages
+                                                                         WHERE user_id=?2)
+                                                 AND (?3 IS NULL
+                                                      OR (messages.author_id=?2 AND messages.group_id IS NULL
+                                                          AND messages.id IN (SELECT message_id
+                                                                              FROM message_recipients
+                                                                              WHERE recipient_id=?3))
+                                                      OR (messages.author_id=?3 AND messages.group_id IS NULL))
+                                                 AND (?4 IS NULL OR messages.group_id=?4)
+                                                 AND (?5 IS NULL OR messages.datetime>=?5)
+                                                 AND (?6 IS NULL OR messages.datetime<?6)
+                                                 ORDER BY messages.datetime DESC, messages.id DESC
+                                                 LIMIT ?7 OFFSET ?8").unwrap();
+
+    let params = [
+        query.to_sql().unwrap(),
+        user_id.to_sql().unwrap(),
+        peer_id.to_sql().unwrap(),
+        group_id.to_sql().unwrap(),
+        from.to_sql().unwrap(),
+        to.to_sql().unwrap(),
+        limit.to_sql().unwrap(),
+        offset.to_sql().unwrap(),
+    ];
+    let ids_iter = stmt.query_map(params, |row| {
+        let id: usize = row.get(0).unwrap();
+        Ok(id)
+    }).unwrap();
+
+    ids_iter.map(|id| id.unwrap()).collect()
+}
+
+/// Returns ids of up to `limit` newest messages user with given id can see that are older than message
+/// with id `before_id`, oldest first.
+///
+/// Messages are from a conversation with user with id `peer_id` or from group with id `group_id`.
+pub fn get_history_ids(db_conn: &mut Connection,
+                       user_id: usize,
+                       peer_id: Option<usize>,
+                       group_id: Option<usize>,
+                       before_id: Option<usize>,
+                       limit: usize) -> Vec<usize> {
+
+    let mut stmt = db_conn.prepare("SELECT id
+                                                 FROM messages
+                                                 WHERE (author_id=?1
+                                                        OR (release_at IS NULL
+                                                            AND id IN (SELECT message_id
+                                                                       FROM message_recipients
+                                                                       WHERE recipient_id=?1)))
+                                                 AND id NOT IN (SELECT message_id
+                                                                FROM deleted_messages
+                                                                WHERE user_id=?1)
+                                                 AND (?2 IS NULL
+                                                      OR (author_id=?1 AND group_id IS NULL AND id IN (SELECT message_id
+                                                                                                       FROM message_recipients
+                                                                                                       WHERE recipient_id=?2))
+                                                      OR (author_id=?2 AND group_id IS NULL))
+                                                 AND (?3 IS NULL OR group_id=?3)
+                                                 AND (?4 IS NULL OR id<?4)
+                                                 ORDER BY id DESC
+                                                 LIMIT ?5").unwrap();
+
+    let params = [
+        user_id.to_sql().unwrap(),
+        peer_id.to_sql().unwrap(),
+        group_id.to_sql().unwrap(),
+        before_id.to_sql().unwrap(),
+        limit.to_sql().unwrap(),
+    ];
+    let ids_iter = stmt.query_map(params, |row| {
+        let id: usize = row.get(0).unwrap();
+        Ok(id)
+    }).unwrap();
+
+    let mut messages_ids: Vec<usize> = ids_iter.map(|id| id.unwrap()).collect();
+    messages_ids.reverse();
+
+    messages_ids
 }
 
-pub fn delete_waiting_message(db_conn: &mut Connection, recipient_id: usize) -> Result<(), ()> {
+/// Hides message with given id for user with given id, if it was not sent to that user yet, it never will be.
+pub fn hide_message(db_conn: &mut Connection, message_id: usize, user_id: usize) {
 
     db_conn.execute("DELETE FROM waiting_messages
-                         WHERE recipient_id=?1", [recipient_id]).unwrap();
-    
-    Ok(())
-}
\ No newline at end of file
+                         WHERE message_id=?1 AND recipient_id=?2", [message_id, user_id]).unwrap();
+    db_conn.execute("INSERT INTO deleted_messages (message_id, user_id)
+                         VALUES (?1, ?2)", [message_id, user_id]).unwrap();
+}
+
+/// Returns `true` if message with given id is still waiting to be sent to user with given id.
+pub fn is_message_waiting(db_conn: &mut Connection, message_id: usize, recipient_id: usize) -> bool {
+
+    let mut stmt = db_conn.prepare("SELECT message_id
+                                                 FROM waiting_messages
+                                                 WHERE message_id=?1 AND recipient_id=?2
+                                                 LIMIT 1").unwrap();
+
+    stmt.exists([message_id, recipient_id]).unwrap()
+}
+
+/// Returns `true` if user with given id is author or recipient of message with given id
+/// and did not delete it for itself.
+pub fn is_message_visible(db_conn: &mut Connection, message_id: usize, user_id: usize) -> bool {
+
+    let mut stmt = db_conn.prepare("SELECT id
+                                                 FROM messages
+                                                 WHERE id=?1
+                                                 AND (author_id=?2
+                                                      OR (release_at IS NULL
+                                                          AND id IN (SELECT message_id
+                                                                     FROM message_recipients
+                                                                     WHERE recipient_id=?2)))
+                                                 AND id NOT IN (SELECT message_id
+                                                                FROM deleted_messages
+                                                                WHERE user_id=?2)
+                                                 LIMIT 1").unwrap();
+
+    stmt.exists([message_id, user_id]).unwrap()
+}
+
+/// Returns ids of all messages in a thread that message with given id belongs to, from the oldest one.
+///
+/// Thread starts with the first message that does not reply to any existing message.
+pub fn get_thread_messages_ids(db_conn: &mut Connection, message_id: usize) -> Vec<usize> {
+
+    let mut stmt = db_conn.prepare("WITH RECURSIVE
+                                        ancestors(id, reply_to) AS (
+                                            SELECT id, reply_to FROM messages WHERE id=?1
+                                            UNION
+                                            SELECT messages.id, messages.reply_to
+                                            FROM messages
+                                            INNER JOIN ancestors ON messages.id = ancestors.reply_to
+                                        ),
+                                        thread(id) AS (
+                                            SELECT id FROM ancestors
+                                            WHERE reply_to IS NULL
+                                            OR reply_to NOT IN (SELECT id FROM messages)
+                                            UNION
+                                            SELECT messages.id
+                                            FROM messages
+                                            INNER JOIN thread ON messages.reply_to = thread.id
+                                        )
+                                    SELECT id FROM thread ORDER BY id").unwrap();
+
+    let ids_iter = stmt.query_map([message_id], |row| {
+        let id: usize = row.get(0).unwrap();
+        Ok(id)
+    }).unwrap();
+
+    ids_iter.map(|id| id.unwrap()).collect()
+}
+
+/// Returns `true` if message with given id was edited at least once.
+pub fn is_message_edited(db_conn: &mut Connection, message_id: usize) -> bool {
+
+    let mut stmt = db_conn.prepare("SELECT message_id
+                                                 FROM message_edits
+                                                 WHERE message_id=?1
+                                                 LIMIT 1").unwrap();
+
+    stmt.exists([message_id]).unwrap()
+}
+
+/// Adds given reaction of user with given id to message with given id.
+///
+/// Returns `false` if user already reacted with the same reaction.
+pub fn add_reaction(db_conn: &mut Connection, message_id: usize, user_id: usize, reaction: &str) -> bool {
+
+    db_conn.execute("INSERT OR IGNORE INTO reactions (message_id, user_id, reaction)
+                         VALUES (?1, ?2, ?3)",
+                        [message_id.to_sql().unwrap(), user_id.to_sql().unwrap(), reaction.to_sql().unwrap()]).unwrap() != 0
+}
+
+/// Removes given reaction of user with given id from message with given id.
+///
+/// Returns `false` if user did not react with that reaction.
+pub fn remove_reaction(db_conn: &mut Connection, message_id: usize, user_id: usize, reaction: &str) -> bool {
+
+    db_conn.execute("DELETE FROM reactions
+                         WHERE message_id=?1 AND user_id=?2 AND reaction=?3",
+                        [message_id.to_sql().unwrap(), user_id.to_sql().unwrap(), reaction.to_sql().unwrap()]).unwrap() != 0
+}
+
+/// Returns every reaction to message with given id with number of users that reacted with it,
+/// in order in which they were first used.
+pub fn get_message_reactions(db_conn: &mut Connection, message_id: usize) -> Vec<(String, usize)> {
+
+    let mut stmt = db_conn.prepare("SELECT reaction, COUNT(*)
+                                                 FROM reactions
+                                                 WHERE message_id=?1
+                                                 GROUP BY reaction
+                                                 ORDER BY MIN(rowid)").unwrap();
+
+    let reactions_iter = stmt.query_map([message_id], |row| {
+        Ok((row.get(0).unwrap(), row.get(1).unwrap()))
+    }).unwrap();
+
+    reactions_iter.map(|reaction| reaction.unwrap()).collect()
+}
+
+/// Records that message with given id reached user with given id in given [ReceiptKind] at given datetime.
+///
+/// Returns `false` if it was already recorded, read message is also marked as delivered.
+pub fn set_message_receipt(db_conn: &mut Connection,
+                           message_id: usize,
+                           user_id: usize,
+                           kind: ReceiptKind,
+                           datetime: &str) -> bool {
+
+    db_conn.execute("INSERT OR IGNORE INTO receipts (message_id, user_id)
+                         VALUES (?1, ?2)", [message_id, user_id]).unwrap();
+
+    let params = [datetime.to_sql().unwrap(), message_id.to_sql().unwrap(), user_id.to_sql().unwrap()];
+    match kind {
+        ReceiptKind::Delivered => {
+            db_conn.execute("UPDATE receipts
+                                 SET delivered=?1
+                                 WHERE message_id=?2 AND user_id=?3 AND delivered IS NULL", params).unwrap() != 0
+        },
+        ReceiptKind::Read => {
+            db_conn.execute("UPDATE receipts
+                                 SET delivered=IFNULL(delivered, ?1)
+                                 WHERE message_id=?2 AND user_id=?3", params.clone()).unwrap();
+            db_conn.execute("UPDATE receipts
+                                 SET read=?1
+                                 WHERE message_id=?2 AND user_id=?3 AND read IS NULL", params).unwrap() != 0
+        },
+    }
+}
+
+/// Returns `true` if user with given id sends read receipts, that is the default.
+pub fn read_receipts_enabled(db_conn: &mut Connection, user_id: usize) -> bool {
+
+    let mut stmt = db_conn.prepare("SELECT read_receipts
+                                                 FROM user_settings
+                                                 WHERE user_id=?1").unwrap();
+
+    let mut settings_iter = stmt.query_map([user_id], |row| {
+        let enabled: bool = row.get(0).unwrap();
+        Ok(enabled)
+    }).unwrap();
+
+    match settings_iter.next() {
+        Some(enabled) => enabled.unwrap(),
+        None => true,
+    }
+}
+
+/// Turns sending of read receipts of user with given id on or off.
+pub fn set_read_receipts(db_conn: &mut Connection, user_id: usize, enabled: bool) {
+
+    db_conn.execute("INSERT INTO user_settings (user_id, read_receipts)
+                         VALUES (?1, ?2)
+                         ON CONFLICT(user_id) DO UPDATE SET read_receipts=?2",
+                        [user_id.to_sql().unwrap(), enabled.to_sql().unwrap()]).unwrap();
+}
+
+/// Queues a [Notification] for every device of user with given id, it is sent with its waiting messages.
+pub fn insert_notification(db_conn: &mut Connection, recipient_id: usize, notification: &Notification) {
+
+    for device_id in get_delivery_devices_ids(db_conn, recipient_id) {
+        insert_device_notification(db_conn, recipient_id, device_id, notification);
+    }
+}
+
+/// Queues a [Notification] only for device with given id of user with given id.
+pub fn insert_device_notification(db_conn: &mut Connection,
+                                  recipient_id: usize,
+                                  device_id: Option<usize>,
+                                  notification: &Notification) {
+
+    let notification = notification.to_ron().unwrap();
+
+    db_conn.execute("INSERT INTO notifications (recipient_id, notification, device_id)
+                         VALUES (?1, ?2, ?3)",
+                        [recipient_id.to_sql().unwrap(), notification.to_sql().unwrap(), device_id.to_sql().unwrap()]).unwrap();
+}
+
+/// Returns ids and [notifications](Notification) queued for user with given id on device with given id,
+/// from the oldest one.
+pub fn get_notifications(db_conn: &mut Connection, recipient_id: usize, device_id: Option<usize>) -> Vec<(usize, Notification)> {
+
+    let mut stmt = db_conn.prepare("SELECT id, notification
+                                                 FROM notifications
+                                                 WHERE recipient_id=?1 AND device_id IS ?2
+                                                 ORDER BY id").unwrap();
+
+    let params = [recipient_id.to_sql().unwrap(), device_id.to_sql().unwrap()];
+    let notifications_iter = stmt.query_map(params, |row| {
+        let id: usize = row.get(0).unwrap();
+        let notification: String = row.get(1).unwrap();
+        Ok((id, Notification::from_ron(&notification).unwrap()))
+    }).unwrap();
+
+    notifications_iter.map(|notification| notification.unwrap()).collect()
+}
+
+/// Deletes a notification with given id queued for user with given id on device with given id.
+pub fn delete_notification(db_conn: &mut Connection, notification_id: usize, recipient_id: usize, device_id: Option<usize>) {
+
+    db_conn.execute("DELETE FROM notifications WHERE id=?1 AND recipient_id=?2 AND device_id IS ?3",
+                        [notification_id.to_sql().unwrap(), recipient_id.to_sql().unwrap(), device_id.to_sql().unwrap()]).unwrap();
+}
+
+/// Returns ids of messages of user with given id that are scheduled and not sent yet, from the first one to be sent.
+pub fn get_scheduled_messages_ids(db_conn: &mut Connection, author_id: usize) -> Vec<usize> {
+
+    let mut stmt = db_conn.prepare("SELECT id
+                                                 FROM messages
+                                                 WHERE author_id=?1 AND release_at IS NOT NULL
+                                                 ORDER BY release_at, id").unwrap();
+
+    let ids_iter = stmt.query_map([author_id], |row| {
+        let id: usize = row.get(0).unwrap();
+        Ok(id)
+    }).unwrap();
+
+    ids_iter.map(|id| id.unwrap()).collect()
+}
+
+/// Returns ids of scheduled messages whose release time is not later than given datetime.
+pub fn get_due_messages_ids(db_conn: &mut Connection, datetime: DateTime<Utc>) -> Vec<usize> {
+
+    let datetime = datetime.to_rfc3339_opts(SecondsFormat::Secs, true);
+
+    let mut stmt = db_conn.prepare("SELECT id
+                                                 FROM messages
+                                                 WHERE release_at IS NOT NULL AND release_at<=?1
+                                                 ORDER BY release_at, id").unwrap();
+
+    let ids_iter = stmt.query_map([datetime], |row| {
+        let id: usize = row.get(0).unwrap();
+        Ok(id)
+    }).unwrap();
+
+    ids_iter.map(|id| id.unwrap()).collect()
+}
+
+/// Returns ids of messages whose time to live ended before given datetime.
+pub fn get_expired_messages_ids(db_conn: &mut Connection, datetime: DateTime<Utc>) -> Vec<usize> {
+
+    let datetime = datetime.to_rfc3339_opts(SecondsFormat::Secs, true);
+
+    let mut stmt = db_conn.prepare("SELECT id
+                                                 FROM messages
+                                                 WHERE expires_at IS NOT NULL AND expires_at<=?1").unwrap();
+
+    let ids_iter = stmt.query_map([datetime], |row| {
+        let id: usize = row.get(0).unwrap();
+        Ok(id)
+    }).unwrap();
+
+    ids_iter.map(|id| id.unwrap()).collect()
+}
+
+/// Sends scheduled message with given id, it becomes waiting for all its recipients.
+///
+/// Nothing happens if the message is no longer scheduled, for example because it was cancelled meanwhile.
+pub fn release_message(db_conn: &mut Connection, message_id: usize) {
+
+    if db_conn.execute("UPDATE messages SET release_at=NULL WHERE id=?1 AND release_at IS NOT NULL", [message_id]).unwrap() == 0 {
+        return;
+    }
+    for recipient_id in get_message_recipients_ids(db_conn, message_id).unwrap_or_default() {
+        for device_id in get_delivery_devices_ids(db_conn, recipient_id) {
+            db_conn.execute("INSERT INTO waiting_messages (message_id, recipient_id, device_id)
+                                 VALUES (?1, ?2, ?3)",
+                                [message_id.to_sql().unwrap(), recipient_id.to_sql().unwrap(), device_id.to_sql().unwrap()]).unwrap();
+        }
+    }
+}
+
+/// Adds user with given id to recipients of message with given id and marks the message as waiting for this user.
+pub fn add_message_recipient(db_conn: &mut Connection, message_id: ToSqlOutput, recipient_id: usize) {
+
+    db_conn.execute("INSERT INTO message_recipients
+                    (message_id, recipient_id)
+                    VALUES (?1, ?2)",
+                    [
+                        message_id.clone(),
+                        recipient_id.to_sql().unwrap()
+                    ]).unwrap();
+    // Scheduled message starts waiting only once it is released.
+    for device_id in get_delivery_devices_ids(db_conn, recipient_id) {
+        db_conn.execute("INSERT INTO waiting_messages
+                        (message_id, recipient_id, device_id)
+                        SELECT ?1, ?2, ?3
+                        WHERE NOT EXISTS (SELECT id FROM messages WHERE id=?1 AND release_at IS NOT NULL)",
+                        [
+                            message_id.clone(),
+                            recipient_id.to_sql().unwrap(),
+                            device_id.to_sql().unwrap(),
+                        ]).unwrap();
+    }
+}
+
+/// Deletes message with given id from messages waiting for user with given id on device with given id.
+///
+/// Returns `false` if the message was not waiting there.
+pub fn delete_waiting_message(db_conn: &mut Connection,
+                              message_id: usize,
+                              recipient_id: usize,
+                              device_id: Option<usize>) -> bool {
+
+    let deleted = db_conn.execute("DELETE FROM waiting_messages
+                                       WHERE message_id=?1 AND recipient_id=?2 AND device_id IS ?3",
+                                      [message_id.to_sql().unwrap(), recipient_id.to_sql().unwrap(), device_id.to_sql().unwrap()]).unwrap();
+    
+    deleted > 0
+}
+
+/// Registers a new device of user with given id and returns its id.
+///
+/// Messages and notifications that were waiting for the user before it logged in from any device are moved to it.
+pub fn register_device(db_conn: &mut Connection, user_id: usize, datetime: &str) -> usize {
+
+    db_conn.execute("INSERT INTO devices (user_id, registered_at, last_seen)
+                         VALUES (?1, ?2, ?2)",
+                        [user_id.to_sql().unwrap(), datetime.to_sql().unwrap()]).unwrap();
+    let device_id = db_conn.last_insert_rowid() as usize;
+
+    db_conn.execute("UPDATE waiting_messages SET device_id=?1 WHERE recipient_id=?2 AND device_id IS NULL",
+                        [device_id, user_id]).unwrap();
+    db_conn.execute("UPDATE notifications SET device_id=?1 WHERE recipient_id=?2 AND device_id IS NULL",
+                        [device_id, user_id]).unwrap();
+
+    device_id
+}
+
+/// Returns `true` if device with given id belongs to user with given id and was not revoked.
+pub fn is_device_active(db_conn: &mut Connection, device_id: usize, user_id: usize) -> bool {
+
+    let mut stmt = db_conn.prepare("SELECT id
+                                                 FROM devices
+                                                 WHERE id=?1 AND user_id=?2 AND revoked=0
+                                                 LIMIT 1").unwrap();
+
+    stmt.exists([device_id, user_id]).unwrap()
+}
+
+/// Sets RFC 3339 datetime of when device with given id last contacted server.
+pub fn update_device_last_seen(db_conn: &mut Connection, device_id: usize, datetime: &str) {
+
+    db_conn.execute("UPDATE devices SET last_seen=?1 WHERE id=?2",
+                        [datetime.to_sql().unwrap(), device_id.to_sql().unwrap()]).unwrap();
+}
+
+/// Returns every active device of user with given id, device with id `current_id` is marked as current.
+pub fn get_devices(db_conn: &mut Connection, user_id: usize, current_id: Option<usize>) -> Vec<DeviceInfo> {
+
+    let mut stmt = db_conn.prepare("SELECT id, registered_at, last_seen
+                                                 FROM devices
+                                                 WHERE user_id=?1 AND revoked=0
+                                                 ORDER BY id").unwrap();
+
+    let devices_iter = stmt.query_map([user_id], |row| {
+        let id: usize = row.get(0).unwrap();
+        Ok(DeviceInfo::new(id, row.get(1).unwrap(), row.get(2).unwrap(), Some(id) == current_id))
+    }).unwrap();
+
+    devices_iter.map(|device| device.unwrap()).collect()
+}
+
+/// Revokes device with given id of user with given id, messages and notifications waiting for it are deleted.
+///
+/// Returns `false` if the user does not have such active device.
+pub fn revoke_device(db_conn: &mut Connection, device_id: usize, user_id: usize) -> bool {
+
+    let revoked = db_conn.execute("UPDATE devices SET revoked=1 WHERE id=?1 AND user_id=?2 AND revoked=0",
+                                      [device_id, user_id]).unwrap();
+    if revoked == 0 {
+        return false;
+    }
+
+    db_conn.execute("DELETE FROM waiting_messages WHERE device_id=?1", [device_id]).unwrap();
+    db_conn.execute("DELETE FROM notifications WHERE device_id=?1", [device_id]).unwrap();
+
+    true
+}
+
+/// Revokes every device that did not contact server since given datetime,
+/// messages and notifications waiting for those devices are deleted.
+///
+/// Returns number of revoked devices.
+///
+/// # Errors
+///
+/// * Returns an error if database could not be read or written, for example when it is locked.
+pub fn expire_devices(db_conn: &mut Connection, datetime: DateTime<Utc>) -> Result<usize, rusqlite::Error> {
+
+    let datetime = datetime.to_rfc3339();
+
+    // Write lock is only taken when there is something to expire.
+    let mut stmt = db_conn.prepare("SELECT id
+                                                 FROM devices
+                                                 WHERE revoked=0 AND COALESCE(last_seen, registered_at)<?1
+                                                 LIMIT 1")?;
+    if !stmt.exists([&datetime])? {
+        return Ok(0);
+    }
+    drop(stmt);
+
+    db_conn.execute_batch("BEGIN IMMEDIATE")?;
+
+    let result = expire_devices_rows(db_conn, &datetime);
+    match result {
+        Ok(_) => db_conn.execute_batch("COMMIT")?,
+        Err(_) => db_conn.execute_batch("ROLLBACK")?,
+    }
+
+    result
+}
+
+/// Internal function used to expire devices inside a transaction started by [expire_devices].
+fn expire_devices_rows(db_conn: &mut Connection, datetime: &str) -> Result<usize, rusqlite::Error> {
+
+    db_conn.execute("DELETE FROM waiting_messages
+                         WHERE device_id IN (SELECT id
+                                             FROM devices
+                                             WHERE revoked=0 AND COALESCE(last_seen, registered_at)<?1)",
+                        [datetime])?;
+    db_conn.execute("DELETE FROM notifications
+                         WHERE device_id IN (SELECT id
+                                             FROM devices
+                                             WHERE revoked=0 AND COALESCE(last_seen, registered_at)<?1)",
+                        [datetime])?;
+
+    db_conn.execute("UPDATE devices
+                         SET revoked=1
+                         WHERE revoked=0 AND COALESCE(last_seen, registered_at)<?1",
+                        [datetime])
+}
+
+/// Returns ids of devices a message or notification for user with given id has to be delivered to,
+/// that is every active device or [None] if the user did not log in from any device yet.
+pub fn get_delivery_devices_ids(db_conn: &mut Connection, user_id: usize) -> Vec<Option<usize>> {
+
+    let mut stmt = db_conn.prepare("SELECT id FROM devices WHERE user_id=?1 AND revoked=0").unwrap();
+    let ids_iter = stmt.query_map([user_id], |row| {
+        let id: usize = row.get(0).unwrap();
+        Ok(Some(id))
+    }).unwrap();
+
+    let devices_ids: Vec<Option<usize>> = ids_iter.map(|id| id.unwrap()).collect();
+    if devices_ids.is_empty() {
+        vec![None]
+    } else {
+        devices_ids
+    }
+}
+
+/// Adds a reference to a file with given `hash` in blob store, if it is not yet referenced it is created.
+pub fn add_blob_reference(db_conn: &mut Connection, hash: &str, size: u64) {
+
+    db_conn.execute("INSERT INTO blobs (hash, size, ref_count)
+                         VALUES (?1, ?2, 1)
+                         ON CONFLICT(hash) DO UPDATE SET ref_count = ref_count + 1",
+                        [
+                            hash.to_sql().unwrap(),
+                            size.to_sql().unwrap(),
+                        ]).unwrap();
+}
+
+/// Removes a reference to a file with given `hash` in blob store and returns number of remaining references.
+///
+/// If there are no references left, row is deleted.
+pub fn remove_blob_reference(db_conn: &mut Connection, hash: &str) -> usize {
+
+    db_conn.execute("UPDATE blobs
+                         SET ref_count = ref_count - 1
+                         WHERE hash=?1", [hash]).unwrap();
+
+    let mut stmt = db_conn.prepare("SELECT ref_count FROM blobs WHERE hash=?1").unwrap();
+    let ref_count: usize = match stmt.query_map([hash], |row| {
+        let ref_count: usize = row.get(0).unwrap();
+        Ok(ref_count)
+    }).unwrap().next() {
+        Some(ref_count) => ref_count.unwrap(),
+        None => 0,
+    };
+
+    if ref_count == 0 {
+        db_conn.execute("DELETE FROM blobs WHERE hash=?1", [hash]).unwrap();
+    }
+
+    ref_count
+}
+
+/// Returns hash of a file in blob store referenced by message with given id.
+pub fn get_message_blob_hash(db_conn: &mut Connection, message_id: usize) -> Option<String> {
+
+    let mut stmt = db_conn.prepare("SELECT blob_hash FROM messages WHERE id=?1").unwrap();
+    let mut hash_iter = stmt.query_map([message_id], |row| {
+        let hash: Option<String> = row.get(0).unwrap();
+        Ok(hash)
+    }).unwrap();
+
+    match hash_iter.next() {
+        Some(hash) => hash.unwrap(),
+        None => None,
+    }
+}
+
+/// Deletes a message with given id with all its recipients,
+/// if it was the last message referencing its file, the file is removed from blob store.
+pub fn delete_message(db_conn: &mut Connection, message_id: usize, location: &Path) -> Result<(), ()> {
+    delete_message_in_transaction(db_conn, message_id, None, location)
+}
+
+/// Deletes a scheduled message with given id like [delete_message], but only if user with id `author_id`
+/// is its author and it was not sent yet.
+///
+/// Returns an error if there is no such message.
+pub fn delete_scheduled_message(db_conn: &mut Connection, message_id: usize, author_id: usize, location: &Path) -> Result<(), ()> {
+    delete_message_in_transaction(db_conn, message_id, Some(author_id), location)
+}
+
+/// Internal function used to delete a message, if `scheduled_by` is [Some] only a scheduled message of user with that id.
+fn delete_message_in_transaction(db_conn: &mut Connection,
+                                 message_id: usize,
+                                 scheduled_by: Option<usize>,
+                                 location: &Path) -> Result<(), ()> {
+
+    // Write lock is held until unreferenced files are removed,
+    // so no message inserted in the meantime can start referencing one of them.
+    db_conn.execute_batch("BEGIN IMMEDIATE").unwrap();
+
+    let result = delete_message_rows(db_conn, message_id, scheduled_by, location);
+    match result {
+        Ok(_) => db_conn.execute_batch("COMMIT").unwrap(),
+        Err(_) => db_conn.execute_batch("ROLLBACK").unwrap(),
+    }
+
+    result
+}
+
+/// Internal function used to delete a message inside a transaction started by [delete_message_in_transaction].
+fn delete_message_rows(db_conn: &mut Connection,
+                       message_id: usize,
+                       scheduled_by: Option<usize>,
+                       location: &Path) -> Result<(), ()> {
+
+    let blob_hash = get_message_blob_hash(db_conn, message_id);
+    let attachments = get_message_attachments(db_conn, message_id, location);
+
+    // Message is checked and deleted at once, so scheduled message can not be released meanwhile.
+    let deleted = match scheduled_by {
+        Some(author_id) => db_conn.execute("DELETE FROM messages WHERE id=?1 AND author_id=?2 AND release_at IS NOT NULL",
+                                           [message_id, author_id]).unwrap(),
+        None => db_conn.execute("DELETE FROM messages WHERE id=?1", [message_id]).unwrap(),
+    };
+    if deleted == 0 {
+        return Err(());
+    }
+
+    db_conn.execute("DELETE FROM waiting_messages WHERE message_id=?1", [message_id]).unwrap();
+    db_conn.execute("DELETE FROM message_recipients WHERE message_id=?1", [message_id]).unwrap();
+    db_conn.execute("DELETE FROM attachments WHERE message_id=?1", [message_id]).unwrap();
+    db_conn.execute("DELETE FROM message_edits WHERE message_id=?1", [message_id]).unwrap();
+    db_conn.execute("DELETE FROM deleted_messages WHERE message_id=?1", [message_id]).unwrap();
+    db_conn.execute("DELETE FROM reactions WHERE message_id=?1", [message_id]).unwrap();
+    db_conn.execute("DELETE FROM receipts WHERE message_id=?1", [message_id]).unwrap();
+    db_conn.execute("DELETE FROM messages_fts WHERE rowid=?1", [message_id]).unwrap();
+
+    let hashes = blob_hash.into_iter()
+                          .chain(attachments.iter().map(|attachment| attachment.digest()));
+    for hash in hashes {
+        if remove_blob_reference(db_conn, &hash) == 0 {
+            blob_store::remove_blob(location, &hash);
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves every attachment of a message to blob store and inserts it into database.
+///
+/// # Errors
+///
+/// * Returns an error if an attachment has no path or can not be moved to blob store.
+pub fn insert_attachments(db_conn: &mut Connection,
+                          attachments: Vec<Attachment>,
+                          message_id: ToSqlOutput,
+                          location: &Path) -> Result<(), NetCommsError> {
+
+    for (position, attachment) in attachments.into_iter().enumerate() {
+        let path = match attachment.path() {
+            Some(path) => path,
+            None => return Err(NetCommsError::new(
+                NetCommsErrorKind::OpeningFileFailed,
+                Some(format!("Attachment {} was not received.", attachment.name())))),
+        };
+        blob_store::store_blob(location, &path, &attachment.digest())?;
+        add_blob_reference(db_conn, &attachment.digest(), attachment.size());
+
+        db_conn.execute("INSERT INTO attachments
+                            (message_id, position, name, size, mime_type, digest)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                            [
+                                message_id.clone(),
+                                position.to_sql().unwrap(),
+                                attachment.name().to_sql().unwrap(),
+                                attachment.size().to_sql().unwrap(),
+                                attachment.mime_type().to_sql().unwrap(),
+                                attachment.digest().to_sql().unwrap(),
+                            ]).unwrap();
+    }
+
+    Ok(())
+}
+
+/// Returns attachments of message with given id, their paths are set to their location in blob store.
+pub fn get_message_attachments(db_conn: &mut Connection, message_id: usize, location: &Path) -> Vec<Attachment> {
+
+    let mut stmt = db_conn.prepare("SELECT name, size, mime_type, digest
+                                                 FROM attachments
+                                                 WHERE message_id=?1
+                                                 ORDER BY position").unwrap();
+
+    let attachments_iter = stmt.query_map([message_id], |row| {
+        let digest: String = row.get(3).unwrap();
+        let path = blob_store::blob_location(location, &digest);
+
+        Ok(Attachment::from_data(row.get(0).unwrap(),
+                                 row.get(1).unwrap(),
+                                 row.get(2).unwrap(),
+                                 digest,
+                                 Some(path)))
+    }).unwrap();
+
+    attachments_iter.map(|attachment| attachment.unwrap()).collect()
+}
+
+/// Returns number of bytes stored on server for files and attachments of messages authored by user with given id,
+/// together with files of its unfinished transfers inside staging directory of given `location`.
+///
+/// Every message is counted, even if its file is stored only once.
+pub fn get_user_storage_usage(db_conn: &mut Connection, user_id: usize, location: &Path) -> u64 {
+
+    let mut stmt = db_conn.prepare("SELECT
+                                        (SELECT COALESCE(SUM(blobs.size), 0)
+                                         FROM messages
+                                         INNER JOIN blobs ON messages.blob_hash = blobs.hash
+                                         WHERE messages.author_id=?1)
+                                        +
+                                        (SELECT COALESCE(SUM(attachments.size), 0)
+                                         FROM messages
+                                         INNER JOIN attachments ON messages.id = attachments.message_id
+                                         WHERE messages.author_id=?1)").unwrap();
+
+    let mut usage_iter = stmt.query_map([user_id], |row| {
+        let usage: i64 = row.get(0).unwrap();
+        Ok(usage as u64)
+    }).unwrap();
+
+    let usage = match usage_iter.next() {
+        Some(usage) => usage.unwrap(),
+        None => 0,
+    };
+
+    // Transfer ids start with id of their author.
+    let prefix = format!("{}-", user_id);
+    let staged: u64 = transfer::unfinished_transfers(location).into_iter()
+                                                              .filter(|(id, _)| id.starts_with(&prefix))
+                                                              .map(|(_, staged)| staged)
+                                                              .sum();
+
+    usage + staged
+}
+
+/// Creates a new group with given name and makes user with given id its first member.
+///
+/// Returns an error if group with that name already exists.
+pub fn create_group(db_conn: &mut Connection, name: &str, user_id: usize) -> Result<usize, ()> {
+
+    let created = Utc::now().to_rfc3339();
+
+    if let Err(_) = db_conn.execute("INSERT INTO groups (name, created) VALUES (?1, ?2)", [name, created.as_str()]) {
+        return Err(());
+    }
+    let group_id = db_conn.last_insert_rowid() as usize;
+
+    add_group_member(db_conn, group_id, user_id, GroupRole::Owner);
+
+    Ok(group_id)
+}
+
+/// Returns id of a group with given name.
+pub fn get_group_id(db_conn: &mut Connection, name: &str) -> Result<usize, ()> {
+
+    let mut stmt = db_conn.prepare("SELECT id FROM groups WHERE name=?1 LIMIT 1").unwrap();
+    let mut id_iter = stmt.query_map([name], |row| {
+        let id: usize = row.get(0).unwrap();
+        Ok(id)
+    }).unwrap();
+
+    match id_iter.next() {
+        Some(id) => return Ok(id.unwrap()),
+        None => return Err(()),
+    }
+}
+
+/// Returns name of a group in which conversation was sent message with given id, prefixed with [GROUP_PREFIX].
+pub fn get_message_group(db_conn: &mut Connection, message_id: usize) -> Option<String> {
+
+    let mut stmt = db_conn.prepare("SELECT groups.name
+                                                 FROM messages
+                                                 INNER JOIN groups ON messages.group_id = groups.id
+                                                 WHERE messages.id=?1").unwrap();
+    let mut name_iter = stmt.query_map([message_id], |row| {
+        let name: String = row.get(0).unwrap();
+        Ok(format!("{}{}", GROUP_PREFIX, name))
+    }).unwrap();
+
+    match name_iter.next() {
+        Some(name) => Some(name.unwrap()),
+        None => None,
+    }
+}
+
+/// Renames group with given id, returns an error if group with `new_name` already exists.
+pub fn rename_group(db_conn: &mut Connection, group_id: usize, new_name: &str) -> Result<(), ()> {
+
+    match db_conn.execute("UPDATE groups SET name=?1 WHERE id=?2",
+                          [new_name.to_sql().unwrap(), group_id.to_sql().unwrap()]) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(()),
+    }
+}
+
+/// Adds user with given id to members of group with given id with given [GroupRole],
+/// if it is not a member already.
+pub fn add_group_member(db_conn: &mut Connection, group_id: usize, user_id: usize, role: GroupRole) {
+
+    if is_group_member(db_conn, group_id, user_id) {
+        return;
+    }
+
+    let joined = Utc::now().to_rfc3339();
+
+    let role = role.to_ron().unwrap();
+
+    db_conn.execute("INSERT INTO group_members (group_id, user_id, joined, role)
+                         VALUES (?1, ?2, ?3, ?4)",
+                        [
+                            group_id.to_sql().unwrap(),
+                            user_id.to_sql().unwrap(),
+                            joined.to_sql().unwrap(),
+                            role.to_sql().unwrap(),
+                        ]).unwrap();
+}
+
+/// Removes user with given id from members of group with given id, group without any members is deleted.
+///
+/// If the owner is removed, the member that joined first becomes a new owner.
+///
+/// Returns an error if user was not a member of that group.
+pub fn remove_group_member(db_conn: &mut Connection, group_id: usize, user_id: usize) -> Result<(), ()> {
+
+    if db_conn.execute("DELETE FROM group_members
+                             WHERE group_id=?1 AND user_id=?2", [group_id, user_id]).unwrap() == 0 {
+        return Err(());
+    }
+
+    let members = get_group_members(db_conn, group_id);
+    match members.first() {
+        None => {
+            db_conn.execute("DELETE FROM groups WHERE id=?1", [group_id]).unwrap();
+        },
+        Some((first_id, _)) => {
+            if !members.iter().any(|(_, member)| member.role() == GroupRole::Owner) {
+                set_group_member_role(db_conn, group_id, *first_id, GroupRole::Owner);
+            }
+        },
+    }
+
+    Ok(())
+}
+
+/// Returns [GroupRole] of user with given id inside group with given id, [None] if it is not its member.
+pub fn get_group_member_role(db_conn: &mut Connection, group_id: usize, user_id: usize) -> Option<GroupRole> {
+
+    let mut stmt = db_conn.prepare("SELECT role
+                                                 FROM group_members
+                                                 WHERE group_id=?1 AND user_id=?2
+                                                 LIMIT 1").unwrap();
+    let mut role_iter = stmt.query_map([group_id, user_id], |row| {
+        let role: String = row.get(0).unwrap();
+        Ok(GroupRole::from_ron(&role).unwrap())
+    }).unwrap();
+
+    match role_iter.next() {
+        Some(role) => Some(role.unwrap()),
+        None => None,
+    }
+}
+
+/// Sets [GroupRole] of user with given id inside group with given id.
+pub fn set_group_member_role(db_conn: &mut Connection, group_id: usize, user_id: usize, role: GroupRole) {
+
+    let role = role.to_ron().unwrap();
+
+    db_conn.execute("UPDATE group_members
+                         SET role=?1
+                         WHERE group_id=?2 AND user_id=?3",
+                        [
+                            role.to_sql().unwrap(),
+                            group_id.to_sql().unwrap(),
+                            user_id.to_sql().unwrap(),
+                        ]).unwrap();
+}
+
+/// Mutes or unmutes user with given id inside group with given id.
+pub fn set_group_member_muted(db_conn: &mut Connection, group_id: usize, user_id: usize, muted: bool) {
+
+    db_conn.execute("UPDATE group_members
+                         SET muted=?1
+                         WHERE group_id=?2 AND user_id=?3",
+                        [
+                            muted.to_sql().unwrap(),
+                            group_id.to_sql().unwrap(),
+                            user_id.to_sql().unwrap(),
+                        ]).unwrap();
+}
+
+/// Returns `true` if user with given id is muted inside group with given id.
+pub fn is_group_member_muted(db_conn: &mut Connection, group_id: usize, user_id: usize) -> bool {
+
+    let mut stmt = db_conn.prepare("SELECT user_id
+                                                 FROM group_members
+                                                 WHERE group_id=?1 AND user_id=?2 AND muted<>0
+                                                 LIMIT 1").unwrap();
+
+    stmt.exists([group_id, user_id]).unwrap()
+}
+
+/// Returns id of a group in which conversation was sent message with given id.
+pub fn get_message_group_id(db_conn: &mut Connection, message_id: usize) -> Option<usize> {
+
+    let mut stmt = db_conn.prepare("SELECT group_id FROM messages WHERE id=?1").unwrap();
+    let mut group_iter = stmt.query_map([message_id], |row| {
+        let group_id: Option<usize> = row.get(0).unwrap();
+        Ok(group_id)
+    }).unwrap();
+
+    match group_iter.next() {
+        Some(group_id) => group_id.unwrap(),
+        None => None,
+    }
+}
+
+/// Returns `true` if user with given id is a member of group with given id.
+pub fn is_group_member(db_conn: &mut Connection, group_id: usize, user_id: usize) -> bool {
+
+    let mut stmt = db_conn.prepare("SELECT user_id
+                                                 FROM group_members
+                                                 WHERE group_id=?1 AND user_id=?2
+                                                 LIMIT 1").unwrap();
+
+    stmt.exists([group_id, user_id]).unwrap()
+}
+
+/// Returns ids and [GroupMember] of all members of group with given id, in order in which they joined.
+pub fn get_group_members(db_conn: &mut Connection, group_id: usize) -> Vec<(usize, GroupMember)> {
+
+    let mut stmt = db_conn.prepare("SELECT users.id, users.username, group_members.role, group_members.muted
+                                                 FROM group_members
+                                                 INNER JOIN users ON group_members.user_id = users.id
+                                                 WHERE group_members.group_id=?1
+                                                 ORDER BY group_members.joined").unwrap();
+
+    let members_iter = stmt.query_map([group_id], |row| {
+        let id: usize = row.get(0).unwrap();
+        let username: String = row.get(1).unwrap();
+        let role: String = row.get(2).unwrap();
+        let muted: bool = row.get(3).unwrap();
+        Ok((id, GroupMember::new(username, GroupRole::from_ron(&role).unwrap(), muted)))
+    }).unwrap();
+
+    members_iter.map(|member| member.unwrap()).collect()
+}
+
+/// Returns ids of messages sent to group with given id that user with given id neither wrote nor received,
+/// from the oldest one.
+pub fn get_missing_group_messages_ids(db_conn: &mut Connection, group_id: usize, user_id: usize) -> Vec<usize> {
+
+    let mut stmt = db_conn.prepare("SELECT id
+                                                 FROM messages
+                                                 WHERE group_id=?1
+                                                 AND author_id<>?2
+                                                 AND id NOT IN (SELECT message_id
+                                                                FROM message_recipients
+                                                                WHERE recipient_id=?2)
+                                                 ORDER BY id").unwrap();
+
+    let ids_iter = stmt.query_map([group_id, user_id], |row| {
+        let id: usize = row.get(0).unwrap();
+        Ok(id)
+    }).unwrap();
+
+    ids_iter.map(|id| id.unwrap()).collect()
+}
+
+# [test]
+fn message_sent_again_is_saved_once() {
+
+    use shared::user::UserLite;
+
+    let mut db_conn = Connection::open_in_memory().unwrap();
+    create_tables(&db_conn);
+
+    let content = Content::text("hello".to_string()).into_bytes();
+    let metadata = MetaData::new(&content, MessageKind::Text,
+                                 UserLite::new(2, "alice".to_string()),
+                                 0, vec!["bob".to_string()], None).unwrap();
+
+    let mut message = ImplementedMessage::new();
+    message.set_metadata(metadata);
+    message.set_content(Content::from_bytes(content).unwrap());
+    message.set_end_data(Packet::new(PacketKind::End, Bytes::new()));
+
+    // Retried send carries the same uuid, it holds no files, so location is never touched.
+    let location = std::env::temp_dir();
+    insert_message_into_database(message.clone(), &mut db_conn, &location).unwrap();
+    insert_message_into_database(message, &mut db_conn, &location).unwrap();
+
+    let count: usize = db_conn.query_row("SELECT COUNT(*) FROM messages", [], |row| row.get(0)).unwrap();
+    assert_eq!(count, 1);
+}
+
+# [test]
+fn unknown_message_is_an_error() {
+
+    let mut db_conn = Connection::open_in_memory().unwrap();
+    create_tables(&db_conn);
+
+    // Edit and delete requests answer with an error for those instead of failing.
+    let location = std::env::temp_dir();
+    assert!(get_message(&mut db_conn, 999999, &location).is_err());
+    assert!(delete_message(&mut db_conn, 999999, &location).is_err());
+}
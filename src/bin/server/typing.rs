@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+
+/// Minimum time between two typing events of one user, more frequent events are refused.
+pub const TYPING_INTERVAL: Duration = Duration::from_secs(2);
+/// Time after which a typing event that was not picked up by its recipient is dropped.
+pub const TYPING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One typing event waiting for its recipient.
+///
+/// # Fields
+///
+/// * `username` -- username of the user that is typing.
+/// * `group` -- [Option], if [Some] holds name of the group in which conversation the user is typing.
+/// * `created` -- when the event was relayed, used to drop it after [TYPING_TIMEOUT].
+#[derive(Debug, Clone)]
+pub struct TypingEvent {
+    username: String,
+    group: Option<String>,
+    created: Instant,
+}
+
+impl TypingEvent {
+
+    /// Returns `username`.
+    pub fn username(&self) -> String {
+        self.username.clone()
+    }
+
+    /// Returns `group`.
+    pub fn group(&self) -> Option<String> {
+        self.group.clone()
+    }
+}
+
+/// Pending events are kept for each device of their recipient, so every device gets its own copy.
+#[derive(Debug, Default)]
+struct TypingState {
+    last_events: HashMap<usize, Instant>,
+    pending: HashMap<(usize, Option<usize>), Vec<TypingEvent>>,
+}
+
+/// Relays typing events between users, events are only held in memory and never written to database.
+///
+/// It is shared by every connection thread, cloning it returns a handle to the same relay.
+#[derive(Debug, Clone, Default)]
+pub struct TypingRelay {
+    state: Arc<Mutex<TypingState>>,
+}
+
+impl TypingRelay {
+
+    pub fn new() -> Self {
+        TypingRelay::default()
+    }
+
+    /// Returns `true` if user with given id can send a typing event now,
+    /// which is if its last event is at least [TYPING_INTERVAL] old.
+    pub fn try_start(&self, user_id: usize) -> bool {
+
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        match state.last_events.get(&user_id) {
+            Some(last_event) if now.duration_since(*last_event) < TYPING_INTERVAL => false,
+            _ => {
+                state.last_events.insert(user_id, now);
+                true
+            },
+        }
+    }
+
+    /// Holds a typing event of user with given username for device with id `device_id` of user with id `recipient_id`,
+    /// previous event of the same user in the same conversation is replaced.
+    pub fn relay(&self, recipient_id: usize, device_id: Option<usize>, username: String, group: Option<String>) {
+
+        let mut state = self.state.lock().unwrap();
+        let events = state.pending.entry((recipient_id, device_id)).or_insert_with(Vec::new);
+
+        events.retain(|event| event.username != username || event.group != group);
+        events.push(TypingEvent {
+            username,
+            group,
+            created: Instant::now(),
+        });
+    }
+
+    /// Removes and returns typing events for device with given id of user with given id
+    /// that are not older than [TYPING_TIMEOUT].
+    pub fn take(&self, recipient_id: usize, device_id: Option<usize>) -> Vec<TypingEvent> {
+
+        let mut state = self.state.lock().unwrap();
+        let events = state.pending.remove(&(recipient_id, device_id)).unwrap_or_default();
+
+        events.into_iter()
+              .filter(|event| event.created.elapsed() <= TYPING_TIMEOUT)
+              .collect()
+    }
+}
@@ -13,3 +13,32 @@ pub const UNKNOWN_USER_ID: u32  = 1;
 /// User username that is used when client or server do not know the user that is sending or receiving a [Message](crate::message::Message).
 /// Typical use is when sending a [login](crate::request::Request::Login) or [register](crate::request::Request::Register) request.
 pub const UNKNOWN_USERNAME: &str = "UNKNOWN";
+
+/// Maximum size of one [Packet](nardol::packet::Packet) in bytes, used to estimate size of a [Message](crate::message::Message)
+/// from its `message_length`.
+pub const MAX_PACKET_SIZE: u64 = 1024;
+
+/// Prefix of a recipient in [MetaData](crate::message::MetaData) `recipients` that marks it as a name of a group,
+/// not a username.
+pub const GROUP_PREFIX: &str = "#";
+
+/// Maximum number of characters of one reaction, like an emoji or a short code as `:+1:`.
+pub const MAX_REACTION_LENGTH: usize = 32;
+
+/// Number of [messages](crate::message::Message) returned for one search request if it does not ask for other.
+pub const DEFAULT_SEARCH_RESULTS: usize = 20;
+/// Maximum number of [messages](crate::message::Message) returned for one search request.
+pub const MAX_SEARCH_RESULTS: usize = 100;
+
+/// Number of [messages](crate::message::Message) returned for one history request if it does not ask for other.
+pub const DEFAULT_HISTORY_PAGE: usize = 50;
+/// Maximum number of [messages](crate::message::Message) returned for one history request.
+pub const MAX_HISTORY_PAGE: usize = 200;
+
+/// Maximum number of characters of a status message a user can set for itself.
+pub const MAX_STATUS_LENGTH: usize = 100;
+
+/// Default number of seconds since a user was last seen for which it is shown as [online](crate::user::Presence::Online).
+pub const ONLINE_TIMEOUT: u64 = 30;
+/// Default number of seconds since a user was last seen for which it is shown as [away](crate::user::Presence::Away).
+pub const AWAY_TIMEOUT: u64 = 600;
@@ -0,0 +1,49 @@
+use serde::{Serialize, Deserialize};
+
+use nardol::ron::{FromRon, ToRon};
+
+use super::GroupRole;
+
+
+/// Holds data about one member of a group.
+///
+/// # Fields
+///
+/// * `username` -- username of the member.
+/// * `role` -- [GroupRole] of the member.
+/// * `muted` -- `true` if the member can not send messages to the group.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GroupMember {
+    username: String,
+    role: GroupRole,
+    muted: bool,
+}
+
+impl FromRon<'_> for GroupMember {}
+impl ToRon for GroupMember {}
+
+impl GroupMember {
+
+    pub fn new(username: String, role: GroupRole, muted: bool) -> Self {
+        GroupMember {
+            username,
+            role,
+            muted,
+        }
+    }
+
+    /// Returns `username`.
+    pub fn username(&self) -> String {
+        self.username.clone()
+    }
+
+    /// Returns `role`.
+    pub fn role(&self) -> GroupRole {
+        self.role
+    }
+
+    /// Returns `muted`.
+    pub fn muted(&self) -> bool {
+        self.muted
+    }
+}
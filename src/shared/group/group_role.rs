@@ -0,0 +1,59 @@
+use serde::{Serialize, Deserialize};
+
+use std::fmt::Display;
+
+use nardol::ron::{FromRon, ToRon};
+
+
+/// Role of a member inside a group, it decides which moderation actions the member can do.
+///
+/// # Variants
+///
+/// * `Owner` -- creator of the group, can do everything including changing roles of other members.
+/// * `Moderator` -- can remove and mute members, delete their messages and rename the group.
+/// * `Member` -- can only send messages to the group, invite others and leave.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum GroupRole {
+    Owner,
+    Moderator,
+    Member,
+}
+
+impl Default for GroupRole {
+
+    fn default() -> Self {
+        GroupRole::Member
+    }
+}
+
+impl FromRon<'_> for GroupRole {}
+impl ToRon for GroupRole {}
+
+impl Display for GroupRole {
+
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GroupRole::Owner => write!(f, "owner"),
+            GroupRole::Moderator => write!(f, "moderator"),
+            GroupRole::Member => write!(f, "member"),
+        }
+    }
+}
+
+impl GroupRole {
+
+    /// Returns `true` if this role can remove, mute and delete messages of other members and rename the group.
+    pub fn can_moderate(&self) -> bool {
+        matches!(self, GroupRole::Owner | GroupRole::Moderator)
+    }
+
+    /// Returns `true` if this role can moderate a member with given `role`,
+    /// nobody can moderate an owner and moderators can only moderate ordinary members.
+    pub fn outranks(&self, role: GroupRole) -> bool {
+        match self {
+            GroupRole::Owner => role != GroupRole::Owner,
+            GroupRole::Moderator => role == GroupRole::Member,
+            GroupRole::Member => false,
+        }
+    }
+}
@@ -0,0 +1,5 @@
+pub mod group_member;
+pub mod group_role;
+
+pub use group_member::GroupMember;
+pub use group_role::GroupRole;
@@ -1,5 +1,6 @@
 pub mod message;
 pub mod user;
+pub mod group;
 pub mod config;
 
 pub use message::{Content, MetaData, MessageKind, Request, RequestRaw};
@@ -0,0 +1,153 @@
+use serde::{Serialize, Deserialize};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use nardol::error::{NetCommsError, NetCommsErrorKind};
+use nardol::ron::{FromRon, ToRon};
+
+use super::transfer::{self, STAGING_DIR};
+
+
+/// Holds data about one file attached to a [Message](crate::message::Message).
+///
+/// Attachments are sent after the text of the message in the same order in which they are in
+/// [MetaData](crate::message::MetaData) `attachments`, each of them followed by [End](nardol::packet::PacketKind::End) packet.
+///
+/// # Fields
+///
+/// * `name` -- name of the file with its extension.
+/// * `size` -- size of the file in bytes.
+/// * `mime_type` -- MIME type guessed from extension of the file.
+/// * `digest` -- SHA-256 hash of the file.
+/// * `path` -- [Option], if [Some] it is a location of this file on this side, it is never sent to the other side.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Attachment {
+    name: String,
+    size: u64,
+    mime_type: String,
+    digest: String,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl ToRon for Attachment {}
+impl FromRon<'_> for Attachment {}
+
+impl Attachment {
+
+    /// Creates a new [Attachment] from file at given `path`.
+    ///
+    /// # Errors
+    ///
+    /// * Returns an error if file does not exist or can not be read.
+    pub fn from_path(path: &Path) -> Result<Self, NetCommsError> {
+
+        let size = match fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() => metadata.len(),
+            _ => return Err(NetCommsError::new(
+                NetCommsErrorKind::OpeningFileFailed,
+                Some(format!("{:?} is not a file.", path)))),
+        };
+
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => return Err(NetCommsError::new(
+                NetCommsErrorKind::OpeningFileFailed,
+                Some(format!("{:?} does not have a file name.", path)))),
+        };
+
+        Ok(Attachment {
+            mime_type: mime_type(path),
+            name,
+            size,
+            digest: transfer::file_hash(path)?,
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    /// Creates an [Attachment] from already known data, used when it is loaded from a database.
+    pub fn from_data(name: String,
+                     size: u64,
+                     mime_type: String,
+                     digest: String,
+                     path: Option<PathBuf>) -> Self {
+
+        Attachment {
+            name,
+            size,
+            mime_type,
+            digest,
+            path,
+        }
+    }
+
+    /// Returns `name`.
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Returns `size` in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns `mime_type`.
+    pub fn mime_type(&self) -> String {
+        self.mime_type.clone()
+    }
+
+    /// Returns `digest`.
+    pub fn digest(&self) -> String {
+        self.digest.clone()
+    }
+
+    /// Returns `path`.
+    pub fn path(&self) -> Option<PathBuf> {
+        self.path.clone()
+    }
+
+    /// Sets `path`.
+    pub fn set_path(&mut self, path: Option<PathBuf>) {
+        self.path = path;
+    }
+
+    /// Returns location inside staging directory of given `location` where is this attachment received,
+    /// before it is verified.
+    pub fn staging_location(&self, location: &Path) -> PathBuf {
+
+        let mut path = PathBuf::from(location);
+        path.push(STAGING_DIR);
+        path.push(format!("{}.attachment", self.digest));
+
+        path
+    }
+}
+
+/// Returns MIME type of a file guessed from its extension, if it is not known `application/octet-stream` is returned.
+pub fn mime_type(path: &Path) -> String {
+
+    let extension = match path.extension() {
+        Some(extension) => extension.to_string_lossy().to_lowercase(),
+        None => String::new(),
+    };
+
+    let mime_type = match extension.as_str() {
+        "txt" | "log" => "text/plain",
+        "ron" => "application/ron",
+        "json" => "application/json",
+        "html" | "htm" => "text/html",
+        "csv" => "text/csv",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        _ => "application/octet-stream",
+    };
+
+    mime_type.to_string()
+}
@@ -13,10 +13,87 @@ use nardol::{message::{ContentType}, packet::Packet, prelude::{ToRon, Message, N
 
 use crate::ImplementedMessage;
 
-use super::{message_kind::MessageKind, metadata::MetaData};
+use super::{message_kind::MessageKind, metadata::MetaData, transfer};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Content (String);
+/// Declares how are bytes inside [Content] meant to be read.
+///
+/// # Variants
+///
+/// * `Text` -- UTF-8 text.
+/// * `Binary` -- raw bytes without any meaning known to this library.
+/// * `Ron` -- structured data serialized to [RON](ron).
+/// * `Json` -- structured data serialized to JSON.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ContentKind {
+    Text,
+    Binary,
+    Ron,
+    Json,
+}
+
+impl Default for ContentKind {
+
+    fn default() -> Self {
+        ContentKind::Text
+    }
+}
+
+impl IntoBytes for ContentKind {
+
+    fn into_bytes(self) -> Bytes {
+
+        let value: u8 = match self {
+            ContentKind::Text => 1,
+            ContentKind::Binary => 2,
+            ContentKind::Ron => 3,
+            ContentKind::Json => 4,
+        };
+
+        Bytes::from_vec(vec![value])
+    }
+}
+
+impl FromBytes for ContentKind {
+
+    fn from_bytes(bytes: Bytes) -> Result<Self, NetCommsError>
+    where
+        Self: Sized {
+
+        Self::from_buff(&bytes.into_vec())
+    }
+
+    fn from_buff(buff: &[u8]) -> Result<Self, NetCommsError>
+    where
+        Self: Sized {
+
+        match buff.first() {
+            Some(1) => Ok(ContentKind::Text),
+            Some(2) => Ok(ContentKind::Binary),
+            Some(3) => Ok(ContentKind::Ron),
+            Some(4) => Ok(ContentKind::Json),
+            Some(value) => Err(NetCommsError::new(
+                NetCommsErrorKind::DeserializingFailed,
+                Some(format!("Unknown content kind {}.", value)))),
+            None => Err(NetCommsError::new(
+                NetCommsErrorKind::InvalidBufferSize,
+                Some("Content is missing its kind.".to_string()))),
+        }
+    }
+}
+
+/// Holds bytes of a [Message] together with [ContentKind] saying how they should be read.
+///
+/// Bytes are never converted, so any data, including binary, survive sending and storing in a database unchanged.
+///
+/// # Fields
+///
+/// * `kind` -- [ContentKind] of `data`.
+/// * `data` -- bytes of this content.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Content {
+    kind: ContentKind,
+    data: Vec<u8>,
+}
 
 impl Default for Content {
 
@@ -31,14 +108,22 @@ impl ToRon for Content {}
 impl Display for Content {
     
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Content({})", self.string_ref())
+        match self.kind {
+            ContentKind::Binary => write!(f, "Content(Binary, {} bytes)", self.data.len()),
+            kind => write!(f, "Content({:?}, {})", kind, String::from_utf8_lossy(&self.data)),
+        }
     }
 }
 
+/// Content is encoded as one byte of its [ContentKind] followed by its data.
 impl IntoBytes for Content {
 
     fn into_bytes(self) -> Bytes {
-        Bytes::from_vec(self.0.into_bytes())
+
+        let mut bytes = self.kind.into_bytes();
+        bytes.append(&mut Bytes::from_vec(self.data));
+
+        bytes
     }
 }
 
@@ -48,13 +133,26 @@ impl FromBytes for Content {
     where
         Self: Sized {
 
-        Ok(Content::with_data(bytes.to_string()))
+        Self::from_buff(&bytes.into_vec())
     }
 
     fn from_buff(buff: &[u8]) -> Result<Self, NetCommsError>
     where
         Self: Sized {
-        todo!()
+
+        let kind = ContentKind::from_buff(buff)?;
+        let data = buff[1..].to_vec();
+
+        // Text is checked right away, so it can be safely read as a String later.
+        if let ContentKind::Text | ContentKind::Ron | ContentKind::Json = kind {
+            if std::str::from_utf8(&data).is_err() {
+                return Err(NetCommsError::new(
+                    NetCommsErrorKind::DeserializingFailed,
+                    Some(format!("{:?} content is not valid UTF-8.", kind))));
+            }
+        }
+
+        Ok(Content { kind, data })
     }
 }
 
@@ -66,11 +164,28 @@ impl ContentType<'_, MetaData, Content> for Content {
         match metadata.file_name() {
             Some(file_name) => {
                 let path = Path::new(&file_name);
-                ImplementedMessage::send_file(stream, path)?;
+                // If the transfer is known, only the part the other side does not have yet is sent.
+                match metadata.transfer() {
+                    Some(file_transfer) => {
+                        // File can be stored somewhere else than its name says, for example in server blob store.
+                        let path = file_transfer.source().unwrap_or(path.to_path_buf());
+                        transfer::send_file_from(stream, &path, file_transfer.offset())?
+                    },
+                    None => transfer::send_file_from(stream, path, 0)?,
+                }
             }
             None => {
-                let bytes = self.0.as_bytes().to_vec().into_bytes();
-                ImplementedMessage::send_content(stream, bytes)?
+                let bytes = self.into_bytes();
+                let attachments = metadata.attachments();
+                if attachments.is_empty() {
+                    ImplementedMessage::send_content(stream, bytes)?
+                } else {
+                    // Text is followed by every attachment, each part ends with its own End packet.
+                    transfer::send_bytes(stream, bytes)?;
+                    for attachment in attachments {
+                        transfer::send_file_from(stream, &attachment.path().unwrap(), 0)?;
+                    }
+                }
             },
         }
 
@@ -81,53 +196,166 @@ impl ContentType<'_, MetaData, Content> for Content {
                metadata: &MetaData,
                path: Option<PathBuf>) -> Result<(Self, Packet), NetCommsError> {
 
-        let path = path.unwrap();
-        let path = metadata.get_message_location(&path);
+        Self::receive_limited(stream, metadata, path.unwrap(), None)
+    }
+}
+
+
+impl Content {
+
+    /// Receives [Content] like [ContentType::receive], every file is received into `location`.
+    ///
+    /// Files can not grow past their declared sizes and if `budget` is [Some],
+    /// no more than `budget` bytes of all files together are received.
+    ///
+    /// # Errors
+    ///
+    /// * Returns an error if any file exceeds its limit, the file is then removed.
+    pub fn receive_limited(stream: &mut TcpStream,
+                           metadata: &MetaData,
+                           location: PathBuf,
+                           budget: Option<u64>) -> Result<(Self, Packet), NetCommsError> {
+
+        let mut budget = budget.unwrap_or(u64::MAX);
 
         let (content, end_data) = match metadata.message_kind() {
             MessageKind::File => {
-                let (_, end_data) = ImplementedMessage::receive_file(
-                    stream,
-                    &path,
-                    metadata
-                                .file_name()
-                                .unwrap()
-                )?;
-                (Content::new(), end_data)
+                // File name was already changed to the path where should be this file saved by MetaData::receive.
+                let file_path = PathBuf::from(metadata.file_name().unwrap());
+                match metadata.transfer() {
+                    Some(file_transfer) => {
+                        // File is kept in staging directory until it is complete and verified,
+                        // so interrupted transfer can continue from where it stopped.
+                        let staging_path = file_transfer.staging_location(&location);
+                        // Sender continues from its offset, which is checked against what is already staged.
+                        let offset = file_transfer.offset();
+                        let max_size = file_transfer.size().min(offset.saturating_add(budget));
+                        let end_data = transfer::receive_file_into(stream, &staging_path, offset, max_size)?;
+                        if !transfer::finish_transfer(&file_transfer, &staging_path, &file_path)? {
+                            return Err(NetCommsError::new(
+                                NetCommsErrorKind::InvalidBufferSize,
+                                Some(format!("Transfer {} ended before whole file arrived.", file_transfer.id()))));
+                        }
+                        (Content::new(), end_data)
+                    },
+                    None => {
+                        // Without a transfer file can not be verified, so it is written directly where it belongs.
+                        let _ = fs::remove_file(&file_path);
+                        let max_size = metadata.declared_size().min(budget);
+                        let end_data = transfer::receive_file_into(stream, &file_path, 0, max_size)?;
+                        (Content::new(), end_data)
+                    },
+                }
+            }
+            _ if !metadata.attachments().is_empty() => {
+                let (bytes, mut end_data) = transfer::receive_bytes(stream)?;
+                // Attachment paths were already set by MetaData::receive.
+                for attachment in metadata.attachments() {
+                    let staging_path = attachment.staging_location(&location);
+                    // Anything left from previous unsuccessful attempt would be appended to.
+                    let _ = fs::remove_file(&staging_path);
+                    let max_size = attachment.size().min(budget);
+                    end_data = transfer::receive_file_into(stream, &staging_path, 0, max_size)?;
+                    budget = budget.saturating_sub(attachment.size());
+                    if !transfer::finish_file(&staging_path,
+                                              &attachment.path().unwrap(),
+                                              attachment.size(),
+                                              &attachment.digest())? {
+                        return Err(NetCommsError::new(
+                            NetCommsErrorKind::InvalidBufferSize,
+                            Some(format!("Attachment {} ended before whole file arrived.", attachment.name()))));
+                    }
+                }
+                (Content::from_bytes(bytes)?, end_data)
             }
             _ => {
                 let (bytes, end_data) = ImplementedMessage::receive_content(stream)?;
-                let content = Content::with_data(bytes.to_string());
+                let content = Content::from_bytes(bytes)?;
                 (content, end_data)
             }
         };
-        
+
         Ok((content, end_data))
     }
-}
-
-
-impl Content {
 
+    /// Creates an empty text [Content].
     pub fn new() -> Self {
-        Content(String::new())
+        Content {
+            kind: ContentKind::Text,
+            data: Vec::new(),
+        }
     }
 
+    /// Creates a text [Content] from given `data`.
     pub fn with_data(data: String) -> Self {
-        Content(data)
+        Content::text(data)
+    }
+
+    /// Creates a text [Content] from given `text`.
+    pub fn text(text: String) -> Self {
+        Content {
+            kind: ContentKind::Text,
+            data: text.into_bytes(),
+        }
+    }
+
+    /// Creates a binary [Content] from given `data`.
+    pub fn binary(data: Vec<u8>) -> Self {
+        Content {
+            kind: ContentKind::Binary,
+            data,
+        }
+    }
+
+    /// Creates a [Content] holding given `ron` string.
+    pub fn ron(ron: String) -> Self {
+        Content {
+            kind: ContentKind::Ron,
+            data: ron.into_bytes(),
+        }
+    }
+
+    /// Creates a [Content] holding given `json` string.
+    pub fn json(json: String) -> Self {
+        Content {
+            kind: ContentKind::Json,
+            data: json.into_bytes(),
+        }
     }
     
+    /// Appends `string` to data of this content.
     pub fn append_string(&mut self, string: String) {
-        for char in string.chars() {
-            self.0.push(char);
+        self.data.extend_from_slice(string.as_bytes());
+    }
+
+    /// Returns `kind`.
+    pub fn kind(&self) -> ContentKind {
+        self.kind
+    }
+
+    /// Returns reference to `data`.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns `data`.
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Returns [Some] with text of this content if it is not [Binary](ContentKind::Binary), otherwise [None].
+    pub fn as_text(&self) -> Option<&str> {
+        match self.kind {
+            ContentKind::Binary => None,
+            _ => std::str::from_utf8(&self.data).ok(),
         }
     }
     
+    /// Returns data of this content as a [String], invalid UTF-8 sequences are replaced.
     pub fn into_string(self) -> String {
-        self.0
-    }
-
-    pub fn string_ref<'a>(&'a self) -> &'a String {
-        &self.0
+        match String::from_utf8(self.data) {
+            Ok(string) => string,
+            Err(e) => String::from_utf8_lossy(e.as_bytes()).to_string(),
+        }
     }
 }
@@ -0,0 +1,77 @@
+use serde::{Serialize, Deserialize};
+
+use nardol::ron::{FromRon, ToRon};
+
+
+/// Holds data about a file that is waiting on server, sent instead of the file itself,
+/// so recipient can decide if it wants to download it with [Request::DownloadFile](crate::request::Request::DownloadFile).
+///
+/// # Fields
+///
+/// * `message_id` -- id of [Message](crate::message::Message) on server that holds this file.
+/// * `name` -- name of the file with its extension.
+/// * `size` -- size of the file in bytes.
+/// * `hash` -- SHA-256 hash of the file.
+/// * `attachment` -- [Option], if [Some] the file is attachment at this position inside its message
+/// and it is downloaded with [Request::DownloadAttachment](crate::request::Request::DownloadAttachment).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FileReference {
+    message_id: usize,
+    name: String,
+    size: u64,
+    hash: String,
+    #[serde(default)]
+    attachment: Option<usize>,
+}
+
+impl ToRon for FileReference {}
+impl FromRon<'_> for FileReference {}
+
+impl FileReference {
+
+    pub fn new(message_id: usize, name: String, size: u64, hash: String) -> Self {
+        FileReference {
+            message_id,
+            name,
+            size,
+            hash,
+            attachment: None,
+        }
+    }
+
+    /// Creates a [FileReference] to attachment at given `position` inside message with given id.
+    pub fn for_attachment(message_id: usize, position: usize, name: String, size: u64, hash: String) -> Self {
+        FileReference {
+            message_id,
+            name,
+            size,
+            hash,
+            attachment: Some(position),
+        }
+    }
+
+    /// Returns `message_id`.
+    pub fn message_id(&self) -> usize {
+        self.message_id
+    }
+
+    /// Returns `name`.
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Returns `size` in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns `hash`.
+    pub fn hash(&self) -> String {
+        self.hash.clone()
+    }
+
+    /// Returns `attachment` position.
+    pub fn attachment(&self) -> Option<usize> {
+        self.attachment
+    }
+}
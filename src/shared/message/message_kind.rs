@@ -12,6 +12,8 @@ pub enum MessageKind {
     Text,
     File,
     SeverReply,
+    FileReference,
+    Notification,
     Unknown,
 }
 
@@ -35,6 +37,8 @@ impl IntoBytes for MessageKind {
             MessageKind::Text => [2_u8, 0_u8],
             MessageKind::File => [3_u8, 0_u8],
             MessageKind::SeverReply => [4_u8, 0_u8],
+            MessageKind::FileReference => [5_u8, 0_u8],
+            MessageKind::Notification => [6_u8, 0_u8],
             MessageKind::Unknown => [255_u8, 0_u8],
         };
 
@@ -61,6 +65,8 @@ impl FromBytes for MessageKind {
             2 => MessageKind::Text,
             3 => MessageKind::File,
             4 => MessageKind::SeverReply,
+            5 => MessageKind::FileReference,
+            6 => MessageKind::Notification,
             _ => MessageKind::Unknown,            
         }; 
         
@@ -84,6 +90,8 @@ impl FromBytes for MessageKind {
             2 => MessageKind::Text,
             3 => MessageKind::File,
             4 => MessageKind::SeverReply,
+            5 => MessageKind::FileReference,
+            6 => MessageKind::Notification,
             _ => MessageKind::Unknown,            
         }; 
         
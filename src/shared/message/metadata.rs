@@ -1,5 +1,6 @@
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, NaiveDateTime, Utc};
+use rand::Rng;
 
 use std::io::Write;
 use std::path::{Path, PathBuf};
@@ -12,6 +13,9 @@ use nardol::ron::{ToRon, FromRon};
 use nardol::packet::{Packet, PacketKind};
 
 use super::message_kind::MessageKind;
+use super::attachment::Attachment;
+use super::transfer::FileTransfer;
+use crate::config::MAX_PACKET_SIZE;
 use crate::user::{User, UserLite};
 
 
@@ -30,6 +34,22 @@ use crate::user::{User, UserLite};
 /// * `recipients` -- [Vec] of usernames of recipients.
 /// * `file_name` -- [Option], if [Some] [MessageKind] is [File](MessageKind::File) and [String] inside holds a file name and
 /// file extension.
+/// * `transfer` -- [Option], if [Some] holds [FileTransfer] used to resume sending of a file that was interrupted.
+/// * `attachments` -- [Vec] of [attachments](Attachment) sent after text of [Message].
+/// * `group` -- [Option], if [Some] holds name of a group in which conversation this [Message] was sent,
+/// it is set by server.
+/// * `message_id` -- [Option], if [Some] holds id of [Message] on server, it is set by server when [Message] is sent
+/// to its recipients, so they can refer to it in later requests.
+/// * `edited` -- `true` if content of [Message] was changed by its author after it was sent.
+/// * `reply_to` -- [Option], if [Some] holds id of [Message] on server this [Message] replies to.
+/// * `send_at` -- [Option], if [Some] holds RFC 3339 datetime before which server does not deliver [Message].
+/// * `ttl` -- [Option], if [Some] holds number of seconds after which [Message] is deleted, on server from
+/// the moment it was sent and on client from the moment it was read.
+/// * `reactions` -- [Vec] of reactions to [Message] with number of users that reacted with each of them.
+/// * `device_id` -- [Option], if [Some] holds id of the device of the author that created [Message],
+/// it is assigned by server when the author logs in.
+/// * `uuid` -- [Option], if [Some] holds UUID generated by the author when [Message] was created,
+/// it does not change when [Message] is sent again, so server can recognize retried sends.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MetaData {
     message_kind: MessageKind,
@@ -40,6 +60,28 @@ pub struct MetaData {
     recipient_id: u32, 
     recipients: Vec<String>,
     file_name: Option<String>,  
+    #[serde(default)]
+    transfer: Option<FileTransfer>,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    #[serde(default)]
+    group: Option<String>,
+    #[serde(default)]
+    message_id: Option<usize>,
+    #[serde(default)]
+    edited: bool,
+    #[serde(default)]
+    reply_to: Option<usize>,
+    #[serde(default)]
+    reactions: Vec<(String, usize)>,
+    #[serde(default)]
+    send_at: Option<String>,
+    #[serde(default)]
+    ttl: Option<u64>,
+    #[serde(default)]
+    device_id: Option<usize>,
+    #[serde(default)]
+    uuid: Option<String>,
 }
 
 impl Default for MetaData {
@@ -57,6 +99,17 @@ impl Default for MetaData {
             recipient_id: 0,
             recipients: vec![],
             file_name: None,
+            transfer: None,
+            attachments: Vec::new(),
+            group: None,
+            message_id: None,
+            edited: false,
+            reply_to: None,
+            reactions: Vec::new(),
+            send_at: None,
+            ttl: None,
+            device_id: None,
+            uuid: None,
         }
     }
 }
@@ -158,9 +211,31 @@ impl MetaDataType<'_> for MetaData {
             }
         }
         let mut metadata = MetaData::from_bytes(metadata)?;
-        if let Some(_) = metadata.file_name() {
-            metadata.set_file_name(Some(location.unwrap().to_string_lossy().to_string()))
-        };
+        if let Some(location) = location {
+            // File is saved under its original name inside location of this message.
+            if let Some(file_name) = metadata.file_name() {
+                let mut path = metadata.get_message_location(&location);
+                // Files that arrive in the same second are kept apart by the beginning of their hash.
+                if let Some(transfer) = metadata.transfer() {
+                    let hash = transfer.hash();
+                    path.push(hash.get(..16).unwrap_or(&hash));
+                }
+                if let Some(name) = Path::new(&file_name).file_name() {
+                    path.push(name);
+                }
+                metadata.set_file_name(Some(path.to_string_lossy().to_string()))
+            }
+
+            // Attachments are saved the same way.
+            let message_location = metadata.get_message_location(&location);
+            for attachment in metadata.attachments.iter_mut() {
+                let digest = attachment.digest();
+                let mut path = message_location.clone();
+                path.push(digest.get(..16).unwrap_or(&digest));
+                path.push(Path::new(&attachment.name()).file_name().unwrap_or_default());
+                attachment.set_path(Some(path));
+            }
+        }
 
         Ok(metadata)
     }
@@ -196,6 +271,17 @@ impl MetaData {
             recipient_id,
             recipients,
             file_name,
+            transfer: None,
+            attachments: Vec::new(),
+            group: None,
+            message_id: None,
+            edited: false,
+            reply_to: None,
+            reactions: Vec::new(),
+            send_at: None,
+            ttl: None,
+            device_id: author.device_id(),
+            uuid: Some(Self::generate_uuid()),
         };
 
         let metadata = temp_metadata.with_content_length(content.len());
@@ -235,6 +321,17 @@ impl MetaData {
             recipient_id: 0,
             recipients: vec![],
             file_name: None,
+            transfer: None,
+            attachments: Vec::new(),
+            group: None,
+            message_id: None,
+            edited: false,
+            reply_to: None,
+            reactions: Vec::new(),
+            send_at: None,
+            ttl: None,
+            device_id: None,
+            uuid: None,
         })
     }
 
@@ -256,6 +353,17 @@ impl MetaData {
             recipient_id,
             recipients,
             file_name,
+            transfer: None,
+            attachments: Vec::new(),
+            group: None,
+            message_id: None,
+            edited: false,
+            reply_to: None,
+            reactions: Vec::new(),
+            send_at: None,
+            ttl: None,
+            device_id: None,
+            uuid: None,
         }
     }
 
@@ -284,6 +392,22 @@ impl MetaData {
         self.message_length 
     }
 
+    /// Returns declared size of [Message] in bytes, that is estimated size of its content
+    /// from `message_length` together with sizes of its file and attachments.
+    pub fn declared_size(&self) -> u64 {
+
+        let content_size = self.message_length as u64 * MAX_PACKET_SIZE;
+        let file_size = match &self.transfer {
+            Some(transfer) => transfer.size(),
+            None => 0,
+        };
+        let attachments_size: u64 = self.attachments.iter()
+                                                    .map(|attachment| attachment.size())
+                                                    .sum();
+
+        content_size + file_size + attachments_size
+    }
+
     /// Returns [DateTime<Utc>].
     /// 
     /// # Errors
@@ -335,6 +459,66 @@ impl MetaData {
         self.file_name.clone()
     }
 
+    /// Returns a `transfer`.
+    pub fn transfer(&self) -> Option<FileTransfer> {
+        self.transfer.clone()
+    }
+
+    /// Returns `attachments`.
+    pub fn attachments(&self) -> Vec<Attachment> {
+        self.attachments.clone()
+    }
+
+    /// Returns `group`.
+    pub fn group(&self) -> Option<String> {
+        self.group.clone()
+    }
+
+    /// Returns `message_id`.
+    pub fn message_id(&self) -> Option<usize> {
+        self.message_id
+    }
+
+    /// Returns `edited`.
+    pub fn edited(&self) -> bool {
+        self.edited
+    }
+
+    /// Returns `reply_to`.
+    pub fn reply_to(&self) -> Option<usize> {
+        self.reply_to
+    }
+
+    /// Returns `reactions`.
+    pub fn reactions(&self) -> Vec<(String, usize)> {
+        self.reactions.clone()
+    }
+
+    /// Returns `send_at`.
+    pub fn send_at(&self) -> Option<String> {
+        self.send_at.clone()
+    }
+
+    /// Returns `ttl`.
+    pub fn ttl(&self) -> Option<u64> {
+        self.ttl
+    }
+
+    /// Returns `device_id`.
+    pub fn device_id(&self) -> Option<usize> {
+        self.device_id
+    }
+
+    /// Returns `uuid`.
+    pub fn uuid(&self) -> Option<String> {
+        self.uuid.clone()
+    }
+
+    /// Sets [MessageKind].
+    pub fn set_message_kind(&mut self, message_kind: MessageKind) {
+        self.message_kind = message_kind;
+    }
+
     /// Sets `message_length`.
     pub fn set_message_length(&mut self, length: u32) {
         self.message_length = length;
@@ -360,6 +544,74 @@ impl MetaData {
         self.file_name = name;
     }
 
+    /// Sets `transfer`.
+    pub fn set_transfer(&mut self, transfer: Option<FileTransfer>) {
+        self.transfer = transfer;
+    }
+
+    /// Sets `attachments`.
+    pub fn set_attachments(&mut self, attachments: Vec<Attachment>) {
+        self.attachments = attachments;
+    }
+
+    /// Sets `group`.
+    pub fn set_group(&mut self, group: Option<String>) {
+        self.group = group;
+    }
+
+    /// Sets `message_id`.
+    pub fn set_message_id(&mut self, message_id: Option<usize>) {
+        self.message_id = message_id;
+    }
+
+    /// Sets `edited`.
+    pub fn set_edited(&mut self, edited: bool) {
+        self.edited = edited;
+    }
+
+    /// Sets `reply_to`.
+    pub fn set_reply_to(&mut self, reply_to: Option<usize>) {
+        self.reply_to = reply_to;
+    }
+
+    /// Sets `reactions`.
+    pub fn set_reactions(&mut self, reactions: Vec<(String, usize)>) {
+        self.reactions = reactions;
+    }
+
+    /// Sets `send_at`.
+    pub fn set_send_at(&mut self, send_at: Option<String>) {
+        self.send_at = send_at;
+    }
+
+    /// Sets `ttl`.
+    pub fn set_ttl(&mut self, ttl: Option<u64>) {
+        self.ttl = ttl;
+    }
+
+    /// Sets `device_id`.
+    pub fn set_device_id(&mut self, device_id: Option<usize>) {
+        self.device_id = device_id;
+    }
+
+    /// Sets `uuid`.
+    pub fn set_uuid(&mut self, uuid: Option<String>) {
+        self.uuid = uuid;
+    }
+
+    /// Internal method used in [MetaData::new] to get a random version 4 UUID in its hyphenated form.
+    fn generate_uuid() -> String {
+
+        let mut bytes: [u8; 16] = rand::thread_rng().gen();
+        // Version 4 and RFC 4122 variant bits.
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+        let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+        format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+    }
+
     /// Internal method used in [MetaData::new] and [MetaData::new_empty] to get current [[DateTime<Utc>]].
     fn current_datetime() -> DateTime<Utc> {
     
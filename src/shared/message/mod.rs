@@ -1,11 +1,19 @@
+mod attachment;
 mod content;
+mod file_reference;
 mod message_kind;
 mod metadata;
+mod notification;
 mod request;
 mod server_reply;
+pub mod transfer;
 
-pub use content::Content;
+pub use attachment::Attachment;
+pub use content::{Content, ContentKind};
+pub use file_reference::FileReference;
 pub use message_kind::MessageKind;
 pub use metadata::MetaData;
-pub use request::{Request, RequestRaw};
-pub use server_reply::{ServerReply, ServerReplyRaw};
\ No newline at end of file
+pub use notification::{Notification, ReceiptKind};
+pub use request::{DeleteScope, Request, RequestRaw};
+pub use server_reply::{ServerError, ServerReply, ServerReplyRaw};
+pub use transfer::FileTransfer;
\ No newline at end of file
@@ -0,0 +1,117 @@
+use serde::{Serialize, Deserialize};
+
+use nardol::bytes::{Bytes, IntoBytes, FromBytes};
+use nardol::error::NetCommsError;
+use nardol::packet::{Packet, PacketKind};
+use nardol::ron::{FromRon, ToRon};
+
+use crate::ImplementedMessage;
+use crate::user::{PresenceInfo, UserLite};
+
+use super::{Content, MessageKind, MetaData};
+
+
+/// State of a [Message](crate::message::Message) reported back to its author by [Notification::Receipt].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReceiptKind {
+    /// Recipient received the message from server.
+    Delivered,
+    /// Recipient acknowledged that it read the message.
+    Read,
+}
+
+impl std::fmt::Display for ReceiptKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReceiptKind::Delivered => write!(f, "delivered"),
+            ReceiptKind::Read => write!(f, "read"),
+        }
+    }
+}
+
+/// Changes of already sent [messages](crate::message::Message) that server queues for their recipients,
+/// they are sent inside [Message](crate::message::Message) of [MessageKind::Notification].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Notification {
+    /// Message with given id was edited by its author, holds its new [Content] and
+    /// RFC 3339 datetime of the edit.
+    Edited {
+        message_id: usize,
+        content: Content,
+        datetime: String,
+    },
+    /// Message with given id was deleted for this recipient, its copy should be hidden.
+    Deleted {
+        message_id: usize,
+    },
+    /// User with given username added or removed given reaction to message with given id,
+    /// holds every reaction to that message with number of users that reacted with it.
+    Reaction {
+        message_id: usize,
+        username: String,
+        reaction: String,
+        added: bool,
+        reactions: Vec<(String, usize)>,
+    },
+    /// Message with given id sent by this user was delivered to or read by user with given username,
+    /// holds RFC 3339 datetime of when it happened.
+    Receipt {
+        message_id: usize,
+        username: String,
+        kind: ReceiptKind,
+        datetime: String,
+    },
+    /// Presence or status message of a user this user subscribed to changed.
+    Presence(PresenceInfo),
+    /// User with given username is writing a message to this user, or to a group with given name if [Some].
+    ///
+    /// Server never stores it, it is only sent if this user asks for waiting messages soon enough.
+    Typing {
+        username: String,
+        group: Option<String>,
+    },
+    /// Message of the day set by server administrator, it is sent after every successful login.
+    MessageOfTheDay(String),
+}
+
+impl ToRon for Notification {}
+impl FromRon<'_> for Notification {}
+
+impl Notification {
+
+    /// Creates a [Message](ImplementedMessage) from server to given `recipient` holding this [Notification].
+    pub fn into_message(self, recipient: &UserLite) -> Result<ImplementedMessage, NetCommsError> {
+
+        let mut message = ImplementedMessage::new();
+
+        let content = Content::ron(self.to_ron()?);
+        let content_buff = content.into_bytes();
+
+        let metadata = MetaData::new(&content_buff, MessageKind::Notification,
+                                     UserLite::default_server(),
+                                     recipient.id(), vec![recipient.username()],
+                                     None)?;
+        message.set_metadata(metadata);
+
+        message.set_content(Content::from_bytes(content_buff)?);
+
+        let end_data = Packet::new(PacketKind::End, Bytes::new());
+        message.set_end_data(end_data);
+
+        Ok(message)
+    }
+
+    /// Creates a [Message](ImplementedMessage) like [Notification::into_message] for a [Notification]
+    /// queued on server with given id, which is stored as message id in its metadata,
+    /// recipient acknowledges it by this id with [Request::AckNotifications](crate::request::Request::AckNotifications).
+    pub fn into_queued_message(self, notification_id: usize, recipient: &UserLite) -> Result<ImplementedMessage, NetCommsError> {
+
+        let mut message = self.into_message(recipient)?;
+
+        let mut metadata = message.metadata();
+        metadata.set_message_id(Some(notification_id));
+        message.set_metadata(metadata);
+
+        Ok(message)
+    }
+}
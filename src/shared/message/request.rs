@@ -7,12 +7,23 @@ use nardol::error::NetCommsError;
 use nardol::packet::{Packet, PacketKind};
 
 use crate::config::{SERVER_ID, SERVER_USERNAME};
+use crate::group::GroupRole;
 use crate::message::{MessageKind, MetaData, Content};
 use crate::user::{User, UserLite, UserUnchecked};
 
 use crate::ImplementedMessage;
 
 
+/// Decides who a [Message](crate::message::Message) is deleted for by [Request::DeleteMessage].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum DeleteScope {
+    /// Message is hidden only for requesting user.
+    ForMe,
+    /// Message is deleted on server and recipients that already have it get a tombstone,
+    /// only its author or a moderator of its group can do that.
+    ForEveryone,
+}
+
 /// Holds data about requests from client to server.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Request {
@@ -25,6 +36,149 @@ pub enum Request {
     /// Request to get any [messages](crate::message::Message) that were sent to requesting client.
     GetWaitingMessagesAuto,
 
+    /// Request to get how many bytes of [FileTransfer](crate::message::FileTransfer) with given id server already has.
+    GetTransferOffset(String),
+
+    /// Request to send again a file from [FileTransfer](crate::message::FileTransfer) with given id,
+    /// starting from given offset in bytes.
+    ResumeTransfer(String, u64),
+
+    /// Request to download a file from [Message](crate::message::Message) with given id,
+    /// that was announced by [FileReference](crate::message::FileReference).
+    DownloadFile(usize),
+
+    /// Request to download attachment at given position of [Message](crate::message::Message) with given id,
+    /// that was announced by [FileReference](crate::message::FileReference).
+    DownloadAttachment(usize, usize),
+
+    /// Request to get how many bytes are stored on server for requesting user and what is its quota.
+    GetStorageUsage,
+
+    /// Request to create a group with given name, requesting user becomes its first member.
+    CreateGroup(String),
+
+    /// Request to add user with given username to a group with given name,
+    /// requesting user needs to be a member of that group.
+    InviteToGroup(String, String),
+
+    /// Request to leave a group with given name.
+    LeaveGroup(String),
+
+    /// Request to get usernames of all members of a group with given name.
+    GetGroupMembers(String),
+
+    /// Request to remove user with given username from a group with given name, requires a moderator.
+    RemoveFromGroup(String, String),
+
+    /// Request to mute, if [bool] is `true`, or unmute user with given username inside a group with given name,
+    /// requires a moderator.
+    MuteInGroup(String, String, bool),
+
+    /// Request to set [GroupRole] of user with given username inside a group with given name, requires an owner.
+    SetGroupRole(String, String, GroupRole),
+
+    /// Request to rename a group with given name to a new name, requires a moderator.
+    RenameGroup(String, String),
+
+    /// Request to delete a [Message](crate::message::Message) with given id sent to a group with given name,
+    /// requires a moderator if requesting user is not its author.
+    DeleteGroupMessage(String, usize),
+
+    /// Request to replace content of a [Message](crate::message::Message) with given id,
+    /// only its author can do that.
+    EditMessage {
+        id: usize,
+        new_content: Content,
+    },
+
+    /// Request to delete a [Message](crate::message::Message) with given id for given [DeleteScope].
+    DeleteMessage {
+        id: usize,
+        scope: DeleteScope,
+    },
+
+    /// Request to get every [Message](crate::message::Message) of a thread that message with given id belongs to,
+    /// server answers with [ServerReply::Messages](crate::message::ServerReply::Messages).
+    GetThread(usize),
+
+    /// Request to add or remove, if `add` is `false`, a reaction, for example an emoji,
+    /// to a [Message](crate::message::Message) with given id.
+    React {
+        id: usize,
+        reaction: String,
+        add: bool,
+    },
+
+    /// Acknowledges that device of user stored [messages](crate::message::Message) with given ids,
+    /// they stop waiting for it, the others are sent again next time it asks for waiting messages.
+    AckMessages(Vec<usize>),
+
+    /// Acknowledges that device of user processed [notifications](crate::message::Notification) with given ids,
+    /// they stop waiting for it, the others are sent again next time it asks for waiting messages.
+    AckNotifications(Vec<usize>),
+
+    /// Acknowledges that user read [messages](crate::message::Message) with given ids,
+    /// their authors get a read receipt unless user turned them off.
+    MarkRead(Vec<usize>),
+
+    /// Request to turn sending of read receipts of requesting user on or off.
+    SetReadReceipts(bool),
+
+    /// Request to get [presence](crate::user::PresenceInfo) of user with given username now
+    /// and every time it changes later.
+    SubscribePresence(String),
+
+    /// Request to stop getting presence of user with given username.
+    UnsubscribePresence(String),
+
+    /// Request to get presence of every user requesting user subscribed to.
+    GetPresence,
+
+    /// Request to set status message of requesting user, [None] clears it.
+    SetStatus(Option<String>),
+
+    /// Request to get every device requesting user is logged in from.
+    GetDevices,
+
+    /// Request to revoke device of requesting user with given id, it stops receiving messages and has to log in again.
+    RevokeDevice(usize),
+
+    /// Tells online recipients, usernames or a single group, that requesting user is writing a message to them.
+    Typing(Vec<String>),
+
+    /// Request to get every scheduled [Message](crate::message::Message) of requesting user that was not sent yet,
+    /// server answers with [ServerReply::Messages](crate::message::ServerReply::Messages).
+    GetScheduledMessages,
+
+    /// Request to cancel scheduled [Message](crate::message::Message) with given id before it is sent.
+    CancelScheduledMessage(usize),
+
+    /// Request to get [messages](crate::message::Message) of a conversation with a user or a group in `peer_or_group`
+    /// that requesting user sent or received, server answers with
+    /// [ServerReply::Messages](crate::message::ServerReply::Messages) with the newest `limit` of them older than
+    /// message with id `before_id`, oldest first.
+    GetHistory {
+        peer_or_group: String,
+        before_id: Option<usize>,
+        limit: Option<usize>,
+    },
+
+    /// Request to find [messages](crate::message::Message) requesting user sent or received whose text contains
+    /// every word of `query`, newest first, server answers with
+    /// [ServerReply::Messages](crate::message::ServerReply::Messages).
+    ///
+    /// Results can be limited to a conversation with a `peer`, a username or a group, and to messages sent
+    /// at or after RFC 3339 datetime `from` and before `to`. Only `limit` of them are returned after skipping `offset`.
+    Search {
+        query: String,
+        peer: Option<String>,
+        from: Option<String>,
+        to: Option<String>,
+        limit: Option<usize>,
+        #[serde(default)]
+        offset: usize,
+    },
+
     /// Used if some method fails to recognize the [Request].
     Unknown,    
 }
@@ -42,6 +196,153 @@ pub enum RequestRaw {
     /// Request to get any [messages](crate::message::Message) that were sent to requesting client.
     GetWaitingMessagesAuto(UserLite),
 
+    /// Request to get how many bytes of [FileTransfer](crate::message::FileTransfer) with given id server already has.
+    GetTransferOffset(String, UserLite),
+
+    /// Request to send again a file from [FileTransfer](crate::message::FileTransfer) with given id,
+    /// starting from given offset in bytes.
+    ResumeTransfer(String, u64, UserLite),
+
+    /// Request to download a file from [Message](crate::message::Message) with given id,
+    /// that was announced by [FileReference](crate::message::FileReference).
+    DownloadFile(usize, UserLite),
+
+    /// Request to download attachment at given position of [Message](crate::message::Message) with given id,
+    /// that was announced by [FileReference](crate::message::FileReference).
+    DownloadAttachment(usize, usize, UserLite),
+
+    /// Request to get how many bytes are stored on server for requesting user and what is its quota.
+    GetStorageUsage(UserLite),
+
+    /// Request to create a group with given name, requesting user becomes its first member.
+    CreateGroup(String, UserLite),
+
+    /// Request to add user with given username to a group with given name,
+    /// requesting user needs to be a member of that group.
+    InviteToGroup(String, String, UserLite),
+
+    /// Request to leave a group with given name.
+    LeaveGroup(String, UserLite),
+
+    /// Request to get usernames of all members of a group with given name.
+    GetGroupMembers(String, UserLite),
+
+    /// Request to remove user with given username from a group with given name, requires a moderator.
+    RemoveFromGroup(String, String, UserLite),
+
+    /// Request to mute, if [bool] is `true`, or unmute user with given username inside a group with given name,
+    /// requires a moderator.
+    MuteInGroup(String, String, bool, UserLite),
+
+    /// Request to set [GroupRole] of user with given username inside a group with given name, requires an owner.
+    SetGroupRole(String, String, GroupRole, UserLite),
+
+    /// Request to rename a group with given name to a new name, requires a moderator.
+    RenameGroup(String, String, UserLite),
+
+    /// Request to delete a [Message](crate::message::Message) with given id sent to a group with given name,
+    /// requires a moderator if requesting user is not its author.
+    DeleteGroupMessage(String, usize, UserLite),
+
+    /// Request to replace content of a [Message](crate::message::Message) with given id,
+    /// only its author can do that.
+    EditMessage {
+        id: usize,
+        new_content: Content,
+        author: UserLite,
+    },
+
+    /// Request to delete a [Message](crate::message::Message) with given id for given [DeleteScope].
+    DeleteMessage {
+        id: usize,
+        scope: DeleteScope,
+        author: UserLite,
+    },
+
+    /// Request to get every [Message](crate::message::Message) of a thread that message with given id belongs to,
+    /// server answers with [ServerReply::Messages](crate::message::ServerReply::Messages).
+    GetThread(usize, UserLite),
+
+    /// Request to add or remove, if `add` is `false`, a reaction, for example an emoji,
+    /// to a [Message](crate::message::Message) with given id.
+    React {
+        id: usize,
+        reaction: String,
+        add: bool,
+        author: UserLite,
+    },
+
+    /// Acknowledges that device of user stored [messages](crate::message::Message) with given ids,
+    /// they stop waiting for it, the others are sent again next time it asks for waiting messages.
+    AckMessages(Vec<usize>, UserLite),
+
+    /// Acknowledges that device of user processed [notifications](crate::message::Notification) with given ids,
+    /// they stop waiting for it, the others are sent again next time it asks for waiting messages.
+    AckNotifications(Vec<usize>, UserLite),
+
+    /// Acknowledges that user read [messages](crate::message::Message) with given ids,
+    /// their authors get a read receipt unless user turned them off.
+    MarkRead(Vec<usize>, UserLite),
+
+    /// Request to turn sending of read receipts of requesting user on or off.
+    SetReadReceipts(bool, UserLite),
+
+    /// Request to get [presence](crate::user::PresenceInfo) of user with given username now
+    /// and every time it changes later.
+    SubscribePresence(String, UserLite),
+
+    /// Request to stop getting presence of user with given username.
+    UnsubscribePresence(String, UserLite),
+
+    /// Request to get presence of every user requesting user subscribed to.
+    GetPresence(UserLite),
+
+    /// Request to set status message of requesting user, [None] clears it.
+    SetStatus(Option<String>, UserLite),
+
+    /// Request to get every device requesting user is logged in from.
+    GetDevices(UserLite),
+
+    /// Request to revoke device of requesting user with given id, it stops receiving messages and has to log in again.
+    RevokeDevice(usize, UserLite),
+
+    /// Tells online recipients, usernames or a single group, that requesting user is writing a message to them.
+    Typing(Vec<String>, UserLite),
+
+    /// Request to get every scheduled [Message](crate::message::Message) of requesting user that was not sent yet,
+    /// server answers with [ServerReply::Messages](crate::message::ServerReply::Messages).
+    GetScheduledMessages(UserLite),
+
+    /// Request to cancel scheduled [Message](crate::message::Message) with given id before it is sent.
+    CancelScheduledMessage(usize, UserLite),
+
+    /// Request to get [messages](crate::message::Message) of a conversation with a user or a group in `peer_or_group`
+    /// that requesting user sent or received, server answers with
+    /// [ServerReply::Messages](crate::message::ServerReply::Messages) with the newest `limit` of them older than
+    /// message with id `before_id`, oldest first.
+    GetHistory {
+        peer_or_group: String,
+        before_id: Option<usize>,
+        limit: Option<usize>,
+        author: UserLite,
+    },
+
+    /// Request to find [messages](crate::message::Message) requesting user sent or received whose text contains
+    /// every word of `query`, newest first, server answers with
+    /// [ServerReply::Messages](crate::message::ServerReply::Messages).
+    ///
+    /// Results can be limited to a conversation with a `peer`, a username or a group, and to messages sent
+    /// at or after RFC 3339 datetime `from` and before `to`. Only `limit` of them are returned after skipping `offset`.
+    Search {
+        query: String,
+        peer: Option<String>,
+        from: Option<String>,
+        to: Option<String>,
+        limit: Option<usize>,
+        offset: usize,
+        author: UserLite,
+    },
+
     /// Used if some method fails to recognize the [Request].
     Unknown(UserLite),    
 }
@@ -54,11 +355,48 @@ impl IntoMessage<'_, MetaData, Content> for RequestRaw {
             RequestRaw::Login(user_unchecked, author) => (Request::Login(user_unchecked), author),
             RequestRaw::Register(user_unchecked, author) => (Request::Register(user_unchecked), author),
             RequestRaw::GetWaitingMessagesAuto(author) => (Request::GetWaitingMessagesAuto, author),
+            RequestRaw::GetTransferOffset(id, author) => (Request::GetTransferOffset(id), author),
+            RequestRaw::ResumeTransfer(id, offset, author) => (Request::ResumeTransfer(id, offset), author),
+            RequestRaw::DownloadFile(id, author) => (Request::DownloadFile(id), author),
+            RequestRaw::DownloadAttachment(id, position, author) => (Request::DownloadAttachment(id, position), author),
+            RequestRaw::GetStorageUsage(author) => (Request::GetStorageUsage, author),
+            RequestRaw::CreateGroup(name, author) => (Request::CreateGroup(name), author),
+            RequestRaw::InviteToGroup(name, username, author) => (Request::InviteToGroup(name, username), author),
+            RequestRaw::LeaveGroup(name, author) => (Request::LeaveGroup(name), author),
+            RequestRaw::GetGroupMembers(name, author) => (Request::GetGroupMembers(name), author),
+            RequestRaw::RemoveFromGroup(name, username, author) => (Request::RemoveFromGroup(name, username), author),
+            RequestRaw::MuteInGroup(name, username, muted, author) => (Request::MuteInGroup(name, username, muted), author),
+            RequestRaw::SetGroupRole(name, username, role, author) => (Request::SetGroupRole(name, username, role), author),
+            RequestRaw::RenameGroup(name, new_name, author) => (Request::RenameGroup(name, new_name), author),
+            RequestRaw::DeleteGroupMessage(name, id, author) => (Request::DeleteGroupMessage(name, id), author),
+            RequestRaw::EditMessage { id, new_content, author } => (Request::EditMessage { id, new_content }, author),
+            RequestRaw::DeleteMessage { id, scope, author } => (Request::DeleteMessage { id, scope }, author),
+            RequestRaw::GetThread(id, author) => (Request::GetThread(id), author),
+            RequestRaw::React { id, reaction, add, author } => (Request::React { id, reaction, add }, author),
+            RequestRaw::AckMessages(ids, author) => (Request::AckMessages(ids), author),
+            RequestRaw::AckNotifications(ids, author) => (Request::AckNotifications(ids), author),
+            RequestRaw::MarkRead(ids, author) => (Request::MarkRead(ids), author),
+            RequestRaw::SetReadReceipts(enabled, author) => (Request::SetReadReceipts(enabled), author),
+            RequestRaw::SubscribePresence(username, author) => (Request::SubscribePresence(username), author),
+            RequestRaw::UnsubscribePresence(username, author) => (Request::UnsubscribePresence(username), author),
+            RequestRaw::GetPresence(author) => (Request::GetPresence, author),
+            RequestRaw::SetStatus(status, author) => (Request::SetStatus(status), author),
+            RequestRaw::GetDevices(author) => (Request::GetDevices, author),
+            RequestRaw::RevokeDevice(id, author) => (Request::RevokeDevice(id), author),
+            RequestRaw::Typing(recipients, author) => (Request::Typing(recipients), author),
+            RequestRaw::GetScheduledMessages(author) => (Request::GetScheduledMessages, author),
+            RequestRaw::CancelScheduledMessage(id, author) => (Request::CancelScheduledMessage(id), author),
+            RequestRaw::GetHistory { peer_or_group, before_id, limit, author } => {
+                (Request::GetHistory { peer_or_group, before_id, limit }, author)
+            },
+            RequestRaw::Search { query, peer, from, to, limit, offset, author } => {
+                (Request::Search { query, peer, from, to, limit, offset }, author)
+            },
             RequestRaw::Unknown(author) => (Request::Unknown, author),
         };
 
         let mut message = ImplementedMessage::new();
-        let content = Content::with_data(request.to_ron()?);
+        let content = Content::ron(request.to_ron()?);
         let content_buff = content.into_bytes();
 
         // Recipient of Request will always be a server.
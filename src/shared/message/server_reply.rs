@@ -7,6 +7,8 @@ use nardol::prelude::ToRon;
 use nardol::prelude::Packet;
 use nardol::prelude::PacketKind;
 use serde::{Serialize, Deserialize};
+
+use std::fmt::Display;
 use ron::ser;
 use ron::de;
 
@@ -18,9 +20,69 @@ use crate::MessageKind;
 use crate::MetaData;
 use crate::config::SERVER_ID;
 use crate::config::SERVER_USERNAME;
+use crate::group::GroupMember;
+use crate::user::{DeviceInfo, PresenceInfo};
 use crate::user::User;
 use crate::user::UserLite;
 
+/// Errors that server can return inside [ServerReply::Rejected], so client can recover from them.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ServerError {
+    /// Declared size of a message in bytes is bigger than the limit set on server.
+    MessageTooLarge {
+        size: u64,
+        limit: u64,
+    },
+    /// Storing a message would exceed storage quota of its author, all sizes are in bytes.
+    QuotaExceeded {
+        usage: u64,
+        size: u64,
+        quota: u64,
+    },
+    /// Message was sent to a group its author is not a member of.
+    NotGroupMember {
+        group: String,
+    },
+    /// Author of a message was muted inside the group it sent the message to.
+    MutedInGroup {
+        group: String,
+    },
+    /// Message replies to a message that does not exist or its author can not see.
+    MessageNotFound {
+        id: usize,
+    },
+    /// Request of the same kind was sent again too soon, it can be retried after given number of seconds.
+    RateLimited {
+        retry_after: u64,
+    },
+}
+
+impl Display for ServerError {
+
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerError::MessageTooLarge { size, limit } => {
+                write!(f, "Message has {} bytes, but the limit is {} bytes.", size, limit)
+            },
+            ServerError::QuotaExceeded { usage, size, quota } => {
+                write!(f, "Message has {} bytes, but only {} of {} bytes are left.", size, quota.saturating_sub(*usage), quota)
+            },
+            ServerError::NotGroupMember { group } => {
+                write!(f, "You are not a member of group {}.", group)
+            },
+            ServerError::MutedInGroup { group } => {
+                write!(f, "You were muted in group {}.", group)
+            },
+            ServerError::MessageNotFound { id } => {
+                write!(f, "Message {} does not exist.", id)
+            },
+            ServerError::RateLimited { retry_after } => {
+                write!(f, "You are doing this too often, try again in {} seconds.", retry_after)
+            },
+        }
+    }
+}
+
 /// Enum of all possible replies from server to client.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ServerReply {
@@ -28,6 +90,28 @@ pub enum ServerReply {
     Error(String), // Later this string should be changed to use some kind of error enum, so client can recover from it.
     /// Used when there was a successful [Request::Register](crate::request::Request::Register) or [Request::Login](crate::request::Request::Login).
     User(UserLite),
+    /// Used as an answer to [Request::GetTransferOffset](crate::request::Request::GetTransferOffset),
+    /// holds id of the transfer and number of bytes server already has.
+    TransferOffset(String, u64),
+    /// Used when server refused to accept a [Message](crate::message::Message) or to fulfill a request.
+    Rejected(ServerError),
+    /// Used as an answer to [Request::GetStorageUsage](crate::request::Request::GetStorageUsage),
+    /// holds number of bytes used by user and its quota if there is any.
+    StorageUsage(u64, Option<u64>),
+    /// Used when a request was fulfilled and there is nothing else to return, [String] inside describes what was done.
+    Success(String),
+    /// Used as an answer to [Request::GetGroupMembers](crate::request::Request::GetGroupMembers),
+    /// holds name of the group and its members.
+    GroupMembers(String, Vec<GroupMember>),
+    /// Used when server answers with multiple [messages](crate::message::Message), holds their number,
+    /// they are sent right after this reply.
+    Messages(usize),
+    /// Used as an answer to [Request::GetPresence](crate::request::Request::GetPresence)
+    /// and [Request::SubscribePresence](crate::request::Request::SubscribePresence), holds presence of users.
+    Presence(Vec<PresenceInfo>),
+    /// Used as an answer to [Request::GetDevices](crate::request::Request::GetDevices),
+    /// holds every device user is logged in from.
+    Devices(Vec<DeviceInfo>),
 }
 
 impl ToRon for ServerReply {}
@@ -38,6 +122,28 @@ pub enum ServerReplyRaw {
     Error(String, UserLite), // Later this string should be changed to use some kind of error enum, so client can recover from it.
     /// Used when there was a successful [Request::Register](crate::request::Request::Register) or [Request::Login](crate::request::Request::Login).
     User(UserLite, UserLite),
+    /// Used as an answer to [Request::GetTransferOffset](crate::request::Request::GetTransferOffset),
+    /// holds id of the transfer and number of bytes server already has.
+    TransferOffset(String, u64, UserLite),
+    /// Used when server refused to accept a [Message](crate::message::Message) or to fulfill a request.
+    Rejected(ServerError, UserLite),
+    /// Used as an answer to [Request::GetStorageUsage](crate::request::Request::GetStorageUsage),
+    /// holds number of bytes used by user and its quota if there is any.
+    StorageUsage(u64, Option<u64>, UserLite),
+    /// Used when a request was fulfilled and there is nothing else to return, [String] inside describes what was done.
+    Success(String, UserLite),
+    /// Used as an answer to [Request::GetGroupMembers](crate::request::Request::GetGroupMembers),
+    /// holds name of the group and its members.
+    GroupMembers(String, Vec<GroupMember>, UserLite),
+    /// Used when server answers with multiple [messages](crate::message::Message), holds their number,
+    /// they are sent right after this reply.
+    Messages(usize, UserLite),
+    /// Used as an answer to [Request::GetPresence](crate::request::Request::GetPresence)
+    /// and [Request::SubscribePresence](crate::request::Request::SubscribePresence), holds presence of users.
+    Presence(Vec<PresenceInfo>, UserLite),
+    /// Used as an answer to [Request::GetDevices](crate::request::Request::GetDevices),
+    /// holds every device user is logged in from.
+    Devices(Vec<DeviceInfo>, UserLite),
 }
 
 impl IntoMessage<'_, MetaData, Content> for ServerReplyRaw {
@@ -51,11 +157,35 @@ impl IntoMessage<'_, MetaData, Content> for ServerReplyRaw {
             ServerReplyRaw::User(user, recipient) => {
                 (ServerReply::User(user), recipient)
             },
+            ServerReplyRaw::TransferOffset(id, offset, recipient) => {
+                (ServerReply::TransferOffset(id, offset), recipient)
+            },
+            ServerReplyRaw::Rejected(error, recipient) => {
+                (ServerReply::Rejected(error), recipient)
+            },
+            ServerReplyRaw::StorageUsage(usage, quota, recipient) => {
+                (ServerReply::StorageUsage(usage, quota), recipient)
+            },
+            ServerReplyRaw::Success(description, recipient) => {
+                (ServerReply::Success(description), recipient)
+            },
+            ServerReplyRaw::GroupMembers(name, members, recipient) => {
+                (ServerReply::GroupMembers(name, members), recipient)
+            },
+            ServerReplyRaw::Messages(count, recipient) => {
+                (ServerReply::Messages(count), recipient)
+            },
+            ServerReplyRaw::Presence(presence, recipient) => {
+                (ServerReply::Presence(presence), recipient)
+            },
+            ServerReplyRaw::Devices(devices, recipient) => {
+                (ServerReply::Devices(devices), recipient)
+            },
         };
 
         let mut message = ImplementedMessage::new();
 
-        let content = Content::with_data(server_reply.to_ron()?);
+        let content = Content::ron(server_reply.to_ron()?);
         let content_buff = content.into_bytes();
 
         // Recipient of Request will always be a server.
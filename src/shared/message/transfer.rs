@@ -0,0 +1,506 @@
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+
+use std::fs::{self, OpenOptions};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use nardol::bytes::Bytes;
+use nardol::error::{NetCommsError, NetCommsErrorKind};
+use nardol::packet::{Packet, PacketKind};
+use nardol::ron::{FromRon, ToRon};
+
+use crate::user::UserLite;
+
+
+/// Default number of bytes of a file that are held in memory at once when it is being sent, received or hashed.
+pub const FILE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Currently used chunk size, it is the cap on memory used by one file transfer.
+static CHUNK_SIZE: AtomicUsize = AtomicUsize::new(FILE_CHUNK_SIZE);
+
+/// Returns number of bytes of a file that are held in memory at once, by default it is [FILE_CHUNK_SIZE].
+pub fn chunk_size() -> usize {
+    CHUNK_SIZE.load(Ordering::Relaxed)
+}
+
+/// Sets number of bytes of a file that are held in memory at once for every following transfer.
+///
+/// Size is never set lower than 1 byte.
+pub fn set_chunk_size(size: usize) {
+    CHUNK_SIZE.store(size.max(1), Ordering::Relaxed);
+}
+
+/// Name of directory inside `save_location` where are kept files that are not yet completely received.
+pub const STAGING_DIR: &str = "staging";
+
+/// Staging files that are being received right now, only one transfer at a time can write to each of them.
+static RECEIVING: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Holds data needed to resume a transfer of a file if it was interrupted.
+///
+/// # Fields
+///
+/// * `id` -- id of the transfer, created from author and hash of the file, so the same file sent by the same
+/// author always gets the same id, even after reconnect.
+/// * `size` -- size of the whole file in bytes.
+/// * `offset` -- number of bytes the receiving side already has, sending starts from this byte.
+/// * `hash` -- SHA-256 hash of the whole file, used to verify it after the transfer is complete.
+/// * `source` -- [Option], if [Some] file is read from this path instead of the one in
+/// [MetaData](crate::message::MetaData) `file_name`, it is never sent to the other side.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FileTransfer {
+    id: String,
+    size: u64,
+    offset: u64,
+    hash: String,
+    #[serde(skip)]
+    source: Option<PathBuf>,
+}
+
+impl ToRon for FileTransfer {}
+impl FromRon<'_> for FileTransfer {}
+
+impl FileTransfer {
+
+    /// Creates a new [FileTransfer] for file at given `path` sent by `author`, `offset` is always set to 0.
+    ///
+    /// # Errors
+    ///
+    /// * Returns an error if file does not exist or can not be read.
+    pub fn new(path: &Path, author: &UserLite) -> Result<Self, NetCommsError> {
+
+        let size = match fs::metadata(path) {
+            Ok(metadata) => metadata.len(),
+            Err(e) => return Err(NetCommsError::new(
+                NetCommsErrorKind::OpeningFileFailed,
+                Some(format!("Failed to get metadata of {:?}. ({})", path, e)))),
+        };
+        let hash = file_hash(path)?;
+
+        Ok(FileTransfer {
+            id: format!("{}-{}", author.id(), hash),
+            size,
+            offset: 0,
+            hash,
+            source: None,
+        })
+    }
+
+    /// Returns `id`.
+    pub fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    /// Returns `size` in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns `offset` in bytes.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns `hash`.
+    pub fn hash(&self) -> String {
+        self.hash.clone()
+    }
+
+    /// Returns `source`.
+    pub fn source(&self) -> Option<PathBuf> {
+        self.source.clone()
+    }
+
+    /// Sets `offset`.
+    pub fn set_offset(&mut self, offset: u64) {
+        self.offset = offset;
+    }
+
+    /// Sets `source`.
+    pub fn set_source(&mut self, source: Option<PathBuf>) {
+        self.source = source;
+    }
+
+    /// Returns location of partially received file inside staging directory of given `location`.
+    pub fn staging_location(&self, location: &Path) -> PathBuf {
+        staging_location(location, &self.id)
+    }
+
+    /// Returns how many bytes of this transfer are already stored inside staging directory of given `location`.
+    pub fn staged_bytes(&self, location: &Path) -> u64 {
+        staged_bytes(location, &self.id)
+    }
+}
+
+/// Returns location of partially received file with transfer `id` inside staging directory of given `location`.
+pub fn staging_location(location: &Path, id: &str) -> PathBuf {
+
+    let mut path = PathBuf::from(location);
+    path.push(STAGING_DIR);
+    path.push(format!("{}.part", id));
+
+    path
+}
+
+/// Returns how many bytes of transfer with given `id` are already stored inside staging directory of given `location`.
+pub fn staged_bytes(location: &Path, id: &str) -> u64 {
+    match fs::metadata(staging_location(location, id)) {
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    }
+}
+
+/// Returns ids and number of already received bytes of all unfinished transfers inside staging directory of given `location`.
+pub fn unfinished_transfers(location: &Path) -> Vec<(String, u64)> {
+
+    let mut path = PathBuf::from(location);
+    path.push(STAGING_DIR);
+
+    let mut transfers = Vec::new();
+    if let Ok(entries) = fs::read_dir(path) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().map(|ext| ext == "part").unwrap_or(false) {
+                if let (Some(id), Ok(metadata)) = (path.file_stem(), entry.metadata()) {
+                    transfers.push((id.to_string_lossy().to_string(), metadata.len()));
+                }
+            }
+        }
+    }
+
+    transfers
+}
+
+/// Returns SHA-256 hash of file at given `path` as hexadecimal [String].
+///
+/// File is read in chunks of [chunk_size], so it is never held whole in memory.
+pub fn file_hash(path: &Path) -> Result<String, NetCommsError> {
+
+    let mut file = open_file(path)?;
+    let mut hasher = Sha256::new();
+    let mut buff = vec![0_u8; chunk_size()];
+
+    loop {
+        let n = match file.read(&mut buff) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) => return Err(NetCommsError::new(
+                NetCommsErrorKind::ReadingFromFileFailed,
+                Some(format!("Failed to read {:?}. ({})", path, e)))),
+        };
+        hasher.update(&buff[..n]);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Writes file at given `path` to `stream` starting at `offset`, followed by [End](PacketKind::End) packet.
+///
+/// File is read in chunks of [chunk_size] which are then split to packets, so it is never held whole in memory.
+pub fn send_file_from(stream: &mut TcpStream, path: &Path, offset: u64) -> Result<(), NetCommsError> {
+
+    let mut file = open_file(path)?;
+    if let Err(e) = file.seek(SeekFrom::Start(offset)) {
+        return Err(NetCommsError::new(
+            NetCommsErrorKind::ReadingFromFileFailed,
+            Some(format!("Failed to seek to byte {} of {:?}. ({})", offset, path, e))));
+    }
+
+    let mut buff = vec![0_u8; chunk_size()];
+    loop {
+        let n = match file.read(&mut buff) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) => return Err(NetCommsError::new(
+                NetCommsErrorKind::ReadingFromFileFailed,
+                Some(format!("Failed to read {:?}. ({})", path, e)))),
+        };
+
+        for bytes in Packet::split_to_max_packet_size(Bytes::from_vec(buff[..n].to_vec())) {
+            write_packet(stream, Packet::new(PacketKind::Content, bytes))?;
+        }
+    }
+
+    write_packet(stream, Packet::new(PacketKind::End, Bytes::new()))
+}
+
+/// Reads content packets from `stream` and writes them to file at `staging_path` starting at `offset`
+/// until [End](PacketKind::End) packet arrives, which is then returned.
+///
+/// `offset` is the byte sender starts from, anything already stored after it is cut off.
+/// `max_size` is the most bytes the file can have, usually its declared size.
+///
+/// Packets are written to the file as they arrive through a buffer of [chunk_size], so the file is never held whole
+/// in memory. If reading from `stream` fails, everything that arrived so far stays in the file,
+/// so the transfer can be resumed.
+///
+/// # Errors
+///
+/// * Returns an error if file at `staging_path` has less than `offset` bytes, as the bytes in between would be missing.
+/// * Returns an error if another transfer is already being received into file at `staging_path`.
+/// * Returns an error if file would grow past `max_size`, file is then removed.
+pub fn receive_file_into(stream: &mut TcpStream,
+                         staging_path: &Path,
+                         offset: u64,
+                         max_size: u64) -> Result<Packet, NetCommsError> {
+
+    if let Some(dir) = staging_path.parent() {
+        create_dir(dir)?;
+    }
+
+    // Claim is held until this function returns, so two senders of the same file can not mix their bytes.
+    let _claim = StagingClaim::new(staging_path)?;
+
+    let mut file = match OpenOptions::new().create(true).write(true).open(staging_path) {
+        Ok(file) => file,
+        Err(e) => return Err(NetCommsError::new(
+            NetCommsErrorKind::OpeningFileFailed,
+            Some(format!("Failed to open {:?}. ({})", staging_path, e)))),
+    };
+
+    let staged = match file.metadata() {
+        Ok(metadata) => metadata.len(),
+        Err(e) => return Err(NetCommsError::new(
+            NetCommsErrorKind::OpeningFileFailed,
+            Some(format!("Failed to get metadata of {:?}. ({})", staging_path, e)))),
+    };
+    if staged < offset {
+        return Err(NetCommsError::new(
+            NetCommsErrorKind::InvalidBufferSize,
+            Some(format!("Transfer starts at byte {}, but only {} bytes of {:?} are stored.",
+                         offset, staged, staging_path))));
+    }
+    if let Err(e) = file.set_len(offset).and_then(|_| file.seek(SeekFrom::Start(offset))) {
+        return Err(NetCommsError::new(
+            NetCommsErrorKind::WritingToFileFailed,
+            Some(format!("Failed to truncate {:?} to {} bytes. ({})", staging_path, offset, e))));
+    }
+    // Buffer is flushed also when it is dropped after an error, so nothing that arrived is lost.
+    let mut file = BufWriter::with_capacity(chunk_size(), file);
+    let mut size = offset;
+
+    loop {
+        let packet = Packet::receive(stream)?;
+        match packet.kind() {
+            PacketKind::Content => {
+                let content = packet.content_move().into_vec();
+                size += content.len() as u64;
+                if size > max_size {
+                    drop(file);
+                    let _ = fs::remove_file(staging_path);
+                    return Err(NetCommsError::new(
+                        NetCommsErrorKind::InvalidBufferSize,
+                        Some(format!("Received file {:?} is larger than {} bytes.", staging_path, max_size))));
+                }
+                if let Err(e) = file.write_all(&content) {
+                    return Err(NetCommsError::new(
+                        NetCommsErrorKind::WritingToFileFailed,
+                        Some(format!("Failed to write to {:?}. ({})", staging_path, e))));
+                }
+            },
+            PacketKind::End => {
+                if let Err(e) = file.flush() {
+                    return Err(NetCommsError::new(
+                        NetCommsErrorKind::WritingToFileFailed,
+                        Some(format!("Failed to write to {:?}. ({})", staging_path, e))));
+                }
+                return Ok(packet)
+            },
+            _ => {
+                return Err(NetCommsError::new(
+                    NetCommsErrorKind::InvalidPacketKind,
+                    Some(format!("Unexpected PacketKind, expected Content or End, arrived:\n {:?}", packet.kind()))));
+            },
+        }
+    }
+}
+
+/// Moves completely received file from staging directory to `destination` if its hash matches.
+///
+/// Returns `false` if file in staging directory is not yet complete, in which case it is left there.
+///
+/// # Errors
+///
+/// * Returns an error if hash of received file does not match, file is then removed from staging directory,
+/// so next transfer starts from the beginning.
+pub fn finish_transfer(transfer: &FileTransfer,
+                       staging_path: &Path,
+                       destination: &Path) -> Result<bool, NetCommsError> {
+    finish_file(staging_path, destination, transfer.size(), &transfer.hash())
+}
+
+/// Moves completely received file from `staging_path` to `destination` if it has given `size` and `hash`.
+///
+/// Returns `false` if file is smaller than `size`, in which case it is left where it is.
+///
+/// # Errors
+///
+/// * Returns an error if hash of received file does not match, file is then removed.
+pub fn finish_file(staging_path: &Path,
+                   destination: &Path,
+                   size: u64,
+                   hash: &str) -> Result<bool, NetCommsError> {
+
+    let staged = match fs::metadata(staging_path) {
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    };
+    if staged < size {
+        return Ok(false);
+    }
+
+    if file_hash(staging_path)? != hash {
+        let _ = fs::remove_file(staging_path);
+        return Err(NetCommsError::new(
+            NetCommsErrorKind::InvalidBufferSize,
+            Some(format!("Hash of received file {:?} does not match.", destination))));
+    }
+
+    if let Some(dir) = destination.parent() {
+        create_dir(dir)?;
+    }
+    if let Err(e) = fs::rename(staging_path, destination) {
+        return Err(NetCommsError::new(
+            NetCommsErrorKind::WritingToFileFailed,
+            Some(format!("Failed to move {:?} to {:?}. ({})", staging_path, destination, e))));
+    }
+
+    Ok(true)
+}
+
+/// Writes given `bytes` to `stream` as content packets followed by [End](PacketKind::End) packet.
+pub fn send_bytes(stream: &mut TcpStream, bytes: Bytes) -> Result<(), NetCommsError> {
+
+    for bytes in Packet::split_to_max_packet_size(bytes) {
+        write_packet(stream, Packet::new(PacketKind::Content, bytes))?;
+    }
+
+    write_packet(stream, Packet::new(PacketKind::End, Bytes::new()))
+}
+
+/// Reads content packets from `stream` until [End](PacketKind::End) packet arrives,
+/// returns content of those packets and the [End](PacketKind::End) packet.
+pub fn receive_bytes(stream: &mut TcpStream) -> Result<(Bytes, Packet), NetCommsError> {
+
+    let mut bytes = Bytes::new();
+
+    loop {
+        let mut packet = Packet::receive(stream)?;
+        match packet.kind() {
+            PacketKind::Content => bytes.append(packet.content_mut()),
+            PacketKind::End => return Ok((bytes, packet)),
+            _ => {
+                return Err(NetCommsError::new(
+                    NetCommsErrorKind::InvalidPacketKind,
+                    Some(format!("Unexpected PacketKind, expected Content or End, arrived:\n {:?}", packet.kind()))));
+            },
+        }
+    }
+}
+
+/// Internal function used to open a file and map an error.
+fn open_file(path: &Path) -> Result<fs::File, NetCommsError> {
+    match fs::File::open(path) {
+        Ok(file) => Ok(file),
+        Err(e) => Err(NetCommsError::new(
+            NetCommsErrorKind::OpeningFileFailed,
+            Some(format!("Failed to open {:?}. ({})", path, e)))),
+    }
+}
+
+/// Internal function used to create directory with all its parents.
+fn create_dir(dir: &Path) -> Result<(), NetCommsError> {
+    if let Err(e) = fs::create_dir_all(dir) {
+        return Err(NetCommsError::new(
+            NetCommsErrorKind::OpeningFileFailed,
+            Some(format!("Failed to create directory {:?}. ({})", dir, e))));
+    }
+    Ok(())
+}
+
+/// Internal guard of a staging file claimed by [receive_file_into], the claim is released when it is dropped.
+struct StagingClaim {
+    path: PathBuf,
+}
+
+impl StagingClaim {
+
+    fn new(path: &Path) -> Result<Self, NetCommsError> {
+
+        let mut receiving = RECEIVING.lock().unwrap();
+        if receiving.iter().any(|claimed| claimed == path) {
+            return Err(NetCommsError::new(
+                NetCommsErrorKind::OpeningFileFailed,
+                Some(format!("{:?} is already being received by another transfer.", path))));
+        }
+        receiving.push(path.to_path_buf());
+
+        Ok(StagingClaim { path: path.to_path_buf() })
+    }
+}
+
+impl Drop for StagingClaim {
+
+    fn drop(&mut self) {
+        RECEIVING.lock().unwrap().retain(|claimed| claimed != &self.path);
+    }
+}
+
+/// Internal function used to write a single packet to `stream`.
+fn write_packet(stream: &mut TcpStream, packet: Packet) -> Result<(), NetCommsError> {
+    if let Err(e) = stream.write_all(&packet.into_buff()) {
+        return Err(NetCommsError::new(
+            NetCommsErrorKind::WritingToStreamFailed,
+            Some(format!("Failed to write content packet to stream. ({})", e))));
+    }
+    Ok(())
+}
+
+# [test]
+fn transfer_larger_than_chunk_size() {
+
+    use std::net::TcpListener;
+    use std::thread;
+
+    // Memory cap is set much lower than size of the file, so the file can only arrive whole if it is streamed.
+    set_chunk_size(4 * 1024);
+    let file_size = 64 * chunk_size() + 123;
+
+    let mut dir = std::env::temp_dir();
+    dir.push(format!("net_comms_transfer_test_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut source = dir.clone();
+    source.push("source.bin");
+    // Data are not valid UTF-8, so they would not survive going through a String.
+    let data: Vec<u8> = (0..file_size).map(|i| (i % 251) as u8 ^ 0x80).collect();
+    fs::write(&source, &data).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let source_path = source.clone();
+    let sender = thread::spawn(move || {
+        let mut stream = TcpStream::connect(address).unwrap();
+        send_file_from(&mut stream, &source_path, 0).unwrap();
+    });
+
+    let (mut stream, _) = listener.accept().unwrap();
+    let mut staging_path = dir.clone();
+    staging_path.push("received.part");
+    receive_file_into(&mut stream, &staging_path, 0, file_size as u64).unwrap();
+    sender.join().unwrap();
+
+    let mut destination = dir.clone();
+    destination.push("received.bin");
+    let hash = file_hash(&source).unwrap();
+    assert!(finish_file(&staging_path, &destination, file_size as u64, &hash).unwrap());
+    assert_eq!(fs::read(&destination).unwrap(), data);
+
+    set_chunk_size(FILE_CHUNK_SIZE);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,71 @@
+use serde::{Serialize, Deserialize};
+
+use std::fmt::Display;
+
+use nardol::ron::{FromRon, ToRon};
+
+
+/// Holds information about one device a user is logged in from, as shown to that user.
+///
+/// # Fields
+///
+/// * `id` -- id of the device assigned by server when user logged in from it for the first time.
+/// * `registered_at` -- RFC 3339 datetime of when the device was registered.
+/// * `last_seen` -- [Option], if [Some] holds RFC 3339 datetime of when the device last contacted server.
+/// * `current` -- `true` if it is the device that asked for this information.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DeviceInfo {
+    id: usize,
+    registered_at: String,
+    last_seen: Option<String>,
+    current: bool,
+}
+
+impl FromRon<'_> for DeviceInfo {}
+impl ToRon for DeviceInfo {}
+
+impl Display for DeviceInfo {
+
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Device {}, registered {}", self.id, self.registered_at)?;
+        if let Some(last_seen) = &self.last_seen {
+            write!(f, ", last seen {}", last_seen)?;
+        }
+        if self.current {
+            write!(f, " (this device)")?;
+        }
+        Ok(())
+    }
+}
+
+impl DeviceInfo {
+
+    pub fn new(id: usize, registered_at: String, last_seen: Option<String>, current: bool) -> Self {
+        DeviceInfo {
+            id,
+            registered_at,
+            last_seen,
+            current,
+        }
+    }
+
+    /// Returns `id`.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Returns `registered_at`.
+    pub fn registered_at(&self) -> String {
+        self.registered_at.clone()
+    }
+
+    /// Returns `last_seen`.
+    pub fn last_seen(&self) -> Option<String> {
+        self.last_seen.clone()
+    }
+
+    /// Returns `current`.
+    pub fn current(&self) -> bool {
+        self.current
+    }
+}
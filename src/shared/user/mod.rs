@@ -1,3 +1,7 @@
+pub mod device;
+pub mod presence;
 pub mod user;
 
+pub use device::DeviceInfo;
+pub use presence::{Presence, PresenceInfo};
 pub use user::{Password, UserLite, UserUnchecked, User};
\ No newline at end of file
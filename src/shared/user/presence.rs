@@ -0,0 +1,120 @@
+use serde::{Serialize, Deserialize};
+
+use std::fmt::Display;
+
+use nardol::ron::{FromRon, ToRon};
+
+
+/// Whether a user is currently connected to server, derived from time since its last request or message.
+///
+/// # Variants
+///
+/// * `Online` -- user contacted server recently, typically it is polling for waiting messages.
+/// * `Away` -- user contacted server a while ago, but not recently enough to be online.
+/// * `Offline` -- user did not contact server for a long time or never did.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Presence {
+    Online,
+    Away,
+    Offline,
+}
+
+impl Default for Presence {
+
+    fn default() -> Self {
+        Presence::Offline
+    }
+}
+
+impl FromRon<'_> for Presence {}
+impl ToRon for Presence {}
+
+impl Display for Presence {
+
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Presence::Online => write!(f, "online"),
+            Presence::Away => write!(f, "away"),
+            Presence::Offline => write!(f, "offline"),
+        }
+    }
+}
+
+impl Presence {
+
+    /// Returns [Presence] of a user that was last seen `elapsed` seconds ago, [None] if it was never seen.
+    ///
+    /// User is online up to `online_timeout` seconds and away up to `away_timeout` seconds after it was last seen.
+    pub fn from_elapsed(elapsed: Option<u64>, online_timeout: u64, away_timeout: u64) -> Self {
+        match elapsed {
+            Some(elapsed) if elapsed <= online_timeout => Presence::Online,
+            Some(elapsed) if elapsed <= away_timeout => Presence::Away,
+            _ => Presence::Offline,
+        }
+    }
+}
+
+/// Holds presence of one user as seen by others.
+///
+/// # Fields
+///
+/// * `username` -- username of the user.
+/// * `presence` -- current [Presence] of the user.
+/// * `last_seen` -- [Option], if [Some] holds RFC 3339 datetime of when the user last contacted server.
+/// * `status` -- [Option], if [Some] holds status message the user set for itself.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PresenceInfo {
+    username: String,
+    presence: Presence,
+    last_seen: Option<String>,
+    status: Option<String>,
+}
+
+impl FromRon<'_> for PresenceInfo {}
+impl ToRon for PresenceInfo {}
+
+impl Display for PresenceInfo {
+
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is {}", self.username, self.presence)?;
+        if let (Presence::Away | Presence::Offline, Some(last_seen)) = (self.presence, &self.last_seen) {
+            write!(f, ", last seen {}", last_seen)?;
+        }
+        if let Some(status) = &self.status {
+            write!(f, " \"{}\"", status)?;
+        }
+        Ok(())
+    }
+}
+
+impl PresenceInfo {
+
+    pub fn new(username: String, presence: Presence, last_seen: Option<String>, status: Option<String>) -> Self {
+        PresenceInfo {
+            username,
+            presence,
+            last_seen,
+            status,
+        }
+    }
+
+    /// Returns `username`.
+    pub fn username(&self) -> String {
+        self.username.clone()
+    }
+
+    /// Returns `presence`.
+    pub fn presence(&self) -> Presence {
+        self.presence
+    }
+
+    /// Returns `last_seen`.
+    pub fn last_seen(&self) -> Option<String> {
+        self.last_seen.clone()
+    }
+
+    /// Returns `status`.
+    pub fn status(&self) -> Option<String> {
+        self.status.clone()
+    }
+}
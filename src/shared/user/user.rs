@@ -97,10 +97,19 @@ fn password() {
     assert!(password.verify("not".to_string()));
 }
 
+/// Holds id and username of a user, used as author of [requests](crate::request::Request).
+///
+/// # Fields
+///
+/// * `id`
+/// * `username`
+/// * `device_id` -- [Option], if [Some] holds id of the device user logged in from, it is assigned by server at login.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserLite {
     id: u32,
-    username: String
+    username: String,
+    #[serde(default)]
+    device_id: Option<usize>,
 }
 
 impl UserLite {
@@ -109,13 +118,15 @@ impl UserLite {
         UserLite {
             id,
             username,
+            device_id: None,
         }
     }
     
     pub fn from_user(user: &User) -> Self {
         UserLite { 
             id: user.id(),
-            username: user.username()
+            username: user.username(),
+            device_id: None,
         }
     }
 
@@ -123,6 +134,7 @@ impl UserLite {
         UserLite {
             id: UNKNOWN_USER_ID,
             username: UNKNOWN_USERNAME.to_string(),
+            device_id: None,
         }
     }
 
@@ -130,6 +142,7 @@ impl UserLite {
         UserLite {
             id: SERVER_ID,
             username: SERVER_USERNAME.to_string(),
+            device_id: None,
         }
     }
 
@@ -140,6 +153,16 @@ impl UserLite {
     pub fn username(&self) -> String {
         self.username.clone()
     }
+
+    /// Returns `device_id`.
+    pub fn device_id(&self) -> Option<usize> {
+        self.device_id
+    }
+
+    /// Sets `device_id`.
+    pub fn set_device_id(&mut self, device_id: Option<usize>) {
+        self.device_id = device_id;
+    }
 }
 
 /// Holds data about user that do not need to be valid so are used inside